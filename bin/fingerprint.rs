@@ -11,15 +11,21 @@ fn decode_tx(hex_str: &str) -> Result<Transaction, String> {
         .map_err(|e| format!("Invalid transaction: {e}"))
 }
 
-fn output_type_str(t: &OutputType) -> &'static str {
+fn output_type_str(t: &OutputType) -> String {
     match t {
-        OutputType::OpReturn => "op_return",
-        OutputType::NonStandard => "non_standard",
-        OutputType::P2pkh => "p2pkh",
-        OutputType::P2sh => "p2sh",
-        OutputType::P2wpkh => "p2wpkh",
-        OutputType::P2wsh => "p2wsh",
-        OutputType::P2tr => "p2tr",
+        OutputType::OpReturn => "op_return".into(),
+        OutputType::NonStandard => "non_standard".into(),
+        OutputType::P2pkh => "p2pkh".into(),
+        OutputType::P2sh => "p2sh".into(),
+        OutputType::P2wpkh => "p2wpkh".into(),
+        OutputType::P2wsh => "p2wsh".into(),
+        OutputType::P2tr => "p2tr".into(),
+        OutputType::P2pk => "p2pk".into(),
+        OutputType::P2a => "p2a".into(),
+        OutputType::P2shP2wpkh => "p2sh_p2wpkh".into(),
+        OutputType::P2shP2wsh => "p2sh_p2wsh".into(),
+        OutputType::Multisig { m, n } => format!("multisig_{m}_of_{n}"),
+        OutputType::WitnessUnknown { version } => format!("witness_v{version}"),
     }
 }
 
@@ -85,7 +91,7 @@ fn fingerprint_with_prevouts(tx: &Transaction, prev_outs: &[bitcoin::TxOut]) ->
             json!({
                 "signals_rbf": input::signals_rbf(txin),
                 "low_r_grinding": input::low_r_grinding(txin),
-                "input_type": output_type_str(&input_with_prevout::input_type(prevout)),
+                "input_type": output_type_str(&input_with_prevout::spent_input_type(txin, prevout)),
                 "has_uncompressed_pubkey": input_with_prevout::has_uncompressed_pubkey(txin, prevout),
                 "taproot_keyspend_non_default_sighash": input_with_prevout::taproot_keyspend_non_default_sighash(txin, prevout),
             })
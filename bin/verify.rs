@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+use tx_indexer_primitives::dense::DenseStorage;
+
+/// Re-read one in this many transactions from the blk files unless told otherwise.
+const DEFAULT_SAMPLE_EVERY: u64 = 1_000;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("Usage: {} <datadir> <index_dir> [sample_every]", args[0]);
+        eprintln!();
+        eprintln!("  datadir       Bitcoin Core data directory the index was built from");
+        eprintln!("  index_dir     Directory holding the dense index");
+        eprintln!(
+            "  sample_every  Re-hash one in this many transactions, 0 for none (default {DEFAULT_SAMPLE_EVERY})"
+        );
+        std::process::exit(1);
+    }
+
+    let datadir = PathBuf::from(&args[1]);
+    let index_dir = PathBuf::from(&args[2]);
+    let sample_every = match args.get(3) {
+        Some(arg) => arg.parse().unwrap_or_else(|_| {
+            eprintln!("Error: invalid sample_every: {arg}");
+            std::process::exit(1);
+        }),
+        None => DEFAULT_SAMPLE_EVERY,
+    };
+
+    let storage = DenseStorage::open_read_only(datadir, index_dir).unwrap_or_else(|e| {
+        eprintln!("Error: failed to open index: {e}");
+        std::process::exit(1);
+    });
+
+    let report = storage.verify(sample_every).unwrap_or_else(|e| {
+        eprintln!("Error: failed to read index: {e}");
+        std::process::exit(1);
+    });
+
+    println!(
+        "Checked {} blocks, {} transactions, {} inputs, {} outputs, {} spk db entries",
+        report.blocks, report.txs, report.inputs, report.outputs, report.spk_entries
+    );
+    println!("Re-hashed {} sampled transactions", report.sampled_txs);
+    if report.is_ok() {
+        println!("No issues found");
+        return;
+    }
+    for issue in &report.issues {
+        println!("{issue}");
+    }
+    if report.omitted_issues > 0 {
+        println!("... and {} more issues", report.omitted_issues);
+    }
+    std::process::exit(1);
+}
@@ -0,0 +1,237 @@
+//! Reading Bitcoin Core's UTXO set (`chainstate/`).
+//!
+//! Every unspent output is stored as its own LevelDB entry keyed by outpoint. Values use the
+//! same compressed coin encoding as undo data, XORed with the database's obfuscation key.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use rusty_leveldb::{DB, DBIterator, LdbIterator, Options};
+
+use crate::undo::{decompress_amount, read_compressed_script};
+use crate::varint::{read_varint, write_varint};
+use crate::{Error, deobfuscate, read_obfuscation_key};
+
+/// Key prefix for coin entries, followed by a 32-byte txid and the output index as a VarInt.
+const KEY_COIN: &[u8] = b"C";
+/// Key for the hash of the block the UTXO set corresponds to.
+const KEY_BEST_BLOCK: &[u8] = b"B";
+
+/// An unspent transaction output from the chainstate (`Coin` in Bitcoin Core).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    /// Txid of the creating transaction (internal byte order).
+    pub txid: [u8; 32],
+    pub vout: u32,
+    /// Height of the block containing the creating transaction.
+    pub height: u32,
+    /// Whether the creating transaction was a coinbase.
+    pub is_coinbase: bool,
+    /// Value in satoshis.
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+/// Read-only wrapper around Bitcoin Core's chainstate LevelDB.
+///
+/// The node must not be running: Bitcoin Core holds the database lock while it is up, and
+/// the UTXO set only matches [`best_block`](Self::best_block) once it has been flushed.
+pub struct Chainstate {
+    db: DB,
+    obfuscation_key: Vec<u8>,
+}
+
+impl Chainstate {
+    /// Open the chainstate LevelDB at the given path.
+    ///
+    /// `chainstate_path` is typically `~/.bitcoin/chainstate/`.
+    pub fn open(chainstate_path: &Path) -> Result<Self, Error> {
+        let opts = Options {
+            create_if_missing: false,
+            ..Default::default()
+        };
+        let mut db = DB::open(chainstate_path, opts)?;
+        let obfuscation_key = read_obfuscation_key(&mut db);
+        Ok(Self {
+            db,
+            obfuscation_key,
+        })
+    }
+
+    /// Hash of the block whose UTXO set is stored, i.e. the node's tip at the last flush.
+    pub fn best_block(&mut self) -> Result<[u8; 32], Error> {
+        let raw = self.db.get(KEY_BEST_BLOCK).ok_or(Error::KeyNotFound("B"))?;
+        let deobfuscated = deobfuscate(&self.obfuscation_key, &raw);
+        deobfuscated.try_into().map_err(|_| Error::UnexpectedEof)
+    }
+
+    /// Look up an output by outpoint. Returns `None` if it is spent or never existed.
+    pub fn coin(&mut self, txid: &[u8; 32], vout: u32) -> Result<Option<Coin>, Error> {
+        let key = coin_key(txid, vout);
+        let Some(raw) = self.db.get(&key) else {
+            return Ok(None);
+        };
+        let deobfuscated = deobfuscate(&self.obfuscation_key, &raw);
+        parse_coin(*txid, vout, &deobfuscated).map(Some)
+    }
+
+    /// Iterate over every unspent output, grouped by txid in internal byte order.
+    ///
+    /// The iterator reads from a LevelDB snapshot taken when it is created, so it sees a
+    /// consistent UTXO set even while other handles write to the database.
+    pub fn coins(&mut self) -> Result<CoinIter, Error> {
+        let mut iter = self.db.new_iter()?;
+        iter.seek(KEY_COIN);
+        Ok(CoinIter {
+            iter,
+            obfuscation_key: self.obfuscation_key.clone(),
+            started: false,
+        })
+    }
+}
+
+/// Iterator over the coins of a [`Chainstate`], created by [`Chainstate::coins`].
+pub struct CoinIter {
+    iter: DBIterator,
+    obfuscation_key: Vec<u8>,
+    started: bool,
+}
+
+impl Iterator for CoinIter {
+    type Item = Result<Coin, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // `seek` already positions the iterator on the first entry.
+        if self.started && !self.iter.advance() {
+            return None;
+        }
+        self.started = true;
+        let (key, value) = self.iter.current()?;
+        if !key.starts_with(KEY_COIN) {
+            return None;
+        }
+        let deobfuscated = deobfuscate(&self.obfuscation_key, &value);
+        Some(parse_coin_key(&key).and_then(|(txid, vout)| parse_coin(txid, vout, &deobfuscated)))
+    }
+}
+
+fn coin_key(txid: &[u8; 32], vout: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(KEY_COIN.len() + 32 + 5);
+    key.extend_from_slice(KEY_COIN);
+    key.extend_from_slice(txid);
+    write_varint(&mut key, vout as u64);
+    key
+}
+
+/// Split a [`KEY_COIN`] key into txid and vout.
+fn parse_coin_key(key: &[u8]) -> Result<([u8; 32], u32), Error> {
+    let rest = &key[KEY_COIN.len()..];
+    let txid: [u8; 32] = rest
+        .get(..32)
+        .ok_or(Error::UnexpectedEof)?
+        .try_into()
+        .expect("32 bytes");
+    let vout = read_varint(&mut Cursor::new(&rest[32..]))?;
+    let vout = u32::try_from(vout).map_err(|_| Error::InvalidCoin("vout out of range"))?;
+    Ok((txid, vout))
+}
+
+/// Parse a coin value (after deobfuscation).
+///
+/// Layout:
+///   code:   varint (height * 2 + is_coinbase)
+///   amount: varint (compressed, see [`decompress_amount`])
+///   script: compressed script
+fn parse_coin(txid: [u8; 32], vout: u32, data: &[u8]) -> Result<Coin, Error> {
+    let mut cursor = Cursor::new(data);
+    let code = read_varint(&mut cursor)?;
+    let value = decompress_amount(read_varint(&mut cursor)?);
+    let script_pubkey = read_compressed_script(&mut cursor)?;
+    Ok(Coin {
+        txid,
+        vout,
+        height: (code >> 1) as u32,
+        is_coinbase: code & 1 == 1,
+        value,
+        script_pubkey,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OBFUSCATE_KEY_KEY;
+    use crate::undo::compress_amount;
+
+    const XOR_KEY: [u8; 8] = [0x5a, 0xa5, 0x0f, 0xf0, 0x11, 0x22, 0x33, 0x44];
+
+    /// An in-memory chainstate holding `coins`, laid out the way Bitcoin Core writes it.
+    fn chainstate(coins: &[Coin]) -> Chainstate {
+        let mut db = DB::open("chainstate", rusty_leveldb::in_memory()).unwrap();
+        db.put(OBFUSCATE_KEY_KEY, &[&[8u8][..], &XOR_KEY].concat())
+            .unwrap();
+        db.put(KEY_BEST_BLOCK, &deobfuscate(&XOR_KEY, &[0xbb; 32]))
+            .unwrap();
+        // Entries sorting on either side of the coins must not be picked up.
+        db.put(b"A", b"").unwrap();
+        db.put(b"D", b"").unwrap();
+        for coin in coins {
+            let mut value = Vec::new();
+            write_varint(
+                &mut value,
+                ((coin.height as u64) << 1) | coin.is_coinbase as u64,
+            );
+            write_varint(&mut value, compress_amount(coin.value));
+            write_varint(&mut value, coin.script_pubkey.len() as u64 + 6);
+            value.extend_from_slice(&coin.script_pubkey);
+            db.put(
+                &coin_key(&coin.txid, coin.vout),
+                &deobfuscate(&XOR_KEY, &value),
+            )
+            .unwrap();
+        }
+        let obfuscation_key = read_obfuscation_key(&mut db);
+        Chainstate {
+            db,
+            obfuscation_key,
+        }
+    }
+
+    fn coin(txid: u8, vout: u32, value: u64) -> Coin {
+        Coin {
+            txid: [txid; 32],
+            vout,
+            height: 800_000 + vout,
+            is_coinbase: vout == 0,
+            value,
+            script_pubkey: vec![0x51, 0x20, txid],
+        }
+    }
+
+    #[test]
+    fn lookup_decodes_obfuscated_coin() {
+        let mut cs = chainstate(&[coin(1, 3, 12_345_678)]);
+        assert_eq!(cs.best_block().unwrap(), [0xbb; 32]);
+        assert_eq!(cs.coin(&[1; 32], 3).unwrap(), Some(coin(1, 3, 12_345_678)));
+        assert_eq!(cs.coin(&[1; 32], 4).unwrap(), None);
+    }
+
+    #[test]
+    fn coins_iterates_all_coins_in_key_order() {
+        // vout 200 needs a two-byte VarInt, which still sorts after vout 1.
+        let want = vec![
+            coin(1, 0, 50_0000_0000),
+            coin(1, 1, 546),
+            coin(1, 200, 0),
+            coin(2, 0, 1),
+        ];
+        let mut cs = chainstate(&[
+            want[3].clone(),
+            want[1].clone(),
+            want[2].clone(),
+            want[0].clone(),
+        ]);
+        let got: Vec<Coin> = cs.coins().unwrap().map(Result::unwrap).collect();
+        assert_eq!(got, want);
+    }
+}
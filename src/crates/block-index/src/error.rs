@@ -11,6 +11,9 @@ pub enum Error {
     UnexpectedEof,
     /// Block exists in the index but has no stored data (header-only or pruned).
     BlockNotStored,
+    /// A serialized coin (in undo data or the chainstate) that Bitcoin Core could not have
+    /// written.
+    InvalidCoin(&'static str),
     /// Requested depth exceeds the number of available blocks in the chain.
     DepthExceedsChain {
         /// The depth that was requested.
@@ -27,6 +30,7 @@ impl fmt::Display for Error {
             Error::KeyNotFound(key) => write!(f, "key '{key}' not found in block index"),
             Error::UnexpectedEof => write!(f, "unexpected end of data"),
             Error::BlockNotStored => write!(f, "block has no stored data (pruned or header-only)"),
+            Error::InvalidCoin(reason) => write!(f, "invalid coin data: {reason}"),
             Error::DepthExceedsChain {
                 requested,
                 available,
@@ -1,18 +1,23 @@
+pub mod chainstate;
 mod error;
+mod txindex;
+pub mod undo;
 mod varint;
 
+pub use chainstate::{Chainstate, Coin};
 pub use error::Error;
+pub use txindex::{TxIndex, TxLocation};
 
 use std::io::Cursor;
 use std::path::Path;
 
 use rusty_leveldb::{DB, LdbIterator, Options};
 
-use varint::read_varint;
+pub use varint::{read_varint, write_varint};
 
 /// The LevelDB key for the obfuscation XOR key.
-/// Bitcoin Core: `\x00obfuscate_key`.
-const OBFUSCATE_KEY_KEY: &[u8] = b"\x00obfuscate_key";
+/// Bitcoin Core: `\x00obfuscate_key`, serialized as a string with its CompactSize length prefix.
+const OBFUSCATE_KEY_KEY: &[u8] = b"\x0e\x00obfuscate_key";
 
 /// Key prefix for block index entries (CDiskBlockIndex), followed by a 32-byte block hash.
 const KEY_BLOCK_LOCATION: &[u8] = b"b";
@@ -20,6 +25,10 @@ const KEY_BLOCK_LOCATION: &[u8] = b"b";
 const KEY_BLOCK_FILE_INFO: &[u8] = b"f";
 /// Key for the last-block-file number (single byte, no suffix).
 const KEY_LAST_BLOCK_FILE: &[u8] = b"l";
+/// Key prefix for named boolean flags, followed by the flag name as a serialized string.
+const KEY_FLAG: &[u8] = b"F";
+/// Flag set once the node has deleted any blk/rev files (`-prune`).
+const FLAG_PRUNED_BLOCK_FILES: &[u8] = b"prunedblockfiles";
 
 /// Location of a block in the blk*.dat files, from a `'b'` + block_hash entry.
 #[derive(Debug, Clone)]
@@ -28,11 +37,35 @@ pub struct BlockLocation {
     pub data_pos: u32,
     pub n_tx: u32,
     pub height: u32,
-    /// The hash of the previous block (from the 80-byte block header).
+    /// Offset of the block's undo record in `revNNNNN.dat` (same file number as the block
+    /// data), or `None` if the node has no undo data for it.
+    pub undo_pos: Option<u32>,
+    /// Fields of the 80-byte block header, stored after the file positions.
+    pub version: i32,
+    /// The hash of the previous block.
     pub prev_hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    /// Block timestamp (Unix time) as set by the miner.
+    pub time: u32,
+    /// Compact encoding of the proof-of-work target.
+    pub bits: u32,
+    pub nonce: u32,
 }
 
 impl BlockLocation {
+    /// Reassemble the block header, e.g. to compute the block hash.
+    pub fn header(&self) -> bitcoin::block::Header {
+        use bitcoin::hashes::Hash;
+        bitcoin::block::Header {
+            version: bitcoin::block::Version::from_consensus(self.version),
+            prev_blockhash: bitcoin::BlockHash::from_byte_array(self.prev_hash),
+            merkle_root: bitcoin::TxMerkleNode::from_byte_array(self.merkle_root),
+            time: self.time,
+            bits: bitcoin::CompactTarget::from_consensus(self.bits),
+            nonce: self.nonce,
+        }
+    }
+
     /// Construct the blk file path: `blocks_dir/blkNNNNN.dat`.
     pub fn blk_path(&self, blocks_dir: &Path) -> std::path::PathBuf {
         BlockIndex::blk_path(blocks_dir, self.n_file)
@@ -69,14 +102,7 @@ impl BlockIndex {
             ..Default::default()
         };
         let mut db = DB::open(index_path, opts)?;
-
-        // Read the obfuscation key (may be absent on very old Bitcoin Core installs).
-        // Bitcoin Core serializes the key as a `std::vector<unsigned char>`: one CompactSize
-        // length-prefix byte followed by the actual XOR key bytes.  Skip the prefix.
-        let obfuscation_key = db
-            .get(OBFUSCATE_KEY_KEY)
-            .map(|b| b[1..].to_vec())
-            .unwrap_or_default();
+        let obfuscation_key = read_obfuscation_key(&mut db);
 
         Ok(Self {
             db,
@@ -168,6 +194,39 @@ impl BlockIndex {
         }
     }
 
+    /// Whether the node has ever pruned block files.
+    ///
+    /// Reads the `prunedblockfiles` flag ([`KEY_FLAG`] + flag name). Nodes that never pruned
+    /// don't write the flag at all.
+    pub fn is_pruned(&mut self) -> Result<bool, Error> {
+        let mut key = Vec::with_capacity(KEY_FLAG.len() + 1 + FLAG_PRUNED_BLOCK_FILES.len());
+        key.extend_from_slice(KEY_FLAG);
+        key.push(FLAG_PRUNED_BLOCK_FILES.len() as u8);
+        key.extend_from_slice(FLAG_PRUNED_BLOCK_FILES);
+
+        let Some(raw) = self.db.get(&key) else {
+            return Ok(false);
+        };
+        // Stored as a single character, '1' or '0'.
+        Ok(self.deobfuscate(&raw) == b"1")
+    }
+
+    /// Lowest height from which every block up to `tip_hash` still has its data on disk.
+    ///
+    /// This is 0 unless the node pruned. Walks back from the tip until it reaches a block
+    /// without data, like Bitcoin Core's `GetFirstBlock`.
+    pub fn first_stored_height(&mut self, tip_hash: &[u8; 32]) -> Result<u32, Error> {
+        let mut loc = self.block_location(tip_hash)?;
+        while loc.height > 0 {
+            match self.block_location(&loc.prev_hash) {
+                Ok(prev) => loc = prev,
+                Err(Error::BlockNotStored) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(loc.height)
+    }
+
     /// Walk backwards from `tip_hash` by following `prev_hash` links `depth` times.
     ///
     /// Returns a `Vec<BlockLocation>` in **forward order** (oldest first, tip last),
@@ -200,20 +259,87 @@ impl BlockIndex {
         Ok(chain)
     }
 
+    /// Iterate the best chain forwards, from `start_height` up to `tip_hash`.
+    ///
+    /// The index only links blocks to their parents, so this first walks back from the tip
+    /// to collect the block hashes, then reads each location again as the iterator advances.
+    /// Yields nothing if the tip is below `start_height`.
+    pub fn walk_forward(
+        &mut self,
+        tip_hash: &[u8; 32],
+        start_height: u32,
+    ) -> Result<WalkForward<'_>, Error> {
+        let mut hashes = Vec::new();
+        let mut current_hash = *tip_hash;
+        let mut loc = self.block_location(&current_hash)?;
+        if loc.height >= start_height {
+            hashes.reserve((loc.height - start_height) as usize + 1);
+            loop {
+                hashes.push(current_hash);
+                if loc.height == start_height {
+                    break;
+                }
+                current_hash = loc.prev_hash;
+                loc = self.block_location(&current_hash)?;
+            }
+        }
+        hashes.reverse();
+        Ok(WalkForward {
+            index: self,
+            hashes: hashes.into_iter(),
+        })
+    }
+
     /// Construct the blk file path: `blocks_dir/blkNNNNN.dat`.
     pub fn blk_path(blocks_dir: &Path, file_number: u32) -> std::path::PathBuf {
         blocks_dir.join(format!("blk{:05}.dat", file_number))
     }
 
     fn deobfuscate(&self, data: &[u8]) -> Vec<u8> {
-        if self.obfuscation_key.is_empty() {
-            return data.to_vec();
-        }
-        data.iter()
-            .enumerate()
-            .map(|(i, &b)| b ^ self.obfuscation_key[i % self.obfuscation_key.len()])
-            .collect()
+        deobfuscate(&self.obfuscation_key, data)
+    }
+}
+
+/// Iterator over the best chain in height order, created by [`BlockIndex::walk_forward`].
+pub struct WalkForward<'a> {
+    index: &'a mut BlockIndex,
+    hashes: std::vec::IntoIter<[u8; 32]>,
+}
+
+impl Iterator for WalkForward<'_> {
+    type Item = Result<BlockLocation, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hash = self.hashes.next()?;
+        Some(self.index.block_location(&hash))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hashes.size_hint()
+    }
+}
+
+impl ExactSizeIterator for WalkForward<'_> {}
+
+/// Read the obfuscation key of a Bitcoin Core LevelDB (may be absent on very old installs).
+///
+/// Bitcoin Core serializes the key as a `std::vector<unsigned char>`: one CompactSize
+/// length-prefix byte followed by the actual XOR key bytes.  Skip the prefix.
+fn read_obfuscation_key(db: &mut DB) -> Vec<u8> {
+    db.get(OBFUSCATE_KEY_KEY)
+        .map(|b| b[1..].to_vec())
+        .unwrap_or_default()
+}
+
+/// XOR `data` with the cycling obfuscation `key`.
+fn deobfuscate(key: &[u8], data: &[u8]) -> Vec<u8> {
+    if key.is_empty() {
+        return data.to_vec();
     }
+    data.iter()
+        .enumerate()
+        .map(|(i, &b)| b ^ key[i % key.len()])
+        .collect()
 }
 
 /// Status flag: block data is stored in a blk*.dat file.
@@ -231,7 +357,7 @@ const BLOCK_HAVE_UNDO: u32 = 16;
 ///   n_file:    varint  (only if BLOCK_HAVE_DATA | BLOCK_HAVE_UNDO)
 ///   data_pos:  varint  (only if BLOCK_HAVE_DATA)
 ///   undo_pos:  varint  (only if BLOCK_HAVE_UNDO)
-///   header:    80 bytes (not needed here)
+///   header:    80 bytes (version, prev hash, merkle root, time, bits, nonce)
 fn parse_block_location(data: &[u8]) -> Result<BlockLocation, Error> {
     let mut cursor = Cursor::new(data);
 
@@ -252,26 +378,29 @@ fn parse_block_location(data: &[u8]) -> Result<BlockLocation, Error> {
         return Err(Error::BlockNotStored);
     };
 
-    if n_status & BLOCK_HAVE_UNDO != 0 {
-        let _undo_pos = read_varint(&mut cursor)?;
-    }
+    let undo_pos = if n_status & BLOCK_HAVE_UNDO != 0 {
+        Some(read_varint(&mut cursor)? as u32)
+    } else {
+        None
+    };
 
     // The remaining bytes are the 80-byte block header.
-    // prev_block_hash is at bytes [4..36] of the header.
     let pos = cursor.position() as usize;
-    let remaining = &data[pos..];
-    if remaining.len() < 36 {
-        return Err(Error::UnexpectedEof);
-    }
-    let mut prev_hash = [0u8; 32];
-    prev_hash.copy_from_slice(&remaining[4..36]);
+    let header = data.get(pos..pos + 80).ok_or(Error::UnexpectedEof)?;
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("4 bytes"));
 
     Ok(BlockLocation {
         n_file,
         data_pos,
         n_tx,
         height,
-        prev_hash,
+        undo_pos,
+        version: u32_at(0) as i32,
+        prev_hash: header[4..36].try_into().expect("32 bytes"),
+        merkle_root: header[36..68].try_into().expect("32 bytes"),
+        time: u32_at(68),
+        bits: u32_at(72),
+        nonce: u32_at(76),
     })
 }
 
@@ -295,6 +424,7 @@ fn parse_block_file_info(data: &[u8]) -> Result<BlockFileInfo, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bitcoin::hashes::Hash;
 
     #[test]
     fn blk_path_formatting() {
@@ -371,9 +501,22 @@ mod tests {
         assert_eq!(loc.n_tx, 50);
         assert_eq!(loc.n_file, 3);
         assert_eq!(loc.data_pos, 99);
+        assert_eq!(loc.undo_pos, None);
         assert_eq!(loc.prev_hash, [0xABu8; 32]);
     }
 
+    #[test]
+    fn parse_block_location_with_undo() {
+        // status = HAVE_DATA(8) | HAVE_UNDO(16) | valid(4) | valid_headers(1) = 29,
+        // then n_file=3, data_pos=99, undo_pos=42.
+        let mut data = vec![0x00, 100, 29, 50, 3, 99, 42];
+        data.extend_from_slice(&[0u8; 80]);
+
+        let loc = parse_block_location(&data).unwrap();
+        assert_eq!(loc.data_pos, 99);
+        assert_eq!(loc.undo_pos, Some(42));
+    }
+
     #[test]
     fn parse_block_location_no_data_errors() {
         // status = 5 (valid but no BLOCK_HAVE_DATA)
@@ -389,6 +532,97 @@ mod tests {
         ));
     }
 
+    /// Serialize a CDiskBlockIndex for a block stored at `data_pos` in blk00000.dat.
+    fn disk_block_index(height: u32, data_pos: u32, header: &bitcoin::block::Header) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_varint(&mut data, 0);
+        write_varint(&mut data, height as u64);
+        write_varint(&mut data, (BLOCK_HAVE_DATA | 5) as u64);
+        write_varint(&mut data, 1);
+        write_varint(&mut data, 0);
+        write_varint(&mut data, data_pos as u64);
+        data.extend_from_slice(&bitcoin::consensus::serialize(header));
+        data
+    }
+
+    #[test]
+    fn parse_block_location_header_fields() {
+        let genesis = bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin).header;
+        let loc = parse_block_location(&disk_block_index(0, 8, &genesis)).unwrap();
+        assert_eq!(loc.version, 1);
+        assert_eq!(loc.time, 1_231_006_505);
+        assert_eq!(loc.bits, 0x1d00ffff);
+        assert_eq!(loc.nonce, 2_083_236_893);
+        assert_eq!(loc.header(), genesis);
+    }
+
+    #[test]
+    fn walk_forward_follows_chain_from_start_height() {
+        let mut idx = BlockIndex {
+            db: open_dummy_db(),
+            obfuscation_key: vec![],
+        };
+        let mut header = bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin).header;
+        let mut hashes = Vec::new();
+        for height in 0..5u32 {
+            let hash = header.block_hash().to_byte_array();
+            let key = [KEY_BLOCK_LOCATION, &hash[..]].concat();
+            idx.db
+                .put(&key, &disk_block_index(height, height * 1000, &header))
+                .unwrap();
+            hashes.push(hash);
+            header.prev_blockhash = header.block_hash();
+            header.time += 600;
+        }
+        let tip = *hashes.last().unwrap();
+
+        let walk = idx.walk_forward(&tip, 2).unwrap();
+        assert_eq!(walk.len(), 3);
+        let chain: Vec<BlockLocation> = walk.map(Result::unwrap).collect();
+        assert_eq!(
+            chain.iter().map(|loc| loc.height).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        for (loc, hash) in chain.iter().zip(&hashes[2..]) {
+            assert_eq!(&loc.header().block_hash().to_byte_array(), hash);
+        }
+
+        assert_eq!(idx.walk_forward(&tip, 0).unwrap().count(), 5);
+        assert_eq!(idx.walk_forward(&tip, 6).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn first_stored_height_stops_at_pruned_block() {
+        let mut idx = BlockIndex {
+            db: open_dummy_db(),
+            obfuscation_key: vec![],
+        };
+        assert!(!idx.is_pruned().unwrap());
+
+        let mut header = bitcoin::constants::genesis_block(bitcoin::Network::Bitcoin).header;
+        let mut tip = [0u8; 32];
+        for height in 0..5u32 {
+            tip = header.block_hash().to_byte_array();
+            let value = if height < 2 {
+                // Pruned: the entry keeps its header but loses its file position.
+                let mut data = vec![0, height as u8, 5, 1];
+                data.extend_from_slice(&bitcoin::consensus::serialize(&header));
+                data
+            } else {
+                disk_block_index(height, height * 1000, &header)
+            };
+            idx.db
+                .put(&[KEY_BLOCK_LOCATION, &tip[..]].concat(), &value)
+                .unwrap();
+            header.prev_blockhash = header.block_hash();
+        }
+        idx.db.put(b"F\x10prunedblockfiles", b"1").unwrap();
+
+        assert!(idx.is_pruned().unwrap());
+        assert_eq!(idx.first_stored_height(&tip).unwrap(), 2);
+        assert_eq!(idx.walk_forward(&tip, 2).unwrap().count(), 3);
+    }
+
     #[test]
     fn open_strips_obfuscation_key_length_prefix() {
         // Bitcoin Core stores the obfuscation key as: [0x08, k0..k7] (CompactSize prefix + 8 bytes).
@@ -417,6 +651,38 @@ mod tests {
         assert_eq!(idx.deobfuscate(&obfuscated), plaintext);
     }
 
+    #[test]
+    fn open_reads_obfuscation_key_written_by_bitcoin_core() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("obfuscated_block_index_{nanos}"));
+        let xor_key = [0x5au8, 0xa5, 0x0f, 0xf0, 0x11, 0x22, 0x33, 0x44];
+        {
+            let opts = Options {
+                create_if_missing: true,
+                ..Default::default()
+            };
+            let mut db = DB::open(&path, opts).unwrap();
+            // CDBWrapper writes the key string with its CompactSize length, 14.
+            db.put(b"\x0e\x00obfuscate_key", &[&[8u8][..], &xor_key].concat())
+                .unwrap();
+            db.put(
+                KEY_LAST_BLOCK_FILE,
+                &deobfuscate(&xor_key, &42u32.to_le_bytes()),
+            )
+            .unwrap();
+            db.flush().unwrap();
+        }
+
+        let mut idx = BlockIndex::open(&path).unwrap();
+        assert_eq!(idx.last_block_file().unwrap(), 42);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
     fn open_dummy_db() -> DB {
         let opts = rusty_leveldb::in_memory();
         DB::open("test", opts).unwrap()
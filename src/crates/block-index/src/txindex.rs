@@ -0,0 +1,160 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use rusty_leveldb::{DB, Options};
+
+use crate::varint::read_varint;
+use crate::{Error, deobfuscate, read_obfuscation_key};
+
+/// Key prefix for transaction entries (CDiskTxPos), followed by a 32-byte txid.
+const KEY_TX: &[u8] = b"t";
+/// Key for the locator of the block the index is synced to (CBlockLocator).
+const KEY_BEST_BLOCK: &[u8] = b"B";
+/// Length of the block header that precedes the transactions in a block's data.
+const BLOCK_HEADER_LEN: u32 = 80;
+
+/// Location of a transaction in the blk files, from a `'t'` + txid entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub n_file: u32,
+    /// Offset of the containing block's data in its blk file, as in
+    /// [`BlockLocation::data_pos`](crate::BlockLocation::data_pos).
+    pub block_pos: u32,
+    /// Offset of the transaction from the end of the block header. The first transaction
+    /// starts right after the transaction count.
+    pub tx_offset: u32,
+}
+
+impl TxLocation {
+    /// Offset of the serialized transaction in its blk file.
+    pub fn tx_pos(&self) -> u32 {
+        self.block_pos + BLOCK_HEADER_LEN + self.tx_offset
+    }
+}
+
+/// Read-only wrapper around the LevelDB Bitcoin Core maintains with `-txindex`.
+///
+/// Like [`BlockIndex`](crate::BlockIndex), lookups go straight to LevelDB.
+pub struct TxIndex {
+    db: DB,
+    obfuscation_key: Vec<u8>,
+}
+
+impl TxIndex {
+    /// Open the txindex LevelDB at the given path.
+    ///
+    /// `index_path` is typically `~/.bitcoin/indexes/txindex/`.
+    pub fn open(index_path: &Path) -> Result<Self, Error> {
+        let opts = Options {
+            create_if_missing: false,
+            ..Default::default()
+        };
+        let mut db = DB::open(index_path, opts)?;
+        let obfuscation_key = read_obfuscation_key(&mut db);
+        Ok(Self {
+            db,
+            obfuscation_key,
+        })
+    }
+
+    /// Look up where a transaction is stored by its txid (internal byte order).
+    ///
+    /// Returns `None` for txids the index does not know, including the genesis coinbase,
+    /// which Bitcoin Core never indexes.
+    pub fn tx_location(&mut self, txid: &[u8; 32]) -> Result<Option<TxLocation>, Error> {
+        let mut key = Vec::with_capacity(KEY_TX.len() + 32);
+        key.extend_from_slice(KEY_TX);
+        key.extend_from_slice(txid);
+
+        let Some(raw) = self.db.get(&key) else {
+            return Ok(None);
+        };
+        let deobfuscated = deobfuscate(&self.obfuscation_key, &raw);
+        parse_tx_location(&deobfuscated).map(Some)
+    }
+
+    /// Hash of the block the index has been synced up to.
+    ///
+    /// Reads the first hash of the [`KEY_BEST_BLOCK`] locator. The index lags the node's tip
+    /// while it catches up, so transactions in later blocks are not found yet.
+    pub fn best_block(&mut self) -> Result<[u8; 32], Error> {
+        let raw = self.db.get(KEY_BEST_BLOCK).ok_or(Error::KeyNotFound("B"))?;
+        let deobfuscated = deobfuscate(&self.obfuscation_key, &raw);
+        // Layout: 4-byte version, CompactSize hash count, then the hashes from tip backwards.
+        let hashes = deobfuscated.get(4..).ok_or(Error::UnexpectedEof)?;
+        let (&count, hashes) = hashes.split_first().ok_or(Error::UnexpectedEof)?;
+        if count == 0 {
+            return Err(Error::KeyNotFound("B"));
+        }
+        // Locators hold a few dozen hashes at most, so the count is always a single byte.
+        let tip = hashes.get(..32).ok_or(Error::UnexpectedEof)?;
+        Ok(tip.try_into().expect("32 bytes"))
+    }
+}
+
+/// Parse a CDiskTxPos value (after deobfuscation).
+///
+/// Layout: 3 consecutive Bitcoin Core VarInts: nFile, nPos (of the block), nTxOffset.
+fn parse_tx_location(data: &[u8]) -> Result<TxLocation, Error> {
+    let mut cursor = Cursor::new(data);
+    Ok(TxLocation {
+        n_file: read_varint(&mut cursor)? as u32,
+        block_pos: read_varint(&mut cursor)? as u32,
+        tx_offset: read_varint(&mut cursor)? as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::varint::write_varint;
+
+    fn in_memory_index(obfuscation_key: Vec<u8>) -> TxIndex {
+        let db = DB::open("txindex", rusty_leveldb::in_memory()).unwrap();
+        TxIndex {
+            db,
+            obfuscation_key,
+        }
+    }
+
+    fn put(index: &mut TxIndex, key: &[u8], value: &[u8]) {
+        let obfuscated = deobfuscate(&index.obfuscation_key, value);
+        index.db.put(key, &obfuscated).unwrap();
+    }
+
+    #[test]
+    fn tx_location_decodes_disk_tx_pos() {
+        let mut index = in_memory_index(vec![0x5a, 0xa5, 0x0f]);
+        let txid = [0x42; 32];
+        let mut value = Vec::new();
+        write_varint(&mut value, 12);
+        write_varint(&mut value, 134_217_000);
+        write_varint(&mut value, 301);
+        put(&mut index, &[KEY_TX, &txid[..]].concat(), &value);
+
+        let loc = index.tx_location(&txid).unwrap().unwrap();
+        assert_eq!(
+            loc,
+            TxLocation {
+                n_file: 12,
+                block_pos: 134_217_000,
+                tx_offset: 301,
+            }
+        );
+        assert_eq!(loc.tx_pos(), 134_217_000 + 80 + 301);
+        assert_eq!(index.tx_location(&[0x43; 32]).unwrap(), None);
+    }
+
+    #[test]
+    fn best_block_reads_locator_tip() {
+        let mut index = in_memory_index(vec![]);
+        assert!(matches!(index.best_block(), Err(Error::KeyNotFound("B"))));
+
+        let mut locator = 0i32.to_le_bytes().to_vec();
+        locator.push(2);
+        locator.extend_from_slice(&[0xaa; 32]);
+        locator.extend_from_slice(&[0xbb; 32]);
+        put(&mut index, KEY_BEST_BLOCK, &locator);
+        assert_eq!(index.best_block().unwrap(), [0xaa; 32]);
+    }
+}
@@ -0,0 +1,353 @@
+//! Decoding of Bitcoin Core's block undo data (`rev*.dat`).
+//!
+//! For every block it connects, Bitcoin Core records the coins spent by the block's inputs so
+//! the block can be disconnected again. Each record holds, per non-coinbase transaction and in
+//! input order, the value and script of the spent output along with the height and coinbase
+//! flag of the transaction that created it.
+
+use std::io::{Cursor, Read};
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::Error;
+use crate::varint::{read_varint, write_varint};
+
+/// Script templates with a special compressed encoding (`ScriptCompression` in Bitcoin Core).
+const SPECIAL_SCRIPTS: u64 = 6;
+/// Largest script Bitcoin Core stores verbatim; longer ones are replaced by `OP_RETURN`.
+const MAX_SCRIPT_SIZE: u64 = 10_000;
+
+const OP_DUP: u8 = 0x76;
+const OP_HASH160: u8 = 0xa9;
+const OP_EQUAL: u8 = 0x87;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_RETURN: u8 = 0x6a;
+
+/// Undo data of one block (`CBlockUndo`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockUndo {
+    /// One entry per transaction, skipping the coinbase.
+    pub txs: Vec<TxUndo>,
+}
+
+/// Spent outputs of one transaction (`CTxUndo`), in input order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxUndo {
+    pub spent: Vec<SpentOutput>,
+}
+
+/// An output spent by a transaction input, as restored from undo data (`Coin`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpentOutput {
+    /// Height of the block containing the transaction that created the output.
+    pub height: u32,
+    /// Whether the creating transaction was a coinbase.
+    pub is_coinbase: bool,
+    /// Value in satoshis.
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+impl BlockUndo {
+    /// Decode a serialized `CBlockUndo`, as stored in a rev file between the record header and
+    /// its checksum.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut cursor = Cursor::new(data);
+        let tx_count = read_compact_size(&mut cursor)?;
+        let mut txs = Vec::new();
+        for _ in 0..tx_count {
+            let spent_count = read_compact_size(&mut cursor)?;
+            let mut spent = Vec::new();
+            for _ in 0..spent_count {
+                spent.push(read_spent_output(&mut cursor)?);
+            }
+            txs.push(TxUndo { spent });
+        }
+        Ok(Self { txs })
+    }
+
+    /// Serialize in Bitcoin Core's undo format. Scripts are always written uncompressed,
+    /// which Bitcoin Core reads back the same way.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_compact_size(&mut out, self.txs.len() as u64);
+        for tx in &self.txs {
+            write_compact_size(&mut out, tx.spent.len() as u64);
+            for spent in &tx.spent {
+                let code = ((spent.height as u64) << 1) | spent.is_coinbase as u64;
+                write_varint(&mut out, code);
+                if spent.height > 0 {
+                    // Placeholder for the transaction version that older undo records kept.
+                    write_varint(&mut out, 0);
+                }
+                write_varint(&mut out, compress_amount(spent.value));
+                write_varint(&mut out, spent.script_pubkey.len() as u64 + SPECIAL_SCRIPTS);
+                out.extend_from_slice(&spent.script_pubkey);
+            }
+        }
+        out
+    }
+}
+
+fn read_spent_output(cursor: &mut Cursor<&[u8]>) -> Result<SpentOutput, Error> {
+    let code = read_varint(cursor)?;
+    let height = (code >> 1) as u32;
+    let is_coinbase = code & 1 == 1;
+    if height > 0 {
+        let _version = read_varint(cursor)?;
+    }
+    let value = decompress_amount(read_varint(cursor)?);
+    let script_pubkey = read_compressed_script(cursor)?;
+    Ok(SpentOutput {
+        height,
+        is_coinbase,
+        value,
+        script_pubkey,
+    })
+}
+
+pub(crate) fn read_compressed_script(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, Error> {
+    let size = read_varint(cursor)?;
+    match size {
+        0 => {
+            let hash: [u8; 20] = read_array(cursor)?;
+            let mut script = vec![OP_DUP, OP_HASH160, 20];
+            script.extend_from_slice(&hash);
+            script.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+            Ok(script)
+        }
+        1 => {
+            let hash: [u8; 20] = read_array(cursor)?;
+            let mut script = vec![OP_HASH160, 20];
+            script.extend_from_slice(&hash);
+            script.push(OP_EQUAL);
+            Ok(script)
+        }
+        2 | 3 => {
+            let x: [u8; 32] = read_array(cursor)?;
+            let mut script = vec![33, size as u8];
+            script.extend_from_slice(&x);
+            script.push(OP_CHECKSIG);
+            Ok(script)
+        }
+        4 | 5 => {
+            let x: [u8; 32] = read_array(cursor)?;
+            let mut compressed = [0u8; 33];
+            compressed[0] = size as u8 - 2;
+            compressed[1..].copy_from_slice(&x);
+            let pubkey = PublicKey::from_slice(&compressed)
+                .map_err(|_| Error::InvalidCoin("compressed pubkey not on the curve"))?;
+            let mut script = vec![65];
+            script.extend_from_slice(&pubkey.serialize_uncompressed());
+            script.push(OP_CHECKSIG);
+            Ok(script)
+        }
+        _ => {
+            let len = size - SPECIAL_SCRIPTS;
+            if len > MAX_SCRIPT_SIZE {
+                // Oversized scripts are unspendable; Bitcoin Core keeps only an OP_RETURN.
+                let skipped = std::io::copy(&mut cursor.by_ref().take(len), &mut std::io::sink())
+                    .map_err(|_| Error::UnexpectedEof)?;
+                if skipped != len {
+                    return Err(Error::UnexpectedEof);
+                }
+                return Ok(vec![OP_RETURN]);
+            }
+            let mut script = vec![0u8; len as usize];
+            cursor
+                .read_exact(&mut script)
+                .map_err(|_| Error::UnexpectedEof)?;
+            Ok(script)
+        }
+    }
+}
+
+fn read_array<const N: usize>(cursor: &mut Cursor<&[u8]>) -> Result<[u8; N], Error> {
+    let mut buf = [0u8; N];
+    cursor
+        .read_exact(&mut buf)
+        .map_err(|_| Error::UnexpectedEof)?;
+    Ok(buf)
+}
+
+/// Decode a CompactSize (the length prefix of serialized vectors).
+fn read_compact_size(cursor: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+    let [first] = read_array::<1>(cursor)?;
+    Ok(match first {
+        0xfd => u16::from_le_bytes(read_array(cursor)?) as u64,
+        0xfe => u32::from_le_bytes(read_array(cursor)?) as u64,
+        0xff => u64::from_le_bytes(read_array(cursor)?),
+        n => n as u64,
+    })
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+/// Compress a satoshi amount the way Bitcoin Core stores it in undo and chainstate data:
+/// trailing decimal zeros are folded into an exponent.
+pub fn compress_amount(mut n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    let mut e = 0;
+    while n.is_multiple_of(10) && e < 9 {
+        n /= 10;
+        e += 1;
+    }
+    if e < 9 {
+        let d = n % 10;
+        n /= 10;
+        1 + (n * 9 + d - 1) * 10 + e
+    } else {
+        1 + (n - 1) * 10 + 9
+    }
+}
+
+/// Inverse of [`compress_amount`].
+pub fn decompress_amount(mut x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+    x -= 1;
+    let mut e = x % 10;
+    x /= 10;
+    let mut n = if e < 9 {
+        let d = x % 9 + 1;
+        x /= 9;
+        x * 10 + d
+    } else {
+        x + 1
+    };
+    while e > 0 {
+        n *= 10;
+        e -= 1;
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COIN: u64 = 100_000_000;
+
+    #[test]
+    fn amount_compression_matches_bitcoin_core_vectors() {
+        // From Bitcoin Core's compress_tests.cpp.
+        for (amount, compressed) in [
+            (0, 0x0),
+            (1, 0x1),
+            (COIN / 100, 0x7),
+            (COIN, 0x9),
+            (50 * COIN, 0x32),
+            (21_000_000 * COIN, 0x1406f40),
+        ] {
+            assert_eq!(compress_amount(amount), compressed);
+            assert_eq!(decompress_amount(compressed), amount);
+        }
+        for amount in [12_345, 999_999_999, 2_099_999_997_690_000] {
+            assert_eq!(decompress_amount(compress_amount(amount)), amount);
+        }
+    }
+
+    #[test]
+    fn special_scripts_decompress_to_templates() {
+        let mut data = Vec::new();
+        write_varint(&mut data, 0);
+        data.extend_from_slice(&[0x11; 20]);
+        write_varint(&mut data, 1);
+        data.extend_from_slice(&[0x22; 20]);
+
+        let mut cursor = Cursor::new(data.as_slice());
+        let p2pkh = read_compressed_script(&mut cursor).unwrap();
+        assert_eq!(p2pkh.len(), 25);
+        assert_eq!(&p2pkh[..3], &[OP_DUP, OP_HASH160, 20]);
+        assert_eq!(&p2pkh[3..23], &[0x11; 20]);
+        let p2sh = read_compressed_script(&mut cursor).unwrap();
+        assert_eq!(p2sh.len(), 23);
+        assert_eq!(&p2sh[2..22], &[0x22; 20]);
+        assert_eq!(p2sh[22], OP_EQUAL);
+    }
+
+    #[test]
+    fn uncompressed_pubkey_script_is_restored() {
+        // Generator point G: its compressed form has an even y coordinate (prefix 0x02).
+        let g_x: [u8; 32] = [
+            0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87,
+            0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b,
+            0x16, 0xf8, 0x17, 0x98,
+        ];
+        let mut data = Vec::new();
+        write_varint(&mut data, 4);
+        data.extend_from_slice(&g_x);
+
+        let script = read_compressed_script(&mut Cursor::new(data.as_slice())).unwrap();
+        assert_eq!(script.len(), 67);
+        assert_eq!(script[0], 65);
+        assert_eq!(script[1], 0x04);
+        assert_eq!(&script[2..34], &g_x);
+        assert_eq!(script[66], OP_CHECKSIG);
+    }
+
+    #[test]
+    fn block_undo_roundtrip() {
+        let undo = BlockUndo {
+            txs: vec![
+                TxUndo {
+                    spent: vec![
+                        SpentOutput {
+                            height: 0,
+                            is_coinbase: true,
+                            value: 50 * COIN,
+                            script_pubkey: vec![0x51],
+                        },
+                        SpentOutput {
+                            height: 700_000,
+                            is_coinbase: false,
+                            value: 12_345,
+                            script_pubkey: vec![0x00, 0x14, 0xab, 0xcd],
+                        },
+                    ],
+                },
+                TxUndo { spent: vec![] },
+            ],
+        };
+        assert_eq!(BlockUndo::decode(&undo.encode()).unwrap(), undo);
+    }
+
+    #[test]
+    fn truncated_undo_errors() {
+        let undo = BlockUndo {
+            txs: vec![TxUndo {
+                spent: vec![SpentOutput {
+                    height: 1,
+                    is_coinbase: false,
+                    value: 1,
+                    script_pubkey: vec![0x51; 10],
+                }],
+            }],
+        };
+        let bytes = undo.encode();
+        assert!(matches!(
+            BlockUndo::decode(&bytes[..bytes.len() - 1]),
+            Err(Error::UnexpectedEof)
+        ));
+    }
+}
@@ -23,6 +23,21 @@ pub fn read_varint(reader: &mut impl Read) -> Result<u64, Error> {
     }
 }
 
+/// Encode a Bitcoin Core VarInt, the inverse of [`read_varint`].
+pub fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    let mut tmp = [0u8; 10];
+    let mut len = 0;
+    loop {
+        tmp[len] = (n & 0x7F) as u8 | if len > 0 { 0x80 } else { 0x00 };
+        if n <= 0x7F {
+            break;
+        }
+        n = (n >> 7) - 1;
+        len += 1;
+    }
+    out.extend(tmp[..=len].iter().rev());
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,6 +77,29 @@ mod tests {
         assert_eq!(read_varint(&mut cur).unwrap(), 16512);
     }
 
+    #[test]
+    fn write_then_read_roundtrip() {
+        for n in [
+            0,
+            1,
+            127,
+            128,
+            255,
+            16383,
+            16384,
+            16512,
+            u32::MAX as u64,
+            u64::MAX,
+        ] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, n);
+            assert_eq!(read_varint(&mut Cursor::new(&buf)).unwrap(), n);
+        }
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 16512);
+        assert_eq!(buf, [0x80, 0x80, 0x00]);
+    }
+
     #[test]
     fn unexpected_eof() {
         let mut cur = Cursor::new([0x80]); // continuation but no next byte
@@ -8,6 +8,11 @@ pub fn classify_script_pubkey(spk: &[u8]) -> OutputType {
     tx_indexer_primitives::classify_script_pubkey(spk)
 }
 
+/// Classify the output an input spends, resolving P2SH-wrapped segwit from the scriptSig.
+pub fn classify_spent_output(spk: &[u8], script_sig: &[u8]) -> OutputType {
+    tx_indexer_primitives::classify_spent_output(spk, script_sig)
+}
+
 /// Extract ECDSA signature byte vectors from scriptSig bytes.
 pub(crate) fn extract_signatures_from_script_sig(script_sig_bytes: &[u8]) -> Vec<Vec<u8>> {
     let script = Script::from_bytes(script_sig_bytes);
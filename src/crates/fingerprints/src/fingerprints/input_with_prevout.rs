@@ -1,7 +1,8 @@
 use bitcoin::Script;
 use bitcoin::script::Instruction;
 use tx_indexer_primitives::{
-    HasScriptPubkey, HasWitness, OutputType, traits::abstract_types::HasScriptSig,
+    HasScriptPubkey, HasWitness, InputSpend, OutputType, ScriptTemplate,
+    traits::abstract_types::HasScriptSig,
 };
 
 /// Classify the input type by looking at its prevout's scriptPubKey.
@@ -9,6 +10,15 @@ pub fn input_type(prevout: &impl HasScriptPubkey) -> OutputType {
     prevout.output_type()
 }
 
+/// Like [`input_type`], but also uses the input's scriptSig to tell P2SH-wrapped segwit apart
+/// from other P2SH spends.
+pub fn spent_input_type(
+    input: &(impl HasScriptSig + ?Sized),
+    prevout: &(impl HasScriptPubkey + ?Sized),
+) -> OutputType {
+    crate::classify_spent_output(&prevout.script_pubkey_bytes(), &input.script_sig_bytes())
+}
+
 /// Returns true if the input uses an uncompressed public key.
 ///
 /// Covers two cases:
@@ -71,6 +81,36 @@ pub fn taproot_keyspend_non_default_sighash(
     sig.len() == 65
 }
 
+/// Decode how the input unlocks its prevout: the redeem, witness or tapscript leaf script it
+/// reveals and that script's template.
+pub fn input_spend(
+    input: &(impl HasWitness + HasScriptSig + ?Sized),
+    prevout: &(impl HasScriptPubkey + ?Sized),
+) -> InputSpend {
+    tx_indexer_primitives::decode_input_spend(
+        &prevout.script_pubkey_bytes(),
+        &input.script_sig_bytes(),
+        &input.witness_items(),
+    )
+}
+
+/// Template of the script the input reveals, if any. Tells e.g. multisig wallets and Lightning
+/// channel closes apart from single-key spends of the same output type.
+pub fn spend_script_template(
+    input: &(impl HasWitness + HasScriptSig + ?Sized),
+    prevout: &(impl HasScriptPubkey + ?Sized),
+) -> Option<ScriptTemplate> {
+    input_spend(input, prevout).template()
+}
+
+/// `(m, n)` if the input spends an `m`-of-`n` multisig, bare, wrapped or as a tapscript leaf.
+pub fn multisig_policy(
+    input: &(impl HasWitness + HasScriptSig + ?Sized),
+    prevout: &(impl HasScriptPubkey + ?Sized),
+) -> Option<(u8, u8)> {
+    input_spend(input, prevout).multisig()
+}
+
 pub trait HasInputWithPrevoutFingerprints: HasWitness + HasScriptSig + HasScriptPubkey {
     fn has_uncompressed_pubkey(&self, prevout: &impl HasScriptPubkey) -> bool {
         has_uncompressed_pubkey(self, prevout)
@@ -78,4 +118,10 @@ pub trait HasInputWithPrevoutFingerprints: HasWitness + HasScriptSig + HasScript
     fn taproot_keyspend_non_default_sighash(&self, prevout: &impl HasScriptPubkey) -> bool {
         taproot_keyspend_non_default_sighash(self, prevout)
     }
+    fn spend_script_template(&self, prevout: &impl HasScriptPubkey) -> Option<ScriptTemplate> {
+        spend_script_template(self, prevout)
+    }
+    fn multisig_policy(&self, prevout: &impl HasScriptPubkey) -> Option<(u8, u8)> {
+        multisig_policy(self, prevout)
+    }
 }
@@ -2,7 +2,7 @@ pub mod classify;
 pub mod fingerprints;
 pub mod types;
 
-pub use classify::classify_script_pubkey;
+pub use classify::{classify_script_pubkey, classify_spent_output};
 pub use fingerprints::input::{self, HasInputFingerprints};
 pub use fingerprints::input_with_prevout;
 pub use fingerprints::output::{self, HasOutputFingerprints};
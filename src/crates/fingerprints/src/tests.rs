@@ -4,7 +4,9 @@ use std::str::FromStr;
 use tx_indexer_primitives::OutputType;
 
 use crate::fingerprints::input::{low_r_grinding, signals_rbf};
-use crate::fingerprints::input_with_prevout::{has_uncompressed_pubkey, input_type};
+use crate::fingerprints::input_with_prevout::{
+    has_uncompressed_pubkey, input_type, multisig_policy, spend_script_template,
+};
 use crate::fingerprints::output::output_type;
 use crate::fingerprints::transaction::{
     address_reuse, anti_fee_snipe, input_order, mixed_input_types, output_structure, tx_signals_rbf,
@@ -168,6 +170,43 @@ fn test_input_type_classification() {
     assert_eq!(input_type(&prevout), OutputType::P2pkh);
 }
 
+#[test]
+fn test_p2wsh_multisig_policy() {
+    // 2-of-2 witness script, as in a Lightning channel funding output.
+    let witness_script = bitcoin::script::Builder::new()
+        .push_int(2)
+        .push_slice([0x02; 33])
+        .push_slice([0x03; 33])
+        .push_int(2)
+        .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+        .into_script();
+    let prevout = make_txout(ScriptBuf::new_p2wsh(&witness_script.wscript_hash()));
+    let txin = TxIn {
+        witness: bitcoin::Witness::from_slice(&[
+            vec![],
+            vec![0x30; 71],
+            vec![0x30; 71],
+            witness_script.to_bytes(),
+        ]),
+        ..dummy_txin()
+    };
+    assert_eq!(multisig_policy(&txin, &prevout), Some((2, 2)));
+    assert_eq!(
+        spend_script_template(&txin, &prevout),
+        Some(tx_indexer_primitives::ScriptTemplate::Multisig { m: 2, n: 2 })
+    );
+
+    let p2pkh = make_txout(
+        bitcoin::Address::from_str("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")
+            .unwrap()
+            .require_network(bitcoin::Network::Bitcoin)
+            .unwrap()
+            .script_pubkey(),
+    );
+    assert_eq!(multisig_policy(&dummy_txin(), &p2pkh), None);
+    assert_eq!(spend_script_template(&dummy_txin(), &p2pkh), None);
+}
+
 // --- output tests ---
 
 #[test]
@@ -1,7 +1,10 @@
 use tx_indexer_fingerprints::{
     InputSortingType, classify_script_pubkey,
     input::HasInputFingerprints,
-    input_with_prevout::{has_uncompressed_pubkey, taproot_keyspend_non_default_sighash},
+    input_with_prevout::{
+        has_uncompressed_pubkey, spend_script_template, spent_input_type,
+        taproot_keyspend_non_default_sighash,
+    },
     transaction::{
         address_reuse, anti_fee_snipe, bip68_with_absolute_locktime, input_order, is_bip69_sorted,
         mixed_input_types, nlocktime_optin_without_use, output_structure,
@@ -13,7 +16,10 @@ use tx_indexer_pipeline::{
     node::{Node, NodeId},
     value::{NormalizedFingerprints, TxSet},
 };
-use tx_indexer_primitives::{HasScriptPubkey, handle::TxOutHandle, traits::HasNLockTime};
+use tx_indexer_primitives::{
+    HasScriptPubkey, ScriptTemplate,
+    traits::{AbstractTransaction, HasNLockTime},
+};
 
 fn sorted_deduped(vals: impl Iterator<Item = u32>) -> Vec<u32> {
     let mut v: Vec<u32> = vals.collect();
@@ -80,21 +86,24 @@ impl Node for CollectFingerprintsNode {
             // Is outputs bip69 sorted
             f.push(is_bip69_sorted(&outputs) as u32);
 
-            // Collect prevout TxOuts for inputs (requires loading spent txs)
-            let prevouts: Vec<TxOutHandle<'_>> = inputs
-                .iter()
-                .map(|input| {
-                    input
-                        .prev_txout()
-                        .expect("Prevout should always be present for non pruned setup")
+            // Resolve the outputs spent by the inputs. Inputs whose spent output is unknown,
+            // such as inputs spending coins from before the first block of a pruned index, are
+            // left out of the prevout-based fingerprints.
+            let (spending_inputs, prevouts): (Vec<_>, Vec<_>) = tx
+                .inputs()
+                .filter_map(|input| {
+                    let prevout = input.spent_txout()?;
+                    Some((input, prevout))
                 })
-                .collect();
+                .unzip();
 
-            // input_type - sorted deduped output types of prevout scripts
+            // input_type - sorted deduped output types of prevout scripts, with wrapped
+            // segwit resolved from the scriptSig
             let input_types = sorted_deduped(
-                prevouts
+                inputs
                     .iter()
-                    .map(|o| classify_script_pubkey(&o.script_pubkey_bytes()).as_u32()),
+                    .zip(prevouts.iter())
+                    .map(|(inp, prevout)| spent_input_type(inp, prevout).as_u32()),
             );
             f.extend(input_types);
 
@@ -106,7 +115,7 @@ impl Node for CollectFingerprintsNode {
 
             // input_order - sorted deduped discriminants
             let order_types = sorted_deduped(
-                input_order(&inputs, &prevouts)
+                input_order(&spending_inputs, &prevouts)
                     .into_iter()
                     .map(InputSortingType::as_u32),
             );
@@ -114,7 +123,7 @@ impl Node for CollectFingerprintsNode {
 
             // has_uncompressed_pubkey - any input with uncompressed pubkey
             f.push(
-                inputs
+                spending_inputs
                     .iter()
                     .zip(prevouts.iter())
                     .any(|(inp, prevout)| has_uncompressed_pubkey(inp, prevout))
@@ -123,13 +132,27 @@ impl Node for CollectFingerprintsNode {
 
             // taproot_keyspend_non_default_sighash - any input with explicit sighash in taproot keyspend
             f.push(
-                inputs
+                spending_inputs
                     .iter()
                     .zip(prevouts.iter())
                     .any(|(inp, prevout)| taproot_keyspend_non_default_sighash(inp, prevout))
                     as u32,
             );
 
+            // unresolved_prevouts - whether any input other than a coinbase's was left out above
+            let spends = if tx.is_coinbase() { 0 } else { inputs.len() };
+            f.push((spending_inputs.len() < spends) as u32);
+
+            // spend_script_templates - sorted deduped templates of the scripts the inputs run
+            let templates = sorted_deduped(
+                spending_inputs
+                    .iter()
+                    .zip(prevouts.iter())
+                    .filter_map(|(inp, prevout)| spend_script_template(inp, prevout))
+                    .map(ScriptTemplate::as_u32),
+            );
+            f.extend(templates);
+
             fingerprints.push(f);
         });
         fingerprints
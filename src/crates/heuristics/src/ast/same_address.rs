@@ -84,14 +84,16 @@ mod tests {
             10_000,
             0,
             unique_spk1,
-        )]);
+        )])
+        .with_txid(TxId(1));
 
         // Coinbase 2
         let coinbase2 = DummyTxData::new_with_outputs(vec![DummyTxOutData::new_with_script(
             10_000,
             0,
             unique_spk2,
-        )]);
+        )])
+        .with_txid(TxId(2));
 
         // Spend coinbase 1, make payment + change (change spk is shared)
         let spend1 = DummyTxData::new(
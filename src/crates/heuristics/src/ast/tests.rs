@@ -30,11 +30,11 @@ pub(crate) mod ast_tests {
         }
 
         pub fn coinbase1() -> DummyTxData {
-            DummyTxData::new_with_amounts(vec![100, 150])
+            DummyTxData::new_with_amounts(vec![100, 150]).with_txid(TxId(1))
         }
 
         pub fn coinbase2() -> DummyTxData {
-            DummyTxData::new_with_amounts(vec![150])
+            DummyTxData::new_with_amounts(vec![150]).with_txid(TxId(2))
         }
 
         pub fn payment_output() -> TxOutId {
@@ -95,6 +95,120 @@ pub(crate) mod ast_tests {
         assert_eq!(result.get(&AnyTxId::from(TxId(2))), Some(&true)); // Is a coinjoin
     }
 
+    #[test]
+    fn test_filter_feerate() {
+        // Both spends have the same shape, so the larger fee means the higher feerate.
+        let all_txs: Vec<Arc<dyn AbstractTransaction + Send + Sync>> = vec![
+            Arc::new(TestFixture::coinbase1()),
+            Arc::new(DummyTxData::new_with_spent(
+                vec![40],
+                vec![TxOutId::new(TxId(1), 0)],
+            )),
+            Arc::new(DummyTxData::new_with_spent(
+                vec![149],
+                vec![TxOutId::new(TxId(1), 1)],
+            )),
+        ];
+
+        let mut builder = LooseIndexBuilder::new();
+        for tx in all_txs {
+            builder.add_tx(tx);
+        }
+        let storage = Arc::new(UnifiedStorage::from(builder));
+        let high_fee = AnyTxId::from(TxId(2));
+        let low_fee = AnyTxId::from(TxId(3));
+        assert_eq!(
+            high_fee.with(&*storage).fee(),
+            Some(bitcoin::Amount::from_sat(60))
+        );
+        assert_eq!(
+            low_fee.with(&*storage).fee(),
+            Some(bitcoin::Amount::from_sat(1))
+        );
+        let threshold = high_fee.with(&*storage).feerate().unwrap();
+
+        let ctx = Arc::new(PipelineContext::new());
+        let mut engine = Engine::new(ctx.clone(), storage);
+        let source = AllLooseTxs::new(&ctx);
+        let at_least = source.txs().filter_feerate(threshold..);
+        let below = source.txs().filter_feerate(..threshold);
+
+        assert_eq!(engine.eval(&at_least).as_ref(), &[high_fee]);
+        assert_eq!(engine.eval(&below).as_ref(), &[low_fee]);
+    }
+
+    /// The shared spend chain, see [`tx_indexer_primitives::test_utils::spend_chain_blocks`].
+    fn spend_chain_storage(prefix: &str) -> UnifiedStorage {
+        use tx_indexer_primitives::{
+            dense::DenseStorageBuilder,
+            test_utils::{block_file_datadir, spend_chain_blocks},
+        };
+
+        let datadir = block_file_datadir(prefix, &spend_chain_blocks()).unwrap();
+        let index_dir = datadir.join("index");
+        DenseStorageBuilder::new(datadir, index_dir, 0..3, vec![])
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn test_utxo_set_at() {
+        use tx_indexer_pipeline::ops::source::UtxoSetAt;
+
+        let storage = spend_chain_storage("utxo_set_at");
+        let ctx = Arc::new(PipelineContext::new());
+        let mut engine = Engine::new(ctx.clone(), Arc::new(storage));
+        let genesis = UtxoSetAt::new(&ctx, 0).outputs();
+        let after_first_spend = UtxoSetAt::new(&ctx, 1).outputs();
+        let beyond_tip = UtxoSetAt::new(&ctx, 3).outputs();
+
+        let out =
+            |txid: u32| AnyOutId::from(tx_indexer_primitives::dense::TxOutId::new(txid as u64));
+        let mut at_one: Vec<_> = engine.eval(&after_first_spend).iter().copied().collect();
+        at_one.sort_by_key(|id| id.raw());
+        assert_eq!(at_one, vec![out(1), out(2)]);
+        let at_genesis: Vec<_> = engine.eval(&genesis).iter().copied().collect();
+        assert_eq!(at_genesis, vec![out(0)]);
+        // Block 3 is not indexed yet and could still spend any of these outputs.
+        assert!(engine.eval(&beyond_tip).is_empty());
+    }
+
+    #[test]
+    fn test_coin_age_metrics() {
+        use tx_indexer_pipeline::ops::source::AllDenseTxs;
+        use tx_indexer_primitives::{dense, handle::CoinAge, unified::AnyInId};
+
+        let ctx = Arc::new(PipelineContext::new());
+        let mut engine = Engine::new(ctx.clone(), Arc::new(spend_chain_storage("coin_age")));
+        let txs = AllDenseTxs::new(&ctx).txs();
+        let ages = txs.coin_ages();
+        let cdd = txs.coin_days_destroyed();
+
+        // Only the spends in blocks 1 and 2 have inputs with a known age.
+        let ages = engine.eval(&ages).into_owned();
+        assert_eq!(ages.len(), 2);
+        let one_block = CoinAge {
+            blocks: 1,
+            seconds: Some(600),
+        };
+        assert_eq!(
+            ages.get(&AnyInId::from(dense::TxInId::new(2))),
+            Some(&one_block)
+        );
+        assert_eq!(
+            ages.get(&AnyInId::from(dense::TxInId::new(4))),
+            Some(&one_block)
+        );
+        let cdd = engine.eval(&cdd).into_owned();
+        assert_eq!(cdd.len(), 5);
+        assert_eq!(cdd[&AnyTxId::from(dense::TxId::new(0))], 0.0);
+        let spend_cdd = cdd[&AnyTxId::from(dense::TxId::new(2))];
+        assert!((spend_cdd - 5_000e-8 * 600.0 / 86_400.0).abs() < 1e-12);
+        let second_cdd = cdd[&AnyTxId::from(dense::TxId::new(4))];
+        assert!((second_cdd - 4_000e-8 * 600.0 / 86_400.0).abs() < 1e-12);
+    }
+
     #[test]
     fn test_multi_input_heuristic() {
         let all_txs = setup_test_fixture();
@@ -186,6 +300,87 @@ pub(crate) mod ast_tests {
         );
     }
 
+    #[test]
+    fn test_fingerprints_flag_unresolved_prevouts() {
+        use tx_indexer_pipeline::ops::source::AllDenseTxs;
+        use tx_indexer_primitives::{
+            dense::DenseStorageBuilder,
+            test_utils::{temp_dir, write_single_block_file},
+        };
+
+        use crate::ast::fingerprint::CollectFingerprints;
+
+        // Indexed from block 702861 alone, most inputs spend a coin the index never saw.
+        let datadir = temp_dir("fingerprints_unresolved");
+        let blocks_dir = datadir.join("blocks");
+        std::fs::create_dir_all(&blocks_dir).unwrap();
+        write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861()).unwrap();
+        let index_dir = datadir.join("index");
+        std::fs::create_dir_all(&index_dir).unwrap();
+        let storage = DenseStorageBuilder::new(datadir, index_dir, 0..1, vec![])
+            .build()
+            .unwrap();
+        let ctx = Arc::new(PipelineContext::new());
+        let mut engine = Engine::new(ctx.clone(), Arc::new(storage.into()));
+        let fingerprints = CollectFingerprints::new(AllDenseTxs::new(&ctx).txs());
+        let fingerprints = engine.eval(&fingerprints).into_owned();
+
+        // The unresolved flag is followed by the templates of the scripts run by resolved
+        // inputs. The coinbase has no prevouts to resolve and only spends of outputs from the
+        // block itself resolve. The tx set is unordered, so compare the tails as multisets.
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861()).unwrap();
+        let in_block: std::collections::HashMap<_, _> = block
+            .txdata
+            .iter()
+            .map(|tx| (tx.compute_txid(), tx))
+            .collect();
+        let mut expected: Vec<Vec<u32>> = block
+            .txdata
+            .iter()
+            .map(|tx| {
+                if tx.is_coinbase() {
+                    return vec![0];
+                }
+                let mut unresolved = false;
+                let mut templates = Vec::new();
+                for txin in &tx.input {
+                    let Some(prev) = in_block.get(&txin.previous_output.txid) else {
+                        unresolved = true;
+                        continue;
+                    };
+                    let spk = &prev.output[txin.previous_output.vout as usize].script_pubkey;
+                    let spend = tx_indexer_primitives::decode_input_spend(
+                        spk.as_bytes(),
+                        txin.script_sig.as_bytes(),
+                        &txin.witness.to_vec(),
+                    );
+                    templates.extend(spend.template().map(|t| t.as_u32()));
+                }
+                templates.sort_unstable();
+                templates.dedup();
+                let mut tail = vec![unresolved as u32];
+                tail.extend(templates);
+                tail
+            })
+            .collect();
+        assert_eq!(fingerprints.len(), expected.len());
+        // Every tx of the block runs at most one template and none that reads as a flag.
+        assert!(expected.iter().any(|tail| tail[0] == 1));
+        assert!(expected.iter().any(|tail| tail.len() == 2));
+        assert!(expected.iter().all(|tail| tail.len() == 1 || tail[1] > 1));
+        let mut actual: Vec<Vec<u32>> = fingerprints
+            .iter()
+            .map(|f| match f[f.len() - 1] {
+                0 | 1 => f[f.len() - 1..].to_vec(),
+                _ => f[f.len() - 2..].to_vec(),
+            })
+            .collect();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn test_is_unilateral() {
         let all_txs = setup_test_fixture();
@@ -240,11 +435,12 @@ pub(crate) mod ast_tests {
 
         let txs = vec![
             // Coinbase 0
-            DummyTxData::new_with_amounts(vec![1000]),
+            DummyTxData::new_with_amounts(vec![1000]).with_txid(TxId(1)),
             // tx1: spends coinbase 0, produces payment + change
-            DummyTxData::new_with_spent(vec![700, 300], vec![TxOutId::new(TxId(1), 0)]),
+            DummyTxData::new_with_spent(vec![700, 300], vec![TxOutId::new(TxId(1), 0)])
+                .with_txid(TxId(2)),
             // coinbase 2
-            DummyTxData::new_with_amounts(vec![500]),
+            DummyTxData::new_with_amounts(vec![500]).with_txid(TxId(3)),
             // tx3: spends tx1 change + coinbase2
             DummyTxData::new_with_spent(
                 vec![400, 100],
@@ -284,7 +480,7 @@ pub(crate) mod ast_tests {
         let ctx = Arc::new(PipelineContext::new());
 
         let txs = vec![
-            DummyTxData::new_with_amounts(vec![1000]),
+            DummyTxData::new_with_amounts(vec![1000]).with_txid(TxId(1)),
             DummyTxData::new(
                 vec![
                     DummyTxOutData::new(700, 0), // payment
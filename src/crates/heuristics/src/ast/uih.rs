@@ -167,8 +167,8 @@ mod tests {
 
     fn setup_uih1_qualifying_fixture() -> Vec<Arc<dyn AbstractTransaction + Send + Sync>> {
         vec![
-            Arc::new(DummyTxData::new_with_amounts(vec![100])),
-            Arc::new(DummyTxData::new_with_amounts(vec![200])),
+            Arc::new(DummyTxData::new_with_amounts(vec![100]).with_txid(TxId(1))),
+            Arc::new(DummyTxData::new_with_amounts(vec![200]).with_txid(TxId(2))),
             Arc::new(DummyTxData::new_with_spent(
                 vec![50, 250],
                 vec![TxOutId::new(TxId(1), 0), TxOutId::new(TxId(2), 0)],
@@ -178,8 +178,8 @@ mod tests {
 
     fn setup_uih1_no_candidate_fixture() -> Vec<Arc<dyn AbstractTransaction + Send + Sync>> {
         vec![
-            Arc::new(DummyTxData::new_with_amounts(vec![50])),
-            Arc::new(DummyTxData::new_with_amounts(vec![100])),
+            Arc::new(DummyTxData::new_with_amounts(vec![50]).with_txid(TxId(1))),
+            Arc::new(DummyTxData::new_with_amounts(vec![100]).with_txid(TxId(2))),
             Arc::new(DummyTxData::new_with_spent(
                 vec![80, 70],
                 vec![TxOutId::new(TxId(1), 0), TxOutId::new(TxId(2), 0)],
@@ -189,8 +189,8 @@ mod tests {
 
     fn setup_uih1_tie_fixture() -> Vec<Arc<dyn AbstractTransaction + Send + Sync>> {
         vec![
-            Arc::new(DummyTxData::new_with_amounts(vec![100])),
-            Arc::new(DummyTxData::new_with_amounts(vec![200])),
+            Arc::new(DummyTxData::new_with_amounts(vec![100]).with_txid(TxId(1))),
+            Arc::new(DummyTxData::new_with_amounts(vec![200]).with_txid(TxId(2))),
             Arc::new(DummyTxData::new_with_spent(
                 vec![50, 50],
                 vec![TxOutId::new(TxId(1), 0), TxOutId::new(TxId(2), 0)],
@@ -200,8 +200,8 @@ mod tests {
 
     fn setup_uih2_no_unnecessary_fixture() -> Vec<Arc<dyn AbstractTransaction + Send + Sync>> {
         vec![
-            Arc::new(DummyTxData::new_with_amounts(vec![100])),
-            Arc::new(DummyTxData::new_with_amounts(vec![200])),
+            Arc::new(DummyTxData::new_with_amounts(vec![100]).with_txid(TxId(1))),
+            Arc::new(DummyTxData::new_with_amounts(vec![200]).with_txid(TxId(2))),
             Arc::new(DummyTxData::new_with_spent(
                 vec![250, 40],
                 vec![TxOutId::new(TxId(1), 0), TxOutId::new(TxId(2), 0)],
@@ -211,8 +211,8 @@ mod tests {
 
     fn setup_uih2_boundary_fixture() -> Vec<Arc<dyn AbstractTransaction + Send + Sync>> {
         vec![
-            Arc::new(DummyTxData::new_with_amounts(vec![100])),
-            Arc::new(DummyTxData::new_with_amounts(vec![200])),
+            Arc::new(DummyTxData::new_with_amounts(vec![100]).with_txid(TxId(1))),
+            Arc::new(DummyTxData::new_with_amounts(vec![200]).with_txid(TxId(2))),
             Arc::new(DummyTxData::new_with_spent(
                 vec![200, 0],
                 vec![TxOutId::new(TxId(1), 0), TxOutId::new(TxId(2), 0)],
@@ -222,10 +222,10 @@ mod tests {
 
     fn setup_uih_mixed_fixture() -> Vec<Arc<dyn AbstractTransaction + Send + Sync>> {
         vec![
-            Arc::new(DummyTxData::new_with_amounts(vec![100])),
-            Arc::new(DummyTxData::new_with_amounts(vec![200])),
-            Arc::new(DummyTxData::new_with_amounts(vec![50])),
-            Arc::new(DummyTxData::new_with_amounts(vec![260])),
+            Arc::new(DummyTxData::new_with_amounts(vec![100]).with_txid(TxId(1))),
+            Arc::new(DummyTxData::new_with_amounts(vec![200]).with_txid(TxId(2))),
+            Arc::new(DummyTxData::new_with_amounts(vec![50]).with_txid(TxId(3))),
+            Arc::new(DummyTxData::new_with_amounts(vec![260]).with_txid(TxId(4))),
             Arc::new(DummyTxData::new_with_spent(
                 vec![200, 30],
                 vec![TxOutId::new(TxId(1), 0), TxOutId::new(TxId(2), 0)],
@@ -76,10 +76,12 @@ impl ScriptTypesMatchingChangeIdentification {
             return TxOutChangeAnnotation::NotChange;
         }
 
+        // Output types come from the scriptPubKey alone, where wrapped segwit is plain P2SH.
+        let output_type = input_type.script_pubkey_type();
         let matching_outputs: Vec<usize> = tx
             .outputs()
             .enumerate()
-            .filter_map(|(index, output)| (output.output_type() == input_type).then_some(index))
+            .filter_map(|(index, output)| (output.output_type() == output_type).then_some(index))
             .collect();
 
         if matching_outputs.len() == 1 && matching_outputs[0] == tx_out.vout() {
@@ -174,12 +176,14 @@ mod tests {
                 100,
                 0,
                 script_from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"),
-            )]),
+            )])
+            .with_txid(TxId(1)),
             DummyTxData::new_with_outputs(vec![DummyTxOutData::new_with_script(
                 150,
                 0,
                 script_from_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"),
-            )]),
+            )])
+            .with_txid(TxId(2)),
             DummyTxData::new(
                 vec![
                     // payment output: P2TR (different type from inputs → not change)
@@ -227,14 +231,16 @@ mod tests {
                 100,
                 0,
                 script_from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"), // P2PKH
-            )]),
+            )])
+            .with_txid(TxId(1)),
             DummyTxData::new_with_outputs(vec![DummyTxOutData::new_with_script(
                 150,
                 0,
                 script_from_address(
                     "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
                 ), // P2TR
-            )]),
+            )])
+            .with_txid(TxId(2)),
             DummyTxData::new(
                 vec![
                     DummyTxOutData::new_with_script(
@@ -280,12 +286,14 @@ mod tests {
                 100,
                 0,
                 script_from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"),
-            )]),
+            )])
+            .with_txid(TxId(1)),
             DummyTxData::new_with_outputs(vec![DummyTxOutData::new_with_script(
                 150,
                 0,
                 script_from_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"),
-            )]),
+            )])
+            .with_txid(TxId(2)),
             DummyTxData::new(
                 vec![
                     // two P2PKH outputs — ambiguous, neither qualifies as unique change
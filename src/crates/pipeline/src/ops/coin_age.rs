@@ -0,0 +1,105 @@
+//! Spend-age metrics for the pipeline DSL.
+//!
+//! - `coin_ages`: TxSet -> InputCoinAges (age of each coin the transactions spend)
+//! - `coin_days_destroyed`: TxSet -> TxCoinDaysDestroyed
+
+use std::collections::HashMap;
+
+use tx_indexer_primitives::handle::CoinAge;
+use tx_indexer_primitives::unified::{AnyInId, AnyTxId};
+
+use crate::{
+    engine::EvalContext,
+    expr::Expr,
+    node::{Node, NodeId},
+    value::{InputCoinAges, TxCoinDaysDestroyed, TxSet},
+};
+
+/// Node that computes the coin age of every input of a set of transactions.
+///
+/// Inputs whose age is unknown (unconfirmed, or spending outside the indexed range) are
+/// left out.
+pub struct CoinAgesNode {
+    input: Expr<TxSet>,
+}
+
+impl CoinAgesNode {
+    pub fn new(input: Expr<TxSet>) -> Self {
+        Self { input }
+    }
+}
+
+impl Node for CoinAgesNode {
+    type OutputValue = InputCoinAges;
+
+    fn dependencies(&self) -> Vec<NodeId> {
+        vec![self.input.id()]
+    }
+
+    fn evaluate(&self, ctx: &EvalContext) -> HashMap<AnyInId, CoinAge> {
+        let tx_ids = ctx.get_or_default(&self.input);
+        tx_ids
+            .iter()
+            .flat_map(|tx_id| {
+                tx_id
+                    .with(ctx.unified_storage())
+                    .inputs()
+                    .collect::<Vec<_>>()
+            })
+            .filter_map(|input| Some((input.id(), input.coin_age()?)))
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "CoinAges"
+    }
+}
+
+/// Node that computes the coin-days destroyed by each transaction in a set.
+///
+/// Transactions with an input of unknown age are left out.
+pub struct CoinDaysDestroyedNode {
+    input: Expr<TxSet>,
+}
+
+impl CoinDaysDestroyedNode {
+    pub fn new(input: Expr<TxSet>) -> Self {
+        Self { input }
+    }
+}
+
+impl Node for CoinDaysDestroyedNode {
+    type OutputValue = TxCoinDaysDestroyed;
+
+    fn dependencies(&self) -> Vec<NodeId> {
+        vec![self.input.id()]
+    }
+
+    fn evaluate(&self, ctx: &EvalContext) -> HashMap<AnyTxId, f64> {
+        let tx_ids = ctx.get_or_default(&self.input);
+        tx_ids
+            .iter()
+            .filter_map(|tx_id| {
+                let cdd = tx_id.with(ctx.unified_storage()).coin_days_destroyed()?;
+                Some((*tx_id, cdd))
+            })
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "CoinDaysDestroyed"
+    }
+}
+
+// Extension methods on Expr<TxSet>
+impl Expr<TxSet> {
+    /// Age of every coin spent by these transactions, keyed by the spending input.
+    pub fn coin_ages(&self) -> Expr<InputCoinAges> {
+        self.ctx.register(CoinAgesNode::new(self.clone()))
+    }
+
+    /// Coin-days destroyed by each of these transactions.
+    pub fn coin_days_destroyed(&self) -> Expr<TxCoinDaysDestroyed> {
+        self.ctx.register(CoinDaysDestroyedNode::new(self.clone()))
+    }
+}
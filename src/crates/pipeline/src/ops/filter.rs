@@ -1,8 +1,11 @@
 //! Filter operations for the pipeline DSL.
 
 use std::hash::Hash;
+use std::ops::{Bound, RangeBounds};
 use std::sync::Arc;
 
+use bitcoin::FeeRate;
+
 use crate::engine::EvalContext;
 use crate::expr::Expr;
 use crate::node::{Node, NodeId};
@@ -139,6 +142,49 @@ impl Node for FilterWithPredicateNode<TxOutSet, AnyOutId> {
     }
 }
 
+/// Node that keeps the transactions whose feerate lies within a range.
+///
+/// Transactions without a known fee (coinbases, or inputs whose spent value is unavailable)
+/// are dropped.
+pub struct FeerateRangeNode {
+    input: Expr<TxSet>,
+    range: (Bound<FeeRate>, Bound<FeeRate>),
+}
+
+impl FeerateRangeNode {
+    pub fn new(input: Expr<TxSet>, range: impl RangeBounds<FeeRate>) -> Self {
+        Self {
+            input,
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+        }
+    }
+}
+
+impl Node for FeerateRangeNode {
+    type OutputValue = TxSet;
+
+    fn dependencies(&self) -> Vec<NodeId> {
+        vec![self.input.id()]
+    }
+
+    fn evaluate(&self, ctx: &EvalContext) -> Vec<AnyTxId> {
+        let input_set = ctx.get_or_default(&self.input);
+        input_set
+            .iter()
+            .filter(|id| {
+                id.with(ctx.unified_storage())
+                    .feerate()
+                    .is_some_and(|feerate| self.range.contains(&feerate))
+            })
+            .copied()
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "FeerateRange"
+    }
+}
+
 // Extension methods on Expr<TxSet>
 impl Expr<TxSet> {
     /// Filter transactions using a boolean mask.
@@ -158,6 +204,13 @@ impl Expr<TxSet> {
         self.ctx
             .register(FilterWithPredicateNode::new(self.clone(), Arc::new(f)))
     }
+    /// Keep transactions whose feerate lies within `range`.
+    ///
+    /// Transactions without a known fee are dropped.
+    pub fn filter_feerate(&self, range: impl RangeBounds<FeeRate>) -> Expr<TxSet> {
+        self.ctx
+            .register(FeerateRangeNode::new(self.clone(), range))
+    }
 }
 
 // Extension methods on Expr<TxOutSet>
@@ -1,19 +1,24 @@
 //! Built-in operations for the pipeline DSL.
 //!
 //! This module provides common operations that can be performed on expressions:
-//! - Filtering: `filter_with_mask`
+//! - Filtering: `filter_with_mask`, `filter_feerate`
 //! - Mask operations: `negate`, bitwise `&`
 //! - Set operations: `outputs`, `txs`, `join`
-//! - Source operations: `AllTxs`
+//! - Source operations: `AllTxs`, `UtxoSetAt`
+//! - Spend-age metrics: `coin_ages`, `coin_days_destroyed`
 
 pub mod bitwise;
+pub mod coin_age;
 pub mod filter;
 pub mod negate;
 pub mod set_ops;
 pub mod source;
 
 // Re-export commonly used items
-pub use filter::FilterWithMaskNode;
+pub use coin_age::{CoinAgesNode, CoinDaysDestroyedNode};
+pub use filter::{FeerateRangeNode, FilterWithMaskNode};
 pub use negate::NegateMaskNode;
 pub use set_ops::{JoinClusteringNode, TxsNode};
-pub use source::{AllDenseTxs, AllDenseTxsNode, AllLooseTxs, AllLooseTxsNode};
+pub use source::{
+    AllDenseTxs, AllDenseTxsNode, AllLooseTxs, AllLooseTxsNode, UtxoSetAt, UtxoSetAtNode,
+};
@@ -5,13 +5,14 @@
 
 use std::sync::Arc;
 
-use tx_indexer_primitives::unified::AnyTxId;
+use tx_indexer_primitives::UnifiedStorage;
+use tx_indexer_primitives::unified::{AnyOutId, AnyTxId};
 
 use crate::context::PipelineContext;
 use crate::engine::SourceNodeEvalContext;
 use crate::expr::Expr;
 use crate::node::SourceNode;
-use crate::value::TxSet;
+use crate::value::{TxOutSet, TxSet};
 
 /// Node that returns all newly observed loose transaction IDs.
 pub struct AllLooseTxsNode {
@@ -61,6 +62,57 @@ impl SourceNode for AllDenseTxsNode {
     }
 }
 
+/// Node that returns the outputs that were unspent once the block at `height` was connected.
+///
+/// Blocks up to `height` can still spend outputs funded before them, so nothing is returned
+/// until the index reaches `height`. The whole set is then returned at once, and newly synced
+/// blocks above it add nothing.
+pub struct UtxoSetAtNode {
+    height: u64,
+}
+
+impl UtxoSetAtNode {
+    pub fn new(height: u64) -> Self {
+        Self { height }
+    }
+
+    /// Whether the first `dense_len` dense transactions reach block `self.height`.
+    fn reached(&self, storage: &UnifiedStorage, dense_len: usize) -> bool {
+        dense_len
+            .checked_sub(1)
+            .and_then(|last| storage.dense_txids_from(last).next())
+            .and_then(|txid| txid.with(storage).block_height())
+            .is_some_and(|tip| tip >= self.height)
+    }
+}
+
+impl SourceNode for UtxoSetAtNode {
+    type OutputValue = TxOutSet;
+
+    fn evaluate(&self, ctx: &mut SourceNodeEvalContext<'_>) -> Vec<AnyOutId> {
+        let storage = ctx.unified_storage;
+        if self.reached(storage, ctx.processed_dense_len())
+            || !self.reached(storage, storage.dense_txids_len())
+        {
+            return Vec::new();
+        }
+        storage
+            .dense_txids_from(0)
+            .take_while(|txid| {
+                txid.with(storage)
+                    .block_height()
+                    .is_some_and(|height| height <= self.height)
+            })
+            .flat_map(|txid| storage.tx_out_ids(txid))
+            .filter(|out_id| storage.is_unspent_at(*out_id, self.height))
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "UtxoSetAt"
+    }
+}
+
 /// Factory for creating source expressions.
 pub struct AllLooseTxs {
     txs: Expr<TxSet>,
@@ -94,3 +146,20 @@ impl AllDenseTxs {
         self.txs.clone()
     }
 }
+
+/// Factory for the UTXO set as of a given block height.
+pub struct UtxoSetAt {
+    outputs: Expr<TxOutSet>,
+}
+
+impl UtxoSetAt {
+    /// Create a source expression for the outputs unspent once block `height` was connected.
+    pub fn new(ctx: &Arc<PipelineContext>, height: u64) -> Self {
+        let outputs = ctx.register_source(UtxoSetAtNode::new(height));
+        Self { outputs }
+    }
+
+    pub fn outputs(&self) -> Expr<TxOutSet> {
+        self.outputs.clone()
+    }
+}
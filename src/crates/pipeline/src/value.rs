@@ -9,7 +9,8 @@ use std::hash::Hash;
 use std::marker::PhantomData;
 
 use tx_indexer_disjoint_set::SparseDisjointSet;
-use tx_indexer_primitives::unified::{AnyOutId, AnyTxId};
+use tx_indexer_primitives::handle::CoinAge;
+use tx_indexer_primitives::unified::{AnyInId, AnyOutId, AnyTxId};
 
 /// Trait for types that can be the value of an expression.
 ///
@@ -65,6 +66,39 @@ impl<K: Eq + Hash + Clone + Send + Sync + 'static> ExprValue for Mask<K> {
     }
 }
 
+/// Marker type for a per-item value of type `V` over items of type `K`, such as a metric
+/// computed for each transaction. Items the value is unknown for are left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Annotation<K, V>(PhantomData<(K, V)>);
+
+impl<K, V> Default for Annotation<K, V> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<K, V> ExprValue for Annotation<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: PartialEq + Clone + Send + Sync + 'static,
+{
+    type Output = HashMap<K, V>;
+
+    fn combine_facts<'a>(facts: &[&'a Self::Output]) -> Cow<'a, Self::Output> {
+        match facts {
+            [] => Cow::Owned(Default::default()),
+            [single] => Cow::Borrowed(*single),
+            [first, rest @ ..] => {
+                let mut acc = (*first).clone();
+                for next in rest {
+                    acc.extend(next.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+                Cow::Owned(acc)
+            }
+        }
+    }
+}
+
 /// Marker type for clustering (disjoint set union of transaction outputs).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Clustering<T>(PhantomData<T>);
@@ -165,5 +199,8 @@ pub type TxOutSet = TransactionOutSet;
 pub type TxMask = Mask<AnyTxId>;
 pub type TxOutMask = Mask<AnyOutId>;
 pub type TxOutClustering = Clustering<AnyOutId>;
+pub type InputCoinAges = Annotation<AnyInId, CoinAge>;
+/// Coin-days destroyed per transaction, in BTC-days.
+pub type TxCoinDaysDestroyed = Annotation<AnyTxId, f64>;
 // TODO: replace with fixed size array
 pub type NormalizedFingerprints = ContainerType<Vec<u32>>;
@@ -23,9 +23,12 @@ fn bench_parse_mainnet_702861(c: &mut Criterion) {
             |index_dir| {
                 let mut parser = Parser::new(&blocks_dir);
                 let mut indices = DenseIndexSet::new(&index_dir).unwrap();
-                let mut spk_db = SledDBFactory::open(&sled_dir).unwrap().spk_db().unwrap();
+                let sled_db = SledDBFactory::open(&sled_dir).unwrap();
+                let mut spk_db = sled_db.spk_db().unwrap();
+                let mut txid_db = sled_db.txid_db().unwrap();
 
-                let mut sink = DenseIndexSink::new(&mut indices, &mut spk_db).unwrap();
+                let mut sink =
+                    DenseIndexSink::new(&mut indices, &mut spk_db, &mut txid_db).unwrap();
 
                 parser.parse_blocks(0..1, &mut sink).unwrap();
 
@@ -6,7 +6,7 @@ use std::{
 
 const BLOCK_HEADER_LEN: usize = 8;
 
-/// XOR-aware reader for Bitcoin Core blk*.dat files.
+/// XOR-aware reader for Bitcoin Core blk*.dat and rev*.dat files.
 ///
 /// Bitcoin Core v27+ writes `blocks/xor.dat` containing an 8-byte key and XOR-encrypts
 /// every blk file with it (cycling the key by absolute byte position).  This struct reads
@@ -38,6 +38,11 @@ impl BlkFileStore {
         self.blocks_dir.join(format!("blk{:05}.dat", file_no))
     }
 
+    /// Construct the path `blocks_dir/revNNNNN.dat` for a given file number.
+    pub fn rev_path(&self, file_no: u32) -> PathBuf {
+        self.blocks_dir.join(format!("rev{:05}.dat", file_no))
+    }
+
     /// Read an entire blk file into memory, XOR-decrypting from offset 0.
     pub fn read_file(&self, file_no: u32) -> io::Result<Vec<u8>> {
         let mut bytes = std::fs::read(self.blk_path(file_no))?;
@@ -109,7 +114,40 @@ impl BlkFileStore {
 
     /// Read `len` bytes starting at `offset` from a blk file, XOR-decrypting in place.
     pub fn read_at(&self, file_no: u32, offset: u32, len: u32) -> io::Result<Vec<u8>> {
-        let mut f = File::open(self.blk_path(file_no))?;
+        self.read_path_at(&self.blk_path(file_no), offset, len)
+    }
+
+    /// Read the block whose data starts at `data_pos` in a blk file, XOR-decrypting in place.
+    ///
+    /// `data_pos` is the position recorded in the block index, past the magic and length
+    /// prefix.
+    pub fn read_block(&self, file_no: u32, data_pos: u32) -> io::Result<Vec<u8>> {
+        self.read_record(&self.blk_path(file_no), data_pos)
+    }
+
+    /// Read the undo record starting at `undo_pos` in a rev file, XOR-decrypting in place.
+    ///
+    /// `undo_pos` points past the record's magic and length prefix, like a block's data
+    /// position in its blk file. The trailing checksum is not returned.
+    pub fn read_undo(&self, file_no: u32, undo_pos: u32) -> io::Result<Vec<u8>> {
+        self.read_record(&self.rev_path(file_no), undo_pos)
+    }
+
+    /// Read the length-prefixed record whose payload starts at `pos`.
+    fn read_record(&self, path: &Path, pos: u32) -> io::Result<Vec<u8>> {
+        let len_offset = pos.checked_sub(4).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record position inside file header",
+            )
+        })?;
+        let len_bytes = self.read_path_at(path, len_offset, 4)?;
+        let len = u32::from_le_bytes(len_bytes.try_into().expect("read 4 bytes"));
+        self.read_path_at(path, pos, len)
+    }
+
+    fn read_path_at(&self, path: &Path, offset: u32, len: u32) -> io::Result<Vec<u8>> {
+        let mut f = File::open(path)?;
         f.seek(SeekFrom::Start(offset as u64))?;
         let mut buf = vec![0u8; len as usize];
         f.read_exact(&mut buf)?;
@@ -214,4 +252,30 @@ mod tests {
         let got = store.read_at(0, 4, 8).unwrap();
         assert_eq!(got, &plaintext[4..12]);
     }
+
+    #[test]
+    fn read_undo_skips_record_header_and_checksum() {
+        let tmp = tempfile::tempdir().unwrap();
+        let key = [0x10u8, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80];
+        write_file(&tmp.path().join("xor.dat"), &key);
+        let undo = b"undo-record";
+        // Two records: magic, length, payload, 32-byte checksum.
+        let mut plaintext = Vec::new();
+        for payload in [&b"first"[..], &undo[..]] {
+            plaintext.extend_from_slice(&[0xfa, 0xbf, 0xb5, 0xda]);
+            plaintext.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            plaintext.extend_from_slice(payload);
+            plaintext.extend_from_slice(&[0xee; 32]);
+        }
+        let encrypted: Vec<u8> = plaintext
+            .iter()
+            .enumerate()
+            .map(|(i, b)| b ^ key[i % 8])
+            .collect();
+        write_file(&tmp.path().join("rev00003.dat"), &encrypted);
+        let store = BlkFileStore::open(tmp.path());
+        let second_pos = (8 + 5 + 32 + 8) as u32;
+        assert_eq!(store.read_undo(3, second_pos).unwrap(), undo);
+        assert_eq!(store.read_undo(3, 8).unwrap(), b"first");
+    }
 }
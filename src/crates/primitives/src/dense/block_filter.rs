@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use bitcoin::bip158::{BlockFilter, GcsFilterWriter};
+use bitcoin::hashes::Hash;
+use bitcoin_block_index::undo::BlockUndo;
+
+use crate::{
+    blk_file::BlkFileStore,
+    dense::{DenseIndexSink, DenseStorage, TxInId},
+    indices::BlockFilterIndex,
+    parser::{BlockFileError, UndoHint},
+    sled::spent_output_db::SledSpentOutputDb,
+    traits::{IndexSink, SpentOutputDb},
+};
+
+/// Golomb-Rice parameter `P` of the BIP158 basic filter.
+const BASIC_FILTER_P: u8 = 19;
+/// False positive rate parameter `M` of the BIP158 basic filter.
+const BASIC_FILTER_M: u64 = 784_931;
+
+/// [`IndexSink`] that indexes blocks through a [`DenseIndexSink`] and builds the BIP158 basic
+/// filter of each one in the same pass, appending it to a [`BlockFilterIndex`].
+///
+/// Spent scripts come from the block's undo record, read once per block from the [`UndoHint`]
+/// for it. The spent outputs of inputs whose prevout is not indexed are also recorded in the
+/// spent output db along the way (see [`DenseStorage::spent_txout`]). A block that spends
+/// outputs but has no undo record gets a filter flagged as incomplete.
+pub struct FilteredIndexSink<'a> {
+    dense: DenseIndexSink<'a>,
+    store: &'a BlkFileStore,
+    spent_output_db: &'a mut SledSpentOutputDb,
+    undo_hints: HashMap<u64, UndoHint>,
+    /// Global height of the block being fed.
+    height: u64,
+    /// Non-coinbase inputs of the block so far.
+    spends_in_block: usize,
+    /// Inputs of the block whose prevout is not indexed, with their position among the
+    /// block's spends, which is their position in the block's undo record.
+    unresolved: Vec<(usize, TxInId)>,
+    writer: FilterWriter<'a>,
+}
+
+impl<'a> FilteredIndexSink<'a> {
+    /// `height` is the global height of the first block that will be fed, which must be the
+    /// block after the last one indexed and filtered.
+    pub fn new(
+        dense: DenseIndexSink<'a>,
+        filters: &'a mut BlockFilterIndex,
+        store: &'a BlkFileStore,
+        spent_output_db: &'a mut SledSpentOutputDb,
+        undo_hints: &[UndoHint],
+        height: u64,
+    ) -> Self {
+        Self {
+            dense,
+            store,
+            spent_output_db,
+            undo_hints: undo_hints.iter().map(|hint| (hint.height, *hint)).collect(),
+            height,
+            spends_in_block: 0,
+            unresolved: Vec::new(),
+            writer: FilterWriter::new(filters),
+        }
+    }
+
+    /// Blocks whose filter is incomplete because spent scripts were unknown.
+    pub fn incomplete_blocks(&self) -> u64 {
+        self.writer.incomplete_blocks
+    }
+
+    /// Add the spent scripts of the block from its undo record, or flag them all as unknown if
+    /// it has none.
+    fn add_spent_from_undo(&mut self) -> Result<(), BlockFileError> {
+        let Some(hint) = self.undo_hints.get(&self.height) else {
+            self.unresolved.clear();
+            for _ in 0..self.spends_in_block {
+                self.writer.add_spent(None);
+            }
+            return Ok(());
+        };
+        let raw = self
+            .store
+            .read_undo(hint.file_no, hint.undo_pos)
+            .map_err(BlockFileError::Io)?;
+        let undo = BlockUndo::decode(&raw).map_err(BlockFileError::BlockIndex)?;
+        let spent: Vec<_> = undo.txs.iter().flat_map(|tx| &tx.spent).collect();
+        if spent.len() != self.spends_in_block {
+            return Err(BlockFileError::CorruptId());
+        }
+        for (position, in_id) in self.unresolved.drain(..) {
+            self.spent_output_db
+                .insert(in_id, spent[position])
+                .map_err(BlockFileError::SpentOutputDb)?;
+        }
+        for spent in spent {
+            self.writer.add_spent(Some(spent.script_pubkey.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl IndexSink for FilteredIndexSink<'_> {
+    type Error = BlockFileError;
+
+    fn on_input(
+        &mut self,
+        vin: usize,
+        prev_txid: &[u8; 32],
+        prev_vout: u32,
+    ) -> Result<(), BlockFileError> {
+        self.dense.on_input(vin, prev_txid, prev_vout)?;
+        if is_null_prevout(prev_txid, prev_vout) {
+            return Ok(());
+        }
+        if let Some(in_id) = self.dense.last_unresolved() {
+            self.unresolved.push((self.spends_in_block, in_id));
+        }
+        self.spends_in_block += 1;
+        Ok(())
+    }
+
+    fn on_output(
+        &mut self,
+        vout: usize,
+        value: u64,
+        script_pubkey: &[u8],
+    ) -> Result<(), BlockFileError> {
+        self.dense.on_output(vout, value, script_pubkey)?;
+        self.writer.add_output(script_pubkey);
+        Ok(())
+    }
+
+    fn on_transaction(
+        &mut self,
+        txid: &[u8; 32],
+        blk_file_no: u32,
+        blk_file_off: u32,
+        tx_len: u32,
+        tx_bytes: &[u8],
+    ) -> Result<(), BlockFileError> {
+        self.dense
+            .on_transaction(txid, blk_file_no, blk_file_off, tx_len, tx_bytes)
+    }
+
+    fn on_block_end(
+        &mut self,
+        header: &[u8; 80],
+        block_hash: &[u8; 32],
+        block_tx_count: u64,
+    ) -> Result<(), BlockFileError> {
+        self.dense
+            .on_block_end(header, block_hash, block_tx_count)?;
+        if self.spends_in_block > 0 {
+            self.add_spent_from_undo()?;
+        }
+        self.writer.finish_block(block_hash)?;
+        self.height += 1;
+        self.spends_in_block = 0;
+        Ok(())
+    }
+}
+
+/// [`IndexSink`] that builds the BIP158 basic filter of blocks that are already indexed in
+/// `storage` and appends them to a [`BlockFilterIndex`]. Used to catch the filters up with
+/// the index, e.g. after a sync was interrupted between indexing a block and filtering it.
+///
+/// The parser only hands out the outpoints of inputs, so spent scripts are looked up in
+/// `storage` by dense input id (see [`DenseStorage::spent_txout`]). The blocks must be fed
+/// starting right after the last filtered block.
+pub struct BlockFilterSink<'a> {
+    storage: &'a DenseStorage,
+    next_in_id: u64,
+    writer: FilterWriter<'a>,
+}
+
+impl<'a> BlockFilterSink<'a> {
+    pub fn new(
+        storage: &'a DenseStorage,
+        filters: &'a mut BlockFilterIndex,
+    ) -> Result<Self, BlockFileError> {
+        let (_, next_in_id, _) = storage
+            .indices
+            .boundaries_after(filters.len())
+            .map_err(BlockFileError::Io)?;
+        Ok(Self {
+            storage,
+            next_in_id,
+            writer: FilterWriter::new(filters),
+        })
+    }
+
+    /// Blocks whose filter is incomplete because spent scripts were unknown.
+    pub fn incomplete_blocks(&self) -> u64 {
+        self.writer.incomplete_blocks
+    }
+}
+
+impl IndexSink for BlockFilterSink<'_> {
+    type Error = BlockFileError;
+
+    fn on_input(
+        &mut self,
+        _vin: usize,
+        prev_txid: &[u8; 32],
+        prev_vout: u32,
+    ) -> Result<(), BlockFileError> {
+        let in_id = TxInId::new(self.next_in_id);
+        self.next_in_id += 1;
+        if is_null_prevout(prev_txid, prev_vout) {
+            return Ok(());
+        }
+        let script = self
+            .storage
+            .spent_txout(in_id)
+            .map(|txout| txout.script_pubkey.into_bytes());
+        self.writer.add_spent(script);
+        Ok(())
+    }
+
+    fn on_output(
+        &mut self,
+        _vout: usize,
+        _value: u64,
+        script_pubkey: &[u8],
+    ) -> Result<(), BlockFileError> {
+        self.writer.add_output(script_pubkey);
+        Ok(())
+    }
+
+    fn on_transaction(
+        &mut self,
+        _txid: &[u8; 32],
+        _blk_file_no: u32,
+        _blk_file_off: u32,
+        _tx_len: u32,
+        _tx_bytes: &[u8],
+    ) -> Result<(), BlockFileError> {
+        Ok(())
+    }
+
+    fn on_block_end(
+        &mut self,
+        _header: &[u8; 80],
+        block_hash: &[u8; 32],
+        _block_tx_count: u64,
+    ) -> Result<(), BlockFileError> {
+        self.writer.finish_block(block_hash)
+    }
+}
+
+/// Collects the filter elements of one block at a time and appends the finished filter.
+struct FilterWriter<'a> {
+    filters: &'a mut BlockFilterIndex,
+    elements: Vec<Vec<u8>>,
+    /// Spent scripts of the current block that are unknown.
+    missing_in_block: u64,
+    incomplete_blocks: u64,
+}
+
+impl<'a> FilterWriter<'a> {
+    fn new(filters: &'a mut BlockFilterIndex) -> Self {
+        Self {
+            filters,
+            elements: Vec::new(),
+            missing_in_block: 0,
+            incomplete_blocks: 0,
+        }
+    }
+
+    fn add_output(&mut self, script_pubkey: &[u8]) {
+        if !bitcoin::Script::from_bytes(script_pubkey).is_op_return() {
+            self.elements.push(script_pubkey.to_vec());
+        }
+    }
+
+    /// Add the script spent by an input, `None` if it is unknown.
+    fn add_spent(&mut self, script_pubkey: Option<Vec<u8>>) {
+        match script_pubkey {
+            Some(script_pubkey) => self.elements.push(script_pubkey),
+            None => self.missing_in_block += 1,
+        }
+    }
+
+    fn finish_block(&mut self, block_hash: &[u8; 32]) -> Result<(), BlockFileError> {
+        let filter = basic_filter(block_hash, &self.elements);
+        let complete = self.missing_in_block == 0;
+        self.filters
+            .append(&filter.content, complete)
+            .map_err(BlockFileError::Io)?;
+        if !complete {
+            log::warn!(
+                "block {}: {} spent scripts unknown, its filter is incomplete",
+                bitcoin::BlockHash::from_byte_array(*block_hash),
+                self.missing_in_block
+            );
+            self.incomplete_blocks += 1;
+        }
+        self.missing_in_block = 0;
+        self.elements.clear();
+        Ok(())
+    }
+}
+
+fn is_null_prevout(prev_txid: &[u8; 32], prev_vout: u32) -> bool {
+    prev_vout == u32::MAX && prev_txid.iter().all(|b| *b == 0)
+}
+
+/// BIP158 basic filter over `elements`, keyed by the block hash (internal byte order).
+/// Empty elements are skipped and duplicates counted once.
+fn basic_filter(block_hash: &[u8; 32], elements: &[Vec<u8>]) -> BlockFilter {
+    let k0 = u64::from_le_bytes(block_hash[0..8].try_into().expect("slice length"));
+    let k1 = u64::from_le_bytes(block_hash[8..16].try_into().expect("slice length"));
+    let mut content = Vec::new();
+    let mut writer = GcsFilterWriter::new(&mut content, k0, k1, BASIC_FILTER_M, BASIC_FILTER_P);
+    for element in elements {
+        writer.add_element(element);
+    }
+    writer.finish().expect("writing to a Vec cannot fail");
+    BlockFilter { content }
+}
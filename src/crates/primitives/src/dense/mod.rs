@@ -1,15 +1,37 @@
+pub mod block_filter;
 pub mod sink;
+pub mod verify;
+pub use block_filter::{BlockFilterSink, FilteredIndexSink};
 pub use sink::DenseIndexSink;
+pub use verify::{VerifyIssue, VerifyReport};
 
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bitcoin::{Amount, ScriptBuf, hashes::Hash};
+use bitcoin_block_index::undo::{BlockUndo, SpentOutput};
 
 use crate::{
-    ScriptPubkeyHash,
+    AnyTxId, OutputType, ScriptPubkeyHash,
     blk_file::BlkFileStore,
-    indices::{DenseIndexSet, INID_NONE, OUTID_NONE, TxPtr},
-    parser::{BlkFileHint, BlockFileError, Parser, collect_file_hints},
-    sled::{db::SledDBFactory, spk_db::SledScriptPubkeyDb},
-    traits::ScriptPubkeyDb,
+    indices::{
+        BlockFilterIndex, BlockHashIndex, DenseIndexMeta, DenseIndexSet, INID_NONE, OUTID_NONE,
+        TxPtr,
+    },
+    loose::ConfirmedTx,
+    parser::{
+        BlkFileHint, BlockFileError, Parser, UndoHint, collect_file_hints, collect_undo_hints,
+    },
+    sled::{
+        db::SledDBFactory, spent_output_db::SledSpentOutputDb, spk_db::SledScriptPubkeyDb,
+        txid_db::SledTxidDb,
+    },
+    traits::{
+        ScriptPubkeyDb, SpentOutputDb, TxidDb, abstract_types::AbstractTransaction,
+        graph_index::TxIndex,
+    },
     unified::SyncError,
 };
 
@@ -65,8 +87,21 @@ impl TxInId {
 pub struct DenseStorageBuilder {
     data_dir: PathBuf,
     index_dir: PathBuf,
+    /// Height of the first block in the index once built.
+    first_indexed: u64,
+    /// Heights of the blocks to parse.
     range: std::ops::Range<u64>,
     file_hints: Vec<BlkFileHint>,
+    /// Already-indexed blocks to keep; everything after them is rolled back before syncing.
+    keep_blocks: Option<u64>,
+    parse_threads: usize,
+    /// Undo records of the blocks in `range`, used to resolve prevouts created before the
+    /// first indexed block.
+    undo_hints: Vec<UndoHint>,
+    /// Network requested by the caller; `None` keeps the one recorded in the index.
+    network: Option<bitcoin::Network>,
+    /// Start keeping BIP158 filters. Indexes that already keep them always do.
+    block_filters: bool,
 }
 
 impl DenseStorageBuilder {
@@ -79,11 +114,56 @@ impl DenseStorageBuilder {
         Self {
             data_dir,
             index_dir,
+            first_indexed: range.start,
             range,
             file_hints,
+            keep_blocks: None,
+            parse_threads: 1,
+            undo_hints: Vec::new(),
+            network: None,
+            block_filters: false,
         }
     }
 
+    /// Decode blk files on `threads` worker threads. See [`Parser::with_threads`].
+    pub fn with_parse_threads(mut self, threads: usize) -> Self {
+        self.parse_threads = threads;
+        self
+    }
+
+    /// Network of the node in `data_dir`, used to render and parse addresses. It is recorded
+    /// in the index, so later syncs and [`Self::open`] pick it up without being told again.
+    /// An index created without it records no network until a later build supplies one.
+    ///
+    /// Building fails with [`SyncError::NetworkMismatch`] if the index was created for a
+    /// different network.
+    pub fn with_network(mut self, network: bitcoin::Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Build the BIP158 basic filter of every synced block and store it with the index. Once
+    /// an index keeps filters, later syncs extend them without being told again. See
+    /// [`DenseStorage::block_filter`].
+    ///
+    /// Filters are built in the same pass as the index, taking spent scripts from the blocks'
+    /// undo records, so they need undo hints for every block that spends outputs; the
+    /// `sync_from_*` constructors collect them. Blocks without one get no filter. Filters can
+    /// only be started together with a new index; otherwise building fails with
+    /// [`SyncError::MissingBlockFilters`].
+    pub fn with_block_filters(mut self) -> Self {
+        self.block_filters = true;
+        self
+    }
+
+    /// Read the spent outputs of inputs whose prevout lies before the first indexed block
+    /// from the blocks' undo records. See [`DenseStorage::spent_txout`]. Block filters read
+    /// every spent script from them.
+    pub fn with_undo_hints(mut self, undo_hints: Vec<UndoHint>) -> Self {
+        self.undo_hints = undo_hints;
+        self
+    }
+
     /// Build a [`DenseStorage`] for every block from genesis up to the chain tip.
     ///
     /// `data_dir` is Bitcoin Core's data directory (e.g. `~/.bitcoin/` or
@@ -91,30 +171,18 @@ impl DenseStorageBuilder {
     ///
     /// `index_dir` is the output directory where all dense index files and the sled database
     /// will be written. The caller is responsible for creating this directory before calling.
+    ///
+    /// If `index_dir` already holds an index, only the blocks after the last indexed one are
+    /// parsed. Indexed blocks that are no longer on the best chain are rolled back first.
+    ///
+    /// On a pruned node a new index starts at the lowest block still on disk instead, as
+    /// reported by [`Self::indexed_heights`]. Prevouts created before it are handled as for
+    /// [`Self::sync_from_tip`].
     pub fn sync_from_genesis(
         data_dir: PathBuf,
         index_dir: PathBuf,
     ) -> Result<Self, BlockFileError> {
-        use bitcoin_block_index::BlockIndex;
-        let block_index_path = data_dir.join("blocks/index");
-
-        let mut index = BlockIndex::open(&block_index_path).map_err(BlockFileError::BlockIndex)?;
-
-        let tip_hash = index.best_block().map_err(BlockFileError::BlockIndex)?;
-        let tip_loc = index
-            .block_location(&tip_hash)
-            .map_err(BlockFileError::BlockIndex)?;
-        let end_height = tip_loc.height as u64;
-
-        let file_hints = collect_file_hints(&mut index, 0, end_height)?;
-
-        let builder = DenseStorageBuilder {
-            data_dir,
-            index_dir,
-            range: 0..end_height + 1,
-            file_hints,
-        };
-        Ok(builder)
+        Self::sync_to_tip(data_dir, index_dir, |_| 0)
     }
 
     /// Build a [`DenseStorage`] for the `depth + 1` blocks ending at the chain tip.
@@ -124,85 +192,649 @@ impl DenseStorageBuilder {
     ///
     /// `index_dir` is the output directory where all dense index files and the sled database
     /// will be written. The caller is responsible for creating this directory before calling.
+    ///
+    /// If `index_dir` already holds an index, `depth` is ignored: the sync resumes after the
+    /// last indexed block, rolling back any indexed blocks that were reorged out. On a pruned
+    /// node `depth` is capped at the blocks still on disk.
+    ///
+    /// Inputs spending outputs created before the first indexed block have no prevout in the
+    /// index. Their value and script are read from Bitcoin Core's undo data instead, where the
+    /// node has it.
     pub fn sync_from_tip(
         data_dir: PathBuf,
         index_dir: PathBuf,
         depth: u32,
     ) -> Result<Self, BlockFileError> {
-        // TODO: check if the indices were built already past or before the depth
+        Self::sync_to_tip(data_dir, index_dir, |tip_height| {
+            tip_height.saturating_sub(depth as u64)
+        })
+    }
+
+    /// Shared body of the `sync_from_*` constructors. `fresh_start` maps the tip height to
+    /// the first height to index when `index_dir` holds no index yet.
+    ///
+    /// Fails with [`BlockFileError::Pruned`] if an existing index ends below the blocks a
+    /// pruned node still stores.
+    fn sync_to_tip(
+        data_dir: PathBuf,
+        index_dir: PathBuf,
+        fresh_start: impl FnOnce(u64) -> u64,
+    ) -> Result<Self, BlockFileError> {
         use bitcoin_block_index::BlockIndex;
         let block_index_path = data_dir.join("blocks/index");
 
         let mut index = BlockIndex::open(&block_index_path).map_err(BlockFileError::BlockIndex)?;
 
         let tip_hash = index.best_block().map_err(BlockFileError::BlockIndex)?;
-        let chain = index
-            .walk_back(&tip_hash, depth)
+        let tip_loc = index
+            .block_location(&tip_hash)
             .map_err(BlockFileError::BlockIndex)?;
+        let end_height = tip_loc.height as u64;
+        let first_stored = if index.is_pruned().map_err(BlockFileError::BlockIndex)? {
+            index
+                .first_stored_height(&tip_hash)
+                .map_err(BlockFileError::BlockIndex)? as u64
+        } else {
+            0
+        };
 
-        let start_height = chain
-            .first()
-            .expect("walk_back returns depth+1 items")
-            .height as u64;
-        let end_height = chain
-            .last()
-            .expect("walk_back returns depth+1 items")
-            .height as u64;
+        let (first_indexed, start_height, keep_blocks) = match find_resume_point(
+            &mut index,
+            &index_dir,
+            &tip_hash,
+            first_stored,
+        )? {
+            Some(resume) => (
+                resume.start_height,
+                resume.start_height + resume.keep_blocks,
+                Some(resume.keep_blocks),
+            ),
+            None => {
+                let mut start_height = fresh_start(end_height);
+                if start_height < first_stored {
+                    log::info!(
+                        "Node is pruned: indexing from height {first_stored} instead of {start_height}"
+                    );
+                    start_height = first_stored;
+                }
+                (start_height, start_height, None)
+            }
+        };
 
-        let file_hints = collect_file_hints(&mut index, start_height, end_height)?;
+        // Blocks indexed but not yet filtered are parsed again to catch the filters up.
+        let parse_start =
+            match BlockFilterIndex::stored_len(&index_dir).map_err(BlockFileError::Io)? {
+                Some(filtered) => start_height.min(first_indexed + filtered),
+                None => start_height,
+            };
+        let file_hints = if parse_start <= end_height {
+            collect_file_hints(&mut index, parse_start, end_height)?
+        } else {
+            Vec::new()
+        };
+        // Block filters take every spent script from the undo records, so they are collected
+        // even for an index that starts at genesis and resolves every prevout by itself.
+        let undo_hints = if start_height <= end_height {
+            collect_undo_hints(&mut index, &tip_hash, start_height, end_height)?
+        } else {
+            Vec::new()
+        };
 
         let builder = DenseStorageBuilder {
             data_dir,
             index_dir,
+            first_indexed,
             range: start_height..end_height + 1,
             file_hints,
+            keep_blocks,
+            parse_threads: 1,
+            undo_hints,
+            network: None,
+            block_filters: false,
         };
         Ok(builder)
     }
 
+    /// Open the index already in `index_dir` without parsing any blocks.
+    ///
+    /// Building still upgrades the index where needed. To only read it, e.g. to
+    /// [verify](DenseStorage::verify) it, use [`DenseStorage::open_read_only`].
+    pub fn open(data_dir: PathBuf, index_dir: PathBuf) -> Result<Self, BlockFileError> {
+        let meta = existing_meta(&index_dir).map_err(BlockFileError::Io)?;
+        let blocks = BlockHashIndex::open(index_dir.join("block_hash.bin"))
+            .map_err(BlockFileError::Io)?
+            .len();
+        let end = meta.start_height + blocks;
+        let mut builder = Self::new(data_dir, index_dir, end..end, Vec::new());
+        builder.first_indexed = meta.start_height;
+        Ok(builder)
+    }
+
+    /// Heights the index will cover once built, including blocks indexed by earlier syncs.
+    ///
+    /// Tells callers where a sync on a pruned node had to start. For a builder created with
+    /// [`Self::new`] this is the range it was given.
+    pub fn indexed_heights(&self) -> std::ops::Range<u64> {
+        self.first_indexed..self.range.end
+    }
+
     pub fn build(self) -> Result<DenseStorage, SyncError> {
         build_indices(self)
     }
 }
 
+/// Read the metadata of the index in `index_dir`, failing if there is none.
+fn existing_meta(index_dir: &Path) -> std::io::Result<DenseIndexMeta> {
+    DenseIndexMeta::read(index_dir)?.ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no dense index in index_dir")
+    })
+}
+
+/// Where a sync into an existing index continues from.
+struct ResumePoint {
+    /// Global height of the first indexed block.
+    start_height: u64,
+    /// How many indexed blocks are still on the node's best chain.
+    keep_blocks: u64,
+}
+
+/// Compare the block hashes stored in `index_dir` against the best chain ending at
+/// `tip_hash`. Returns `None` when `index_dir` holds no index.
+///
+/// The comparison needs the blocks after the index to still be on disk, so an index ending
+/// below `first_stored` is rejected with [`BlockFileError::Pruned`].
+fn find_resume_point(
+    index: &mut bitcoin_block_index::BlockIndex,
+    index_dir: &Path,
+    tip_hash: &[u8; 32],
+    first_stored: u64,
+) -> Result<Option<ResumePoint>, BlockFileError> {
+    let Some(meta) = DenseIndexMeta::read(index_dir).map_err(BlockFileError::Io)? else {
+        return Ok(None);
+    };
+    let start_height = meta.start_height;
+    let hashes =
+        BlockHashIndex::open(index_dir.join("block_hash.bin")).map_err(BlockFileError::Io)?;
+    if hashes.is_empty() {
+        return Ok(Some(ResumePoint {
+            start_height,
+            keep_blocks: 0,
+        }));
+    }
+    let last_indexed = start_height + hashes.len() - 1;
+    if last_indexed + 1 < first_stored {
+        return Err(BlockFileError::Pruned {
+            height: last_indexed + 1,
+            first_stored,
+        });
+    }
+
+    let mut hash = *tip_hash;
+    let mut loc = index
+        .block_location(&hash)
+        .map_err(BlockFileError::BlockIndex)?;
+    while loc.height as u64 > last_indexed {
+        hash = loc.prev_hash;
+        loc = index
+            .block_location(&hash)
+            .map_err(BlockFileError::BlockIndex)?;
+    }
+    loop {
+        let height = loc.height as u64;
+        if height < start_height {
+            break;
+        }
+        let stored = hashes
+            .get(height - start_height)
+            .map_err(BlockFileError::Io)?;
+        if stored == Some(hash) {
+            let keep_blocks = height - start_height + 1;
+            if height < last_indexed {
+                log::info!(
+                    "Reorg detected: rolling back indexed blocks {}..={}",
+                    height + 1,
+                    last_indexed
+                );
+            }
+            return Ok(Some(ResumePoint {
+                start_height,
+                keep_blocks,
+            }));
+        }
+        if height == start_height {
+            break;
+        }
+        hash = loc.prev_hash;
+        loc = index
+            .block_location(&hash)
+            .map_err(BlockFileError::BlockIndex)?;
+    }
+    log::info!("Reorg deeper than the indexed range: re-indexing from {start_height}");
+    Ok(Some(ResumePoint {
+        start_height,
+        keep_blocks: 0,
+    }))
+}
+
+/// Drop every indexed block after the first `keep_blocks`, including the txid, script
+/// pubkey and spent output entries of the dropped transactions, outputs and inputs.
+fn roll_back(
+    indices: &mut DenseIndexSet,
+    spk_db: &mut SledScriptPubkeyDb,
+    txid_db: &mut SledTxidDb,
+    spent_output_db: &mut SledSpentOutputDb,
+    keep_blocks: u64,
+) -> Result<(), BlockFileError> {
+    if keep_blocks >= indices.block_count() {
+        return Ok(());
+    }
+    let (tx_end, in_end, out_end) = indices
+        .boundaries_after(keep_blocks)
+        .map_err(BlockFileError::Io)?;
+    for out_id in out_end..indices.out_spk_hash.len() {
+        let spk_hash = indices
+            .out_spk_hash
+            .get(out_id)
+            .map_err(BlockFileError::Io)?
+            .ok_or(BlockFileError::CorruptId())?;
+        spk_db
+            .remove(&spk_hash, TxOutId::new(out_id))
+            .map_err(BlockFileError::SpkDb)?;
+    }
+    // Newest first, so a duplicate txid falls back to the copy before it.
+    for i in (tx_end..indices.tx_hash.len()).rev() {
+        let dense_txid = TxId::new(i as u32);
+        let txid = indices
+            .tx_hash
+            .get(dense_txid)
+            .map_err(BlockFileError::Io)?
+            .ok_or(BlockFileError::CorruptId())?;
+        txid_db
+            .remove(&txid, dense_txid)
+            .map_err(BlockFileError::TxidDb)?;
+    }
+    spent_output_db
+        .remove_from(TxInId::new(in_end))
+        .map_err(BlockFileError::SpentOutputDb)?;
+    indices
+        .truncate_blocks(keep_blocks)
+        .map_err(BlockFileError::Io)
+}
+
+/// Index every output by script pubkey from the `out_spk_hash` column. Used to upgrade
+/// indexes whose spk db only recorded the first output of each script pubkey.
+fn rebuild_spk_db(
+    indices: &DenseIndexSet,
+    spk_db: &mut SledScriptPubkeyDb,
+) -> Result<(), BlockFileError> {
+    log::info!("Rebuilding the script pubkey index for every output");
+    for out_id in 0..indices.out_spk_hash.len() {
+        let spk_hash = indices
+            .out_spk_hash
+            .get(out_id)
+            .map_err(BlockFileError::Io)?
+            .ok_or(BlockFileError::CorruptId())?;
+        spk_db
+            .insert(spk_hash, TxOutId::new(out_id))
+            .map_err(BlockFileError::SpkDb)?;
+    }
+    Ok(())
+}
+
+/// Classify again the outputs an index from before P2PK, bare multisig, P2A and unknown
+/// witness outputs were told apart recorded as non-standard. Only their transactions are
+/// read back from the blk files.
+fn reclassify_output_types(
+    indices: &mut DenseIndexSet,
+    store: &BlkFileStore,
+) -> Result<(), BlockFileError> {
+    log::info!("Reclassifying non-standard outputs");
+    let io_err = BlockFileError::Io;
+    let mut out_start = 0;
+    for tx in 0..indices.txptr.len() {
+        let ptr = indices
+            .txptr
+            .get(TxId::new(tx as u32))
+            .map_err(io_err)?
+            .ok_or(BlockFileError::CorruptId())?;
+        let mut confirmed_tx = None;
+        for out_id in out_start..ptr.tx_out_end() {
+            let output_type = indices
+                .out_type
+                .get(out_id)
+                .map_err(io_err)?
+                .ok_or(BlockFileError::CorruptId())?;
+            if output_type != OutputType::NonStandard {
+                continue;
+            }
+            let confirmed_tx = match &confirmed_tx {
+                Some(confirmed_tx) => confirmed_tx,
+                None => {
+                    let bytes = store
+                        .read_at(ptr.blk_file_no(), ptr.blk_file_off(), ptr.tx_len())
+                        .map_err(io_err)?;
+                    confirmed_tx.insert(ConfirmedTx::new(bytes.into()))
+                }
+            };
+            let output = confirmed_tx
+                .output_at((out_id - out_start) as usize)
+                .ok_or(BlockFileError::CorruptId())?;
+            indices
+                .out_type
+                .set(out_id, output.output_type())
+                .map_err(io_err)?;
+        }
+        out_start = ptr.tx_out_end();
+    }
+    Ok(())
+}
+
+/// For the blocks in `undo_hints`, record the spent outputs of inputs whose prevout is not
+/// indexed, read from the blocks' undo records. Blocks whose inputs all resolved are skipped
+/// without touching their rev file.
+fn record_undo_prevouts(
+    indices: &DenseIndexSet,
+    spent_output_db: &mut SledSpentOutputDb,
+    store: &BlkFileStore,
+    undo_hints: &[UndoHint],
+    block_height_offset: u64,
+) -> Result<(), BlockFileError> {
+    let io_err = BlockFileError::Io;
+    let tx_in_end = |tx: u64| -> Result<u64, BlockFileError> {
+        let ptr = indices
+            .txptr
+            .get(TxId::new(tx as u32))
+            .map_err(io_err)?
+            .ok_or(BlockFileError::CorruptId())?;
+        Ok(ptr.tx_in_end())
+    };
+    let is_unresolved = |in_id: u64| -> Result<bool, BlockFileError> {
+        let prevout = indices
+            .in_prevout
+            .get(in_id)
+            .map_err(io_err)?
+            .ok_or(BlockFileError::CorruptId())?;
+        Ok(prevout == OUTID_NONE)
+    };
+
+    for hint in undo_hints {
+        let Some(block) = hint.height.checked_sub(block_height_offset) else {
+            continue;
+        };
+        if block >= indices.block_count() {
+            continue;
+        }
+        let (tx_start, _, _) = indices.boundaries_after(block).map_err(io_err)?;
+        let (tx_end, in_end, _) = indices.boundaries_after(block + 1).map_err(io_err)?;
+        if tx_start == tx_end {
+            continue;
+        }
+        // The coinbase has no undo entry, and its input never resolves.
+        let spends_start = tx_in_end(tx_start)?;
+        let mut any_unresolved = false;
+        for in_id in spends_start..in_end {
+            if is_unresolved(in_id)? {
+                any_unresolved = true;
+                break;
+            }
+        }
+        if !any_unresolved {
+            continue;
+        }
+
+        let raw = store
+            .read_undo(hint.file_no, hint.undo_pos)
+            .map_err(io_err)?;
+        let undo = BlockUndo::decode(&raw).map_err(BlockFileError::BlockIndex)?;
+        if undo.txs.len() as u64 != tx_end - tx_start - 1 {
+            return Err(BlockFileError::CorruptId());
+        }
+        let mut in_id = spends_start;
+        for (tx, tx_undo) in (tx_start + 1..tx_end).zip(&undo.txs) {
+            let tx_in_end = tx_in_end(tx)?;
+            if tx_undo.spent.len() as u64 != tx_in_end - in_id {
+                return Err(BlockFileError::CorruptId());
+            }
+            for spent in &tx_undo.spent {
+                if is_unresolved(in_id)? {
+                    spent_output_db
+                        .insert(TxInId::new(in_id), spent)
+                        .map_err(BlockFileError::SpentOutputDb)?;
+                }
+                in_id += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn build_indices(builder: DenseStorageBuilder) -> Result<DenseStorage, SyncError> {
     let datadir = builder.data_dir;
     let blocks_dir = datadir.join("blocks");
     log::debug!("blocks_dir: {}", blocks_dir.display());
     let index_dir = builder.index_dir;
     log::debug!("index_dir: {}", index_dir.display());
-    let mut spk_db = SledDBFactory::open(index_dir.join("spk_db"))
-        .map_err(SyncError::Sled)?
-        .spk_db()
-        .map_err(SyncError::Sled)?;
+    let sled_db = SledDBFactory::open(index_dir.join("spk_db")).map_err(SyncError::Sled)?;
+    let mut spk_db = sled_db.spk_db().map_err(SyncError::Sled)?;
+    let mut txid_db = sled_db.txid_db().map_err(SyncError::Sled)?;
+    let mut spent_output_db = sled_db.spent_output_db().map_err(SyncError::Sled)?;
     log::debug!("spk_db: {}", index_dir.join("spk_db").display());
 
-    let block_height_offset = builder.range.start;
-    let mut parser = Parser::new(blocks_dir).with_file_hints(builder.file_hints);
-    let mut indices =
-        DenseIndexSet::new(&index_dir).map_err(|e| SyncError::Parse(BlockFileError::Io(e)))?;
-    let mut sink = DenseIndexSink::new(&mut indices, &mut spk_db).map_err(SyncError::Parse)?;
+    let io_err = |e| SyncError::Parse(BlockFileError::Io(e));
+    let meta = match DenseIndexMeta::read(&index_dir).map_err(io_err)? {
+        Some(meta) => match (meta.network, builder.network) {
+            (Some(indexed), Some(requested)) if indexed != requested => {
+                return Err(SyncError::NetworkMismatch { indexed, requested });
+            }
+            (None, Some(requested)) => {
+                let meta = DenseIndexMeta {
+                    network: Some(requested),
+                    ..meta
+                };
+                meta.write(&index_dir).map_err(io_err)?;
+                meta
+            }
+            _ => meta,
+        },
+        None => {
+            let meta = DenseIndexMeta {
+                start_height: builder.range.start,
+                network: builder.network,
+                legacy_output_types: false,
+            };
+            meta.write(&index_dir).map_err(io_err)?;
+            meta
+        }
+    };
+    let network = meta.network;
+    let block_height_offset = meta.start_height;
+    let file_hints = builder.file_hints;
+    let parser_for = |file_hints| {
+        Parser::new(&blocks_dir)
+            .with_file_hints(file_hints)
+            .with_threads(builder.parse_threads)
+    };
+    let mut indices = DenseIndexSet::new(&index_dir).map_err(io_err)?;
+    if sled_db.has_legacy_spk_tree() {
+        rebuild_spk_db(&indices, &mut spk_db).map_err(SyncError::Parse)?;
+        sled_db.drop_legacy_spk_tree().map_err(SyncError::Sled)?;
+    }
+    if meta.legacy_output_types {
+        reclassify_output_types(&mut indices, &BlkFileStore::open(&blocks_dir))
+            .map_err(SyncError::Parse)?;
+        let meta = DenseIndexMeta {
+            legacy_output_types: false,
+            ..meta
+        };
+        meta.write(&index_dir).map_err(io_err)?;
+    }
+    if let Some(keep_blocks) = builder.keep_blocks {
+        roll_back(
+            &mut indices,
+            &mut spk_db,
+            &mut txid_db,
+            &mut spent_output_db,
+            keep_blocks,
+        )
+        .map_err(SyncError::Parse)?;
+    }
+    let filters_exist = BlockFilterIndex::exists(&index_dir);
+    let mut block_filters = if builder.block_filters || filters_exist {
+        if !filters_exist && indices.block_count() > 0 {
+            return Err(SyncError::MissingBlockFilters {
+                filtered_end: block_height_offset,
+                indexed_end: block_height_offset + indices.block_count(),
+            });
+        }
+        let mut filters = BlockFilterIndex::open_or_create(&index_dir).map_err(io_err)?;
+        filters.truncate(indices.block_count()).map_err(io_err)?;
+        Some(filters)
+    } else {
+        None
+    };
+    // Filters behind the index, e.g. because a sync was interrupted between indexing a block
+    // and filtering it, are caught up after the sync. Otherwise they are built in the same
+    // pass as the index.
+    let filtered_end = block_filters
+        .as_ref()
+        .map(|filters| filters.len())
+        .filter(|&filtered| filtered < indices.block_count());
+
+    let mut parser = parser_for(file_hints.clone());
+    // Whether inputs spending outputs from before the index still need their undo records.
+    let mut undo_pending = false;
+    if !builder.range.is_empty() {
+        let indexed_end = block_height_offset + indices.block_count();
+        if builder.range.start != indexed_end {
+            return Err(SyncError::NonContiguousRange {
+                indexed_end,
+                requested_start: builder.range.start,
+            });
+        }
+        let sink = DenseIndexSink::new(&mut indices, &mut spk_db, &mut txid_db)
+            .map_err(SyncError::Parse)?;
+        match block_filters.as_mut() {
+            Some(filters) if filtered_end.is_none() => {
+                let store = BlkFileStore::open(&blocks_dir);
+                let mut sink = FilteredIndexSink::new(
+                    sink,
+                    filters,
+                    &store,
+                    &mut spent_output_db,
+                    &builder.undo_hints,
+                    builder.range.start,
+                );
+                parser
+                    .parse_blocks(builder.range, &mut sink)
+                    .map_err(SyncError::Parse)?;
+                if sink.incomplete_blocks() > 0 {
+                    log::warn!(
+                        "{} block filters are incomplete because spent scripts were unknown",
+                        sink.incomplete_blocks()
+                    );
+                }
+            }
+            _ => {
+                let mut sink = sink;
+                parser
+                    .parse_blocks(builder.range, &mut sink)
+                    .map_err(SyncError::Parse)?;
+                undo_pending = sink.unresolved_spends() > 0;
+            }
+        }
+    }
+    indices.remap().map_err(io_err)?;
 
-    parser
-        .parse_blocks(builder.range, &mut sink)
+    let store = parser.into_blk_store();
+    if undo_pending {
+        record_undo_prevouts(
+            &indices,
+            &mut spent_output_db,
+            &store,
+            &builder.undo_hints,
+            block_height_offset,
+        )
         .map_err(SyncError::Parse)?;
+    }
 
-    Ok(DenseStorage {
-        store: parser.into_blk_store(),
+    let mut storage = DenseStorage {
+        store,
         block_height_offset,
+        network,
         indices,
         spk_db,
-    })
+        txid_db,
+        spent_output_db,
+        block_filters: None,
+    };
+    if let Some(filters) = block_filters.as_mut() {
+        if let Some(filtered_end) = filtered_end {
+            let mut sink = BlockFilterSink::new(&storage, filters).map_err(SyncError::Parse)?;
+            let range = block_height_offset + filtered_end
+                ..block_height_offset + storage.indices.block_count();
+            parser_for(file_hints)
+                .parse_blocks(range, &mut sink)
+                .map_err(SyncError::Parse)?;
+            if sink.incomplete_blocks() > 0 {
+                log::warn!(
+                    "{} block filters are incomplete because spent scripts were unknown",
+                    sink.incomplete_blocks()
+                );
+            }
+        }
+        filters.remap().map_err(io_err)?;
+    }
+    storage.block_filters = block_filters;
+    Ok(storage)
 }
 
 pub struct DenseStorage {
     store: BlkFileStore,
     block_height_offset: u64,
+    network: Option<bitcoin::Network>,
     indices: DenseIndexSet,
     spk_db: SledScriptPubkeyDb,
+    txid_db: SledTxidDb,
+    spent_output_db: SledSpentOutputDb,
+    block_filters: Option<BlockFilterIndex>,
 }
 
 impl DenseStorage {
+    /// Open the index in `index_dir` for reads only, e.g. to [verify](Self::verify) it.
+    ///
+    /// Unlike [`DenseStorageBuilder::open`], this creates, truncates or upgrades nothing in
+    /// `index_dir`: `meta.bin` is validated but not rewritten, and an index whose script
+    /// pubkey index still has to be rebuilt is rejected.
+    pub fn open_read_only(data_dir: PathBuf, index_dir: PathBuf) -> Result<Self, SyncError> {
+        let io_err = |e| SyncError::Parse(BlockFileError::Io(e));
+        let meta = existing_meta(&index_dir).map_err(io_err)?;
+        let mut indices = DenseIndexSet::open(&index_dir).map_err(io_err)?;
+        indices.remap().map_err(io_err)?;
+        let sled_db = SledDBFactory::open(index_dir.join("spk_db")).map_err(SyncError::Sled)?;
+        if sled_db.has_legacy_spk_tree() {
+            return Err(io_err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the script pubkey index must be rebuilt by a sync first",
+            )));
+        }
+        if meta.legacy_output_types {
+            return Err(io_err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the output types must be reclassified by a sync first",
+            )));
+        }
+        Ok(Self {
+            store: BlkFileStore::open(data_dir.join("blocks")),
+            block_height_offset: meta.start_height,
+            network: meta.network,
+            indices,
+            spk_db: sled_db.spk_db().map_err(SyncError::Sled)?,
+            txid_db: sled_db.txid_db().map_err(SyncError::Sled)?,
+            spent_output_db: sled_db.spent_output_db().map_err(SyncError::Sled)?,
+            block_filters: BlockFilterIndex::open(&index_dir).map_err(io_err)?,
+        })
+    }
+
     pub fn tx_count(&self) -> u64 {
         self.indices.txptr.len()
     }
@@ -218,8 +850,158 @@ impl DenseStorage {
         }
     }
 
+    /// Txid of the transaction at the given dense TxId.
+    pub fn tx_hash(&self, txid: TxId) -> bitcoin::Txid {
+        match self.indices.tx_hash.get(txid) {
+            Ok(Some(bytes)) => bitcoin::Txid::from_byte_array(bytes),
+            Ok(None) => panic!(
+                "Corrupted data store: tx hash not found for txid: {:?}",
+                txid
+            ),
+            Err(e) => panic!("Corrupted data store: error reading tx hash: {:?}", e),
+        }
+    }
+
+    /// Look up the dense TxId of a confirmed transaction by its txid.
+    ///
+    /// For the two historical duplicate coinbase txids this returns the later transaction.
+    pub fn txid_for_hash(&self, txid: &bitcoin::Txid) -> Option<TxId> {
+        self.txid_db
+            .get(&txid.to_byte_array())
+            .unwrap_or_else(|e| panic!("Corrupted data store: error reading txid db: {:?}", e))
+    }
+
+    /// Network the indexed chain belongs to, if one was recorded. See
+    /// [`DenseStorageBuilder::with_network`].
+    pub fn network(&self) -> Option<bitcoin::Network> {
+        self.network
+    }
+
+    /// Heights of the blocks covered by this index.
+    pub fn indexed_heights(&self) -> std::ops::Range<u64> {
+        self.block_height_offset..self.block_height_offset + self.indices.block_count()
+    }
+
+    /// Hash of the indexed block at `height`, or `None` if it is outside [`Self::indexed_heights`].
+    pub fn block_hash(&self, height: u64) -> Option<bitcoin::BlockHash> {
+        let relative = height.checked_sub(self.block_height_offset)?;
+        self.indices
+            .block_hash
+            .get(relative)
+            .unwrap_or_else(|e| panic!("Corrupted data store: error reading block hash: {:?}", e))
+            .map(bitcoin::BlockHash::from_byte_array)
+    }
+
+    /// Header of the indexed block at `height`, or `None` if it is outside [`Self::indexed_heights`].
+    pub fn block_header(&self, height: u64) -> Option<bitcoin::block::Header> {
+        let relative = height.checked_sub(self.block_height_offset)?;
+        let raw = self
+            .indices
+            .block_header
+            .get(relative)
+            .unwrap_or_else(|e| {
+                panic!("Corrupted data store: error reading block header: {:?}", e)
+            })?;
+        Some(
+            bitcoin::consensus::deserialize(&raw)
+                .unwrap_or_else(|e| panic!("Corrupted data store: invalid block header: {:?}", e)),
+        )
+    }
+
+    /// BIP158 basic filter of the indexed block at `height`, or `None` if it is outside
+    /// [`Self::indexed_heights`], the index keeps no filters (see
+    /// [`DenseStorageBuilder::with_block_filters`]) or the block's filter is incomplete
+    /// because some of its spent scripts were unknown.
+    pub fn block_filter(&self, height: u64) -> Option<bitcoin::bip158::BlockFilter> {
+        let relative = height.checked_sub(self.block_height_offset)?;
+        let filters = self.block_filters.as_ref()?;
+        let complete = filters.is_complete(relative).unwrap_or_else(|e| {
+            panic!("Corrupted data store: error reading block filter: {:?}", e)
+        })?;
+        if !complete {
+            return None;
+        }
+        let content = filters.get(relative).unwrap_or_else(|e| {
+            panic!("Corrupted data store: error reading block filter: {:?}", e)
+        })?;
+        Some(bitcoin::bip158::BlockFilter { content })
+    }
+
+    /// Whether the filter of the block at `height` matches any of `scripts`, as it would for a
+    /// light client watching them. Filters have false positives, so a match does not mean the
+    /// block touches the scripts. `None` if there is no filter for `height`.
+    pub fn filter_matches_any<S: AsRef<[u8]>>(&self, height: u64, scripts: &[S]) -> Option<bool> {
+        let filter = self.block_filter(height)?;
+        let block_hash = self.block_hash(height)?;
+        Some(
+            filter
+                .match_any(&block_hash, scripts.iter().map(AsRef::as_ref))
+                .unwrap_or_else(|e| panic!("Corrupted data store: invalid block filter: {:?}", e)),
+        )
+    }
+
+    /// Heights of the filtered blocks whose filter matches any of `scripts`: the blocks a
+    /// light client watching them would download, true and false positives alike. Blocks
+    /// without a complete filter are left out, see [`Self::block_filter`].
+    pub fn heights_matching<S: AsRef<[u8]>>(&self, scripts: &[S]) -> Vec<u64> {
+        if scripts.is_empty() {
+            return Vec::new();
+        }
+        self.indexed_heights()
+            .filter(|&height| self.filter_matches_any(height, scripts) == Some(true))
+            .collect()
+    }
+
+    /// Timestamp from the header of the indexed block at `height`.
+    pub fn block_time(&self, height: u64) -> Option<u32> {
+        self.block_header(height).map(|header| header.time)
+    }
+
+    /// Median of the timestamps of the block at `height` and the ten blocks before it.
+    ///
+    /// Only indexed blocks are considered, so for the first ten indexed heights the median is
+    /// taken over fewer blocks unless indexing started at genesis.
+    pub fn median_time_past(&self, height: u64) -> Option<u32> {
+        if !self.indexed_heights().contains(&height) {
+            return None;
+        }
+        let first = height.saturating_sub(10).max(self.block_height_offset);
+        let mut times: Vec<u32> = (first..=height)
+            .map(|h| self.block_time(h).expect("height is indexed"))
+            .collect();
+        times.sort_unstable();
+        Some(times[times.len() / 2])
+    }
+
+    /// First indexed height whose median-time-past is at or after `time`.
+    ///
+    /// Block timestamps are not monotonic, but median-time-past is, so it can be binary searched.
+    pub fn height_for_time(&self, time: u32) -> Option<u64> {
+        let heights = self.indexed_heights();
+        let mtp = |i: u64| {
+            self.median_time_past(heights.start + i)
+                .expect("height is indexed") as u64
+        };
+        // `upper_bound` finds the first value greater than its target.
+        let target = (time as u64).checked_sub(1);
+        let relative = match target {
+            Some(target) => Self::upper_bound(heights.end - heights.start, target, mtp)?,
+            None if heights.is_empty() => return None,
+            None => 0,
+        };
+        Some(heights.start + relative)
+    }
+
     /// Return the range of TxIds for the given block height.
     pub fn tx_range_for_block(&self, height: u64) -> (u32, u32) {
+        let height = height
+            .checked_sub(self.block_height_offset)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Corrupted data store: block height out of range: {}",
+                    height
+                )
+            });
         let end = self
             .indices
             .block_tx
@@ -402,6 +1184,31 @@ impl DenseStorage {
         }
     }
 
+    /// Return the output spent by the given input, or `None` for a coinbase input.
+    ///
+    /// Prevouts created before the first indexed block come from the undo data read at sync
+    /// time; without it they are `None` as well.
+    pub fn spent_txout(&self, in_id: TxInId) -> Option<bitcoin::TxOut> {
+        if let Some(out_id) = self.prevout_for_in(in_id) {
+            return Some(self.get_txout(out_id));
+        }
+        self.undo_spent_output(in_id).map(|spent| bitcoin::TxOut {
+            value: Amount::from_sat(spent.value),
+            script_pubkey: ScriptBuf::from_bytes(spent.script_pubkey),
+        })
+    }
+
+    /// Return the undo record of the output spent by the given input, if its prevout was
+    /// created before the first indexed block.
+    pub fn undo_spent_output(&self, in_id: TxInId) -> Option<SpentOutput> {
+        self.spent_output_db.get(in_id).unwrap_or_else(|e| {
+            panic!(
+                "Corrupted data store: error reading spent output db: {:?}",
+                e
+            )
+        })
+    }
+
     /// Read a transaction from disk into a buffer.
     fn read_tx(
         &self,
@@ -414,14 +1221,25 @@ impl DenseStorage {
             .map_err(BlockFileError::Io)
     }
 
-    /// Return the transaction at the given dense TxId as a rust-bitcoin Transaction.
-    pub fn get_tx(&self, txid: TxId) -> bitcoin::Transaction {
+    /// Return the raw serialized bytes of the transaction at the given dense TxId.
+    pub fn raw_tx(&self, txid: TxId) -> Vec<u8> {
         let ptr = self.tx_ptr(txid);
         let block_file = BlockFileId(ptr.blk_file_no());
-        let tx_offset = ptr.blk_file_off();
-        let tx_bytes = self
-            .read_tx(block_file, tx_offset, ptr.tx_len())
-            .unwrap_or_else(|e| panic!("Corrupted data store: error reading tx: {:?}", e));
+        self.read_tx(block_file, ptr.blk_file_off(), ptr.tx_len())
+            .unwrap_or_else(|e| panic!("Corrupted data store: error reading tx: {:?}", e))
+    }
+
+    /// Return the transaction at the given dense TxId as a [`ConfirmedTx`].
+    ///
+    /// Only the transaction's own bytes are read from the blk file; fields are decoded
+    /// lazily by the [`AbstractTransaction`] methods.
+    pub fn confirmed_tx(&self, txid: TxId) -> ConfirmedTx {
+        ConfirmedTx::new(self.raw_tx(txid).into())
+    }
+
+    /// Return the transaction at the given dense TxId as a rust-bitcoin Transaction.
+    pub fn get_tx(&self, txid: TxId) -> bitcoin::Transaction {
+        let tx_bytes = self.raw_tx(txid);
         bitcoin::consensus::deserialize::<bitcoin::Transaction>(&tx_bytes).unwrap_or_else(|e| {
             panic!("Corrupted data store: error parsing tx: {:?}", e);
         })
@@ -457,6 +1275,65 @@ impl DenseStorage {
             .clone()
     }
 
+    /// Return the value of the given output, read from the `out_value` column.
+    pub fn output_value(&self, out_id: TxOutId) -> Amount {
+        let value = self
+            .indices
+            .out_value
+            .get(out_id.index())
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Corrupted data store: error reading out_value index: {:?}",
+                    e
+                )
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "Corrupted data store: output id out of range: {}",
+                    out_id.index()
+                )
+            });
+        Amount::from_sat(value)
+    }
+
+    /// Return the script type of the given output, read from the `out_type` column.
+    pub fn output_type(&self, out_id: TxOutId) -> OutputType {
+        self.indices
+            .out_type
+            .get(out_id.index())
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Corrupted data store: error reading out_type index: {:?}",
+                    e
+                )
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "Corrupted data store: output id out of range: {}",
+                    out_id.index()
+                )
+            })
+    }
+
+    /// Return the script pubkey hash of the given output, read from the `out_spk_hash` column.
+    pub fn output_spk_hash(&self, out_id: TxOutId) -> ScriptPubkeyHash {
+        self.indices
+            .out_spk_hash
+            .get(out_id.index())
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Corrupted data store: error reading out_spk_hash index: {:?}",
+                    e
+                )
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "Corrupted data store: output id out of range: {}",
+                    out_id.index()
+                )
+            })
+    }
+
     /// Return all dense TxInIds for the transaction at the given dense TxId.
     pub fn get_txin_ids(&self, txid: TxId) -> impl Iterator<Item = TxInId> {
         let (start, end) = self.tx_in_range(txid);
@@ -478,4 +1355,70 @@ impl DenseStorage {
             .get(script_pubkey)
             .map_err(BlockFileError::SpkDb)
     }
+
+    /// Return every dense TxOutId that uses the given script pubkey hash, in chain order.
+    pub fn script_pubkey_to_txout_ids(
+        &self,
+        script_pubkey: &ScriptPubkeyHash,
+    ) -> Result<Vec<TxOutId>, BlockFileError> {
+        self.spk_db
+            .out_ids(script_pubkey)
+            .map_err(BlockFileError::SpkDb)
+    }
+}
+
+impl TxIndex for DenseStorage {
+    fn tx(&self, txid: &AnyTxId) -> Option<Arc<dyn AbstractTransaction + Send + Sync>> {
+        let did = txid.confirmed_txid()?;
+        if did.index() as u64 >= self.tx_count() {
+            return None;
+        }
+        Some(Arc::new(self.confirmed_tx(did)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn roll_back_restores_the_txids_of_an_earlier_duplicate() {
+        // The same block twice in a row duplicates every txid, like the BIP30 coinbases.
+        let block = bitcoin_test_data::blocks::mainnet_702861();
+        let datadir = temp_dir("dense_roll_back_duplicate");
+        let blocks_dir = datadir.join("blocks");
+        fs::create_dir_all(&blocks_dir).unwrap();
+        let mut blk = Vec::new();
+        for _ in 0..2 {
+            blk.extend_from_slice(&[0xF9, 0xBE, 0xB4, 0xD9]);
+            blk.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            blk.extend_from_slice(block);
+        }
+        fs::write(blocks_dir.join("blk00000.dat"), blk).unwrap();
+        let index_dir = datadir.join("index");
+        fs::create_dir_all(&index_dir).unwrap();
+
+        let storage = DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..2, vec![])
+            .build()
+            .unwrap();
+        let block_txs = storage.tx_count() / 2;
+        let coinbase = storage.tx_hash(TxId::new(0));
+        assert_eq!(
+            storage.txid_for_hash(&coinbase),
+            Some(TxId::new(block_txs as u32))
+        );
+        drop(storage);
+
+        let mut builder = DenseStorageBuilder::new(datadir, index_dir, 0..0, vec![]);
+        builder.keep_blocks = Some(1);
+        let storage = builder.build().unwrap();
+        assert_eq!(storage.tx_count(), block_txs);
+        for i in 0..block_txs as u32 {
+            let txid = TxId::new(i);
+            assert_eq!(storage.txid_for_hash(&storage.tx_hash(txid)), Some(txid));
+        }
+    }
 }
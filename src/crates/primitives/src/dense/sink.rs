@@ -1,17 +1,19 @@
-use std::collections::HashMap;
-
 use bitcoin::hashes::hash160::Hash as Hash160;
 use bitcoin_slices::bitcoin_hashes::Hash;
 
 use crate::{
-    ScriptPubkeyHash,
-    dense::{TxId, TxOutId},
+    ScriptPubkeyHash, classify_script_pubkey,
+    dense::{TxId, TxInId, TxOutId},
     indices::{ConfirmedTxPtrIndex, DenseIndexSet, INID_NONE, OUTID_NONE, TxPtr},
     parser::BlockFileError,
-    sled::spk_db::SledScriptPubkeyDb,
-    traits::{IndexSink, ScriptPubkeyDb},
+    sled::{spk_db::SledScriptPubkeyDb, txid_db::SledTxidDb},
+    traits::{IndexSink, ScriptPubkeyDb, TxidDb},
 };
 
+/// Blocks between remaps of the index files, so prevout lookups during sync mostly hit the
+/// memory-mapped prefix.
+const REMAP_INTERVAL_BLOCKS: u64 = 1000;
+
 /// [`IndexSink`] implementation that writes into the dense binary index files.
 ///
 /// Moves all dense-specific state out of the parser visitor so the parser
@@ -19,19 +21,24 @@ use crate::{
 pub struct DenseIndexSink<'a> {
     pub(crate) indices: &'a mut DenseIndexSet,
     spk_db: &'a mut SledScriptPubkeyDb,
-    // TODO: unbounded — grows to hold every txid seen. Needs a cap / eviction strategy for mainnet.
-    txids: HashMap<[u8; 32], TxId>,
+    txid_db: &'a mut SledTxidDb,
     tx_in_total: u64,
     tx_out_total: u64,
     tx_total: u64,
     current_in_count: u64,
     current_out_count: u64,
+    blocks_since_remap: u64,
+    /// Dense id of the input last passed to `on_input`, if its prevout is not indexed.
+    last_unresolved: Option<TxInId>,
+    /// Inputs spending outputs that are not indexed, across the whole sync.
+    unresolved_spends: u64,
 }
 
 impl<'a> DenseIndexSink<'a> {
     pub fn new(
         indices: &'a mut DenseIndexSet,
         spk_db: &'a mut SledScriptPubkeyDb,
+        txid_db: &'a mut SledTxidDb,
     ) -> Result<Self, BlockFileError> {
         let (tx_in_total, tx_out_total) = tx_io_totals(&indices.txptr);
         let tx_total = indices
@@ -39,17 +46,32 @@ impl<'a> DenseIndexSink<'a> {
             .last()
             .map_err(BlockFileError::Io)?
             .unwrap_or(0) as u64;
+        indices.remap().map_err(BlockFileError::Io)?;
         Ok(Self {
             indices,
             spk_db,
-            txids: HashMap::new(),
+            txid_db,
             tx_in_total,
             tx_out_total,
             tx_total,
             current_in_count: 0,
             current_out_count: 0,
+            blocks_since_remap: 0,
+            last_unresolved: None,
+            unresolved_spends: 0,
         })
     }
+
+    /// Dense id of the input last passed to [`IndexSink::on_input`], if it spends an output
+    /// the index does not have. `None` for coinbase inputs.
+    pub(crate) fn last_unresolved(&self) -> Option<TxInId> {
+        self.last_unresolved
+    }
+
+    /// Number of inputs fed so far that spend an output the index does not have.
+    pub(crate) fn unresolved_spends(&self) -> u64 {
+        self.unresolved_spends
+    }
 }
 
 impl IndexSink for DenseIndexSink<'_> {
@@ -64,7 +86,11 @@ impl IndexSink for DenseIndexSink<'_> {
         let in_id = self.tx_in_total + vin as u64;
         let out_id = if is_null_prevout(prev_txid, prev_vout) {
             OUTID_NONE
-        } else if let Some(prev_dense) = self.txids.get(prev_txid).copied() {
+        } else if let Some(prev_dense) = self
+            .txid_db
+            .get(prev_txid)
+            .map_err(BlockFileError::TxidDb)?
+        {
             let (start, end) = tx_out_range_for(prev_dense, &self.indices.txptr);
             let candidate = start + prev_vout as u64;
             if candidate < end {
@@ -79,6 +105,11 @@ impl IndexSink for DenseIndexSink<'_> {
             .in_prevout
             .append(out_id)
             .map_err(BlockFileError::Io)?;
+        self.last_unresolved = None;
+        if out_id == OUTID_NONE && !is_null_prevout(prev_txid, prev_vout) {
+            self.last_unresolved = Some(TxInId::new(in_id));
+            self.unresolved_spends += 1;
+        }
         if out_id != OUTID_NONE {
             self.indices
                 .out_spent
@@ -89,7 +120,12 @@ impl IndexSink for DenseIndexSink<'_> {
         Ok(())
     }
 
-    fn on_output(&mut self, vout: usize, script_pubkey: &[u8]) -> Result<(), BlockFileError> {
+    fn on_output(
+        &mut self,
+        vout: usize,
+        value: u64,
+        script_pubkey: &[u8],
+    ) -> Result<(), BlockFileError> {
         let out_id = self.tx_out_total + vout as u64;
         self.indices
             .out_spent
@@ -99,8 +135,20 @@ impl IndexSink for DenseIndexSink<'_> {
             return Err(BlockFileError::CorruptId());
         }
         let spk_hash = script_pubkey_hash(script_pubkey);
+        self.indices
+            .out_value
+            .append(value)
+            .map_err(BlockFileError::Io)?;
+        self.indices
+            .out_type
+            .append(classify_script_pubkey(script_pubkey))
+            .map_err(BlockFileError::Io)?;
+        self.indices
+            .out_spk_hash
+            .append(&spk_hash)
+            .map_err(BlockFileError::Io)?;
         self.spk_db
-            .insert_if_absent(spk_hash, TxOutId::new(out_id))
+            .insert(spk_hash, TxOutId::new(out_id))
             .map_err(BlockFileError::SpkDb)?;
         self.current_out_count += 1;
         Ok(())
@@ -124,13 +172,29 @@ impl IndexSink for DenseIndexSink<'_> {
             self.tx_out_total,
         );
         let dense_txid = self.indices.txptr.append(ptr).map_err(BlockFileError::Io)?;
-        self.txids.insert(*txid, dense_txid);
+        if self
+            .indices
+            .tx_hash
+            .append(txid)
+            .map_err(BlockFileError::Io)?
+            != dense_txid
+        {
+            return Err(BlockFileError::CorruptId());
+        }
+        self.txid_db
+            .insert(*txid, dense_txid)
+            .map_err(BlockFileError::TxidDb)?;
         self.current_in_count = 0;
         self.current_out_count = 0;
         Ok(())
     }
 
-    fn on_block_end(&mut self, block_tx_count: u64) -> Result<(), BlockFileError> {
+    fn on_block_end(
+        &mut self,
+        header: &[u8; 80],
+        block_hash: &[u8; 32],
+        block_tx_count: u64,
+    ) -> Result<(), BlockFileError> {
         self.tx_total += block_tx_count;
         if self.tx_total > u32::MAX as u64 {
             return Err(BlockFileError::CorruptId());
@@ -139,6 +203,19 @@ impl IndexSink for DenseIndexSink<'_> {
             .block_tx
             .append(self.tx_total as u32)
             .map_err(BlockFileError::Io)?;
+        self.indices
+            .block_hash
+            .append(block_hash)
+            .map_err(BlockFileError::Io)?;
+        self.indices
+            .block_header
+            .append(header)
+            .map_err(BlockFileError::Io)?;
+        self.blocks_since_remap += 1;
+        if self.blocks_since_remap == REMAP_INTERVAL_BLOCKS {
+            self.indices.remap().map_err(BlockFileError::Io)?;
+            self.blocks_since_remap = 0;
+        }
         Ok(())
     }
 }
@@ -187,6 +264,6 @@ fn is_null_prevout(prev_txid: &[u8; 32], prev_vout: u32) -> bool {
     prev_vout == u32::MAX && prev_txid.iter().all(|b| *b == 0)
 }
 
-fn script_pubkey_hash(script_pubkey: &[u8]) -> ScriptPubkeyHash {
+pub(crate) fn script_pubkey_hash(script_pubkey: &[u8]) -> ScriptPubkeyHash {
     Hash160::hash(script_pubkey).to_byte_array()
 }
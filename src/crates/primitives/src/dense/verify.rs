@@ -0,0 +1,424 @@
+use std::fmt;
+
+use bitcoin::hashes::{Hash, sha256d};
+
+use crate::{
+    ScriptPubkeyHash, classify_script_pubkey,
+    dense::{BlockFileId, DenseStorage, TxId, TxInId, TxOutId, sink::script_pubkey_hash},
+    indices::{INID_NONE, OUTID_NONE},
+    parser::BlockFileError,
+    traits::TxidDb,
+};
+
+/// Issues recorded in a [`VerifyReport`] beyond this many are only counted.
+const MAX_RECORDED_ISSUES: usize = 1_000;
+
+/// An inconsistency found by [`DenseStorage::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// Two index files that must have one entry per block, transaction or io disagree.
+    LengthMismatch {
+        index: &'static str,
+        expected: u64,
+        actual: u64,
+    },
+    /// An end offset that does not increase from one entry to the next.
+    NotIncreasing {
+        index: &'static str,
+        position: u64,
+        previous: u64,
+        value: u64,
+    },
+    /// The stored block hash is not the hash of the stored header.
+    BlockHashMismatch { height: u64 },
+    /// `in_prevout` points past the last output.
+    PrevoutOutOfRange { in_id: TxInId, out_id: u64 },
+    /// `in_prevout` points to an output whose `out_spent` does not point back.
+    PrevoutNotLinked {
+        in_id: TxInId,
+        out_id: TxOutId,
+        spent_by: Option<TxInId>,
+    },
+    /// `out_spent` points past the last input.
+    SpenderOutOfRange { out_id: TxOutId, in_id: u64 },
+    /// `out_spent` points to an input whose `in_prevout` does not point back.
+    SpenderNotLinked {
+        out_id: TxOutId,
+        in_id: TxInId,
+        prevout: Option<TxOutId>,
+    },
+    /// The bytes a [`TxPtr`](crate::indices::TxPtr) points to could not be read or parsed.
+    TxUnreadable { txid: TxId, error: String },
+    /// The transaction read from the blk file does not hash to the stored txid.
+    TxHashMismatch { txid: TxId },
+    /// The transaction read from the blk file has a different number of inputs or outputs
+    /// than its id ranges.
+    TxShapeMismatch { txid: TxId },
+    /// An `out_*` column disagrees with the output read from the blk file.
+    OutputMismatch {
+        out_id: TxOutId,
+        column: &'static str,
+    },
+    /// The txid db maps the transaction's txid elsewhere.
+    TxidDbMismatch { txid: TxId, found: Option<TxId> },
+    /// A script pubkey db entry names an output that does not exist or has another script.
+    SpkEntryMismatch {
+        spk_hash: ScriptPubkeyHash,
+        out_id: TxOutId,
+    },
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::LengthMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(f, "{index}: expected {expected} entries, found {actual}"),
+            VerifyIssue::NotIncreasing {
+                index,
+                position,
+                previous,
+                value,
+            } => write!(
+                f,
+                "{index}: entry {position} is {value}, not above the previous {previous}"
+            ),
+            VerifyIssue::BlockHashMismatch { height } => {
+                write!(f, "block {height}: hash does not match header")
+            }
+            VerifyIssue::PrevoutOutOfRange { in_id, out_id } => {
+                write!(f, "input {}: prevout {out_id} out of range", in_id.index())
+            }
+            VerifyIssue::PrevoutNotLinked {
+                in_id,
+                out_id,
+                spent_by,
+            } => write!(
+                f,
+                "input {}: prevout {} is spent by {:?}",
+                in_id.index(),
+                out_id.index(),
+                spent_by.map(TxInId::index)
+            ),
+            VerifyIssue::SpenderOutOfRange { out_id, in_id } => {
+                write!(f, "output {}: spender {in_id} out of range", out_id.index())
+            }
+            VerifyIssue::SpenderNotLinked {
+                out_id,
+                in_id,
+                prevout,
+            } => write!(
+                f,
+                "output {}: spender {} has prevout {:?}",
+                out_id.index(),
+                in_id.index(),
+                prevout.map(TxOutId::index)
+            ),
+            VerifyIssue::TxUnreadable { txid, error } => {
+                write!(f, "tx {}: unreadable: {error}", txid.index())
+            }
+            VerifyIssue::TxHashMismatch { txid } => {
+                write!(f, "tx {}: bytes do not hash to stored txid", txid.index())
+            }
+            VerifyIssue::TxShapeMismatch { txid } => write!(
+                f,
+                "tx {}: input or output count differs from its id ranges",
+                txid.index()
+            ),
+            VerifyIssue::OutputMismatch { out_id, column } => {
+                write!(
+                    f,
+                    "output {}: {column} differs from blk file",
+                    out_id.index()
+                )
+            }
+            VerifyIssue::TxidDbMismatch { txid, found } => write!(
+                f,
+                "tx {}: txid db maps its txid to {:?}",
+                txid.index(),
+                found.map(TxId::index)
+            ),
+            VerifyIssue::SpkEntryMismatch { spk_hash, out_id } => write!(
+                f,
+                "spk db entry {} -> output {} does not match out_spk_hash",
+                bitcoin::hex::DisplayHex::to_lower_hex_string(&spk_hash[..]),
+                out_id.index()
+            ),
+        }
+    }
+}
+
+/// Result of [`DenseStorage::verify`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub blocks: u64,
+    pub txs: u64,
+    pub inputs: u64,
+    pub outputs: u64,
+    /// Transactions re-read from the blk files and re-hashed.
+    pub sampled_txs: u64,
+    pub spk_entries: u64,
+    /// The first inconsistencies found, in the order they were checked.
+    pub issues: Vec<VerifyIssue>,
+    /// Inconsistencies found after [`Self::issues`] filled up.
+    pub omitted_issues: u64,
+}
+
+impl VerifyReport {
+    /// Whether no inconsistency was found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, issue: VerifyIssue) {
+        if self.issues.len() < MAX_RECORDED_ISSUES {
+            self.issues.push(issue);
+        } else {
+            self.omitted_issues += 1;
+        }
+    }
+
+    fn check_len(&mut self, index: &'static str, expected: u64, actual: u64) {
+        if expected != actual {
+            self.push(VerifyIssue::LengthMismatch {
+                index,
+                expected,
+                actual,
+            });
+        }
+    }
+}
+
+impl DenseStorage {
+    /// Check the index files against each other, the blk files and the sled dbs.
+    ///
+    /// Every block, transaction and io link is checked. If the id ranges are consistent, one
+    /// in `sample_every` transactions (0 for none) is also re-read from its blk file and compared
+    /// with its txid, id ranges and output columns. Inconsistencies are collected into the
+    /// report, so a store the accessors would panic on can still be inspected. Errors are only
+    /// returned when the index files themselves cannot be read.
+    pub fn verify(&self, sample_every: u64) -> Result<VerifyReport, BlockFileError> {
+        let io_err = BlockFileError::Io;
+        let indices = &self.indices;
+        let mut report = VerifyReport {
+            blocks: indices.block_count(),
+            txs: indices.txptr.len(),
+            inputs: indices.in_prevout.len(),
+            outputs: indices.out_spent.len(),
+            ..Default::default()
+        };
+
+        report.check_len("block_hash", report.blocks, indices.block_hash.len());
+        report.check_len("block_header", report.blocks, indices.block_header.len());
+        report.check_len("tx_hash", report.txs, indices.tx_hash.len());
+        report.check_len("out_value", report.outputs, indices.out_value.len());
+        report.check_len("out_type", report.outputs, indices.out_type.len());
+        report.check_len("out_spk_hash", report.outputs, indices.out_spk_hash.len());
+
+        // Every block has a coinbase, and every transaction at least one input and output,
+        // so all end offsets strictly increase.
+        let mut prev_tx_end = 0;
+        for block in 0..report.blocks {
+            let tx_end = indices.block_tx.get(block).map_err(io_err)?.unwrap_or(0) as u64;
+            if tx_end <= prev_tx_end {
+                report.push(VerifyIssue::NotIncreasing {
+                    index: "block_tx",
+                    position: block,
+                    previous: prev_tx_end,
+                    value: tx_end,
+                });
+            }
+            prev_tx_end = tx_end;
+        }
+        report.check_len("block_tx end", report.txs, prev_tx_end);
+
+        let (mut prev_in_end, mut prev_out_end) = (0, 0);
+        for tx in 0..report.txs {
+            let Some(ptr) = indices.txptr.get(TxId::new(tx as u32)).map_err(io_err)? else {
+                break;
+            };
+            for (index, previous, value) in [
+                ("txptr tx_in_end", prev_in_end, ptr.tx_in_end()),
+                ("txptr tx_out_end", prev_out_end, ptr.tx_out_end()),
+            ] {
+                if value <= previous {
+                    report.push(VerifyIssue::NotIncreasing {
+                        index,
+                        position: tx,
+                        previous,
+                        value,
+                    });
+                }
+            }
+            prev_in_end = ptr.tx_in_end();
+            prev_out_end = ptr.tx_out_end();
+        }
+        report.check_len("txptr tx_in_end", report.inputs, prev_in_end);
+        report.check_len("txptr tx_out_end", report.outputs, prev_out_end);
+        // Re-reading transactions relies on the id ranges checked so far.
+        let offsets_ok = report.is_ok();
+
+        for block in 0..report.blocks {
+            let header = indices.block_header.get(block).map_err(io_err)?;
+            let hash = indices.block_hash.get(block).map_err(io_err)?;
+            if let (Some(header), Some(hash)) = (header, hash)
+                && sha256d::Hash::hash(&header).to_byte_array() != hash
+            {
+                report.push(VerifyIssue::BlockHashMismatch {
+                    height: self.block_height_offset + block,
+                });
+            }
+        }
+
+        for in_id in 0..report.inputs {
+            let out_id = indices
+                .in_prevout
+                .get(in_id)
+                .map_err(io_err)?
+                .unwrap_or(OUTID_NONE);
+            if out_id == OUTID_NONE {
+                continue;
+            }
+            let in_id = TxInId::new(in_id);
+            match indices.out_spent.get(out_id).map_err(io_err)? {
+                None => report.push(VerifyIssue::PrevoutOutOfRange { in_id, out_id }),
+                Some(spent_by) if spent_by != in_id.index() => {
+                    report.push(VerifyIssue::PrevoutNotLinked {
+                        in_id,
+                        out_id: TxOutId::new(out_id),
+                        spent_by: (spent_by != INID_NONE).then_some(TxInId::new(spent_by)),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for out_id in 0..report.outputs {
+            let in_id = indices
+                .out_spent
+                .get(out_id)
+                .map_err(io_err)?
+                .unwrap_or(INID_NONE);
+            if in_id == INID_NONE {
+                continue;
+            }
+            let out_id = TxOutId::new(out_id);
+            match indices.in_prevout.get(in_id).map_err(io_err)? {
+                None => report.push(VerifyIssue::SpenderOutOfRange { out_id, in_id }),
+                Some(prevout) if prevout != out_id.index() => {
+                    report.push(VerifyIssue::SpenderNotLinked {
+                        out_id,
+                        in_id: TxInId::new(in_id),
+                        prevout: (prevout != OUTID_NONE).then_some(TxOutId::new(prevout)),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        if sample_every > 0 && offsets_ok {
+            for tx in (0..report.txs).step_by(sample_every as usize) {
+                self.verify_tx(TxId::new(tx as u32), &mut report)?;
+                report.sampled_txs += 1;
+            }
+        }
+
+        self.verify_spk_db(&mut report)?;
+        Ok(report)
+    }
+
+    /// Re-read one transaction from its blk file and compare it with the indexes.
+    fn verify_tx(&self, txid: TxId, report: &mut VerifyReport) -> Result<(), BlockFileError> {
+        let io_err = BlockFileError::Io;
+        let ptr = self.tx_ptr(txid);
+        let tx = self
+            .read_tx(
+                BlockFileId(ptr.blk_file_no()),
+                ptr.blk_file_off(),
+                ptr.tx_len(),
+            )
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                bitcoin::consensus::deserialize::<bitcoin::Transaction>(&bytes)
+                    .map_err(|e| e.to_string())
+            });
+        let tx = match tx {
+            Ok(tx) => tx,
+            Err(error) => {
+                report.push(VerifyIssue::TxUnreadable { txid, error });
+                return Ok(());
+            }
+        };
+
+        let hash = tx.compute_txid().to_byte_array();
+        if self.indices.tx_hash.get(txid).map_err(io_err)? != Some(hash) {
+            report.push(VerifyIssue::TxHashMismatch { txid });
+        }
+        // The two duplicated coinbase txids map to their later transaction.
+        let found = self.txid_db.get(&hash).map_err(BlockFileError::TxidDb)?;
+        if found != Some(txid)
+            && found
+                .is_none_or(|other| self.indices.tx_hash.get(other).ok().flatten() != Some(hash))
+        {
+            report.push(VerifyIssue::TxidDbMismatch { txid, found });
+        }
+
+        let (in_start, in_end) = self.tx_in_range(txid);
+        let (out_start, out_end) = self.tx_out_range(txid);
+        if in_end - in_start != tx.input.len() as u64
+            || out_end - out_start != tx.output.len() as u64
+        {
+            report.push(VerifyIssue::TxShapeMismatch { txid });
+            return Ok(());
+        }
+        for (out_id, output) in (out_start..out_end).zip(&tx.output) {
+            let script = output.script_pubkey.as_bytes();
+            let checks = [
+                (
+                    "out_value",
+                    self.indices.out_value.get(out_id).map_err(io_err)?
+                        == Some(output.value.to_sat()),
+                ),
+                (
+                    "out_type",
+                    self.indices.out_type.get(out_id).map_err(io_err)?
+                        == Some(classify_script_pubkey(script)),
+                ),
+                (
+                    "out_spk_hash",
+                    self.indices.out_spk_hash.get(out_id).map_err(io_err)?
+                        == Some(script_pubkey_hash(script)),
+                ),
+            ];
+            for (column, matches) in checks {
+                if !matches {
+                    report.push(VerifyIssue::OutputMismatch {
+                        out_id: TxOutId::new(out_id),
+                        column,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the script pubkey db holds exactly one entry per output, under the hash in
+    /// `out_spk_hash`.
+    fn verify_spk_db(&self, report: &mut VerifyReport) -> Result<(), BlockFileError> {
+        for entry in self.spk_db.entries() {
+            let (spk_hash, out_id) = entry.map_err(BlockFileError::SpkDb)?;
+            report.spk_entries += 1;
+            let stored = self
+                .indices
+                .out_spk_hash
+                .get(out_id.index())
+                .map_err(BlockFileError::Io)?;
+            if stored != Some(spk_hash) {
+                report.push(VerifyIssue::SpkEntryMismatch { spk_hash, out_id });
+            }
+        }
+        report.check_len("spk_db", report.outputs, report.spk_entries);
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    AnyInId, AnyOutId, AnyTxId, HasValue, HasVersion, HasWitness, OutputType,
+    AnyInId, AnyOutId, AnyTxId, HasValue, HasVersion, HasWitness, InputSpend, OutputType,
     traits::{
         abstract_types::{
             AbstractTransaction, AbstractTxIn, AbstractTxOut, EnumerateInputValueInArbitraryOrder,
@@ -57,6 +57,80 @@ impl<'a> TxHandle<'a> {
     pub fn block_height(&self) -> Option<u64> {
         self.index.block_height(&self.tx_id)
     }
+
+    pub fn block_time(&self) -> Option<u32> {
+        self.index.block_time(&self.tx_id)
+    }
+
+    pub fn block_hash(&self) -> Option<bitcoin::BlockHash> {
+        self.index.block_hash(&self.tx_id)
+    }
+
+    /// Median-time-past of the block containing the transaction, the time it is final at
+    /// under BIP113.
+    pub fn median_time_past(&self) -> Option<u32> {
+        self.index.median_time_past(&self.tx_id)
+    }
+
+    /// Segwit-aware weight, measured on the raw transaction bytes where the index has them.
+    pub fn weight(&self) -> bitcoin::Weight {
+        match self.index.tx(&self.tx_id) {
+            Some(tx) => tx.weight(),
+            None => crate::traits::abstract_types::serialized_weight(self),
+        }
+    }
+
+    /// Virtual size in vbytes, rounded up.
+    pub fn vsize(&self) -> u64 {
+        self.weight().to_vbytes_ceil()
+    }
+
+    /// Input value minus output value. `None` for coinbases, when any spent output's value is
+    /// unknown, or when the outputs claim more than the inputs provide.
+    pub fn fee(&self) -> Option<bitcoin::Amount> {
+        if self.is_coinbase() {
+            return None;
+        }
+        let mut input_sum = bitcoin::Amount::ZERO;
+        for input in self.inputs() {
+            let value = match input.prev_txout_id() {
+                Some(out_id) => out_id.with(self.index).value(),
+                None => input.spent_txout()?.value,
+            };
+            input_sum = input_sum.checked_add(value)?;
+        }
+        let output_sum = self
+            .outputs()
+            .try_fold(bitcoin::Amount::ZERO, |sum, output| {
+                sum.checked_add(output.value())
+            })?;
+        input_sum.checked_sub(output_sum)
+    }
+
+    /// Coin-days destroyed: the sum over inputs of the spent value in BTC times its age in days,
+    /// measured between block timestamps. A coinbase destroys none. `None` when any input's
+    /// age in seconds is unknown (see [`TxInHandle::coin_age`]).
+    pub fn coin_days_destroyed(&self) -> Option<f64> {
+        if self.is_coinbase() {
+            return Some(0.0);
+        }
+        self.inputs()
+            .map(|input| {
+                let seconds = input.coin_age()?.seconds?;
+                let value = input.prev_txout()?.value();
+                Some(value.to_btc() * seconds as f64 / 86_400.0)
+            })
+            .sum()
+    }
+
+    /// Fee per weight unit, rounded down. `None` whenever [`Self::fee`] is.
+    pub fn feerate(&self) -> Option<bitcoin::FeeRate> {
+        let fee = self.fee()?;
+        let weight = self.weight().to_wu().max(1);
+        Some(bitcoin::FeeRate::from_sat_per_kwu(
+            fee.to_sat().saturating_mul(1000) / weight,
+        ))
+    }
 }
 
 /// Handle for a transaction output in a unified index.
@@ -106,6 +180,19 @@ impl<'a> TxOutHandle<'a> {
             })
     }
 
+    /// Every output paying the same script pubkey as this one, including itself, in chain order.
+    pub fn outputs_with_same_spk(&self) -> Vec<TxOutHandle<'a>> {
+        let spk_hash = self.script_pubkey_hash();
+        self.index
+            .outputs_for_script_pubkey(&spk_hash)
+            .into_iter()
+            .map(|out_id| TxOutHandle {
+                out_id,
+                index: self.index,
+            })
+            .collect()
+    }
+
     pub fn value(&self) -> bitcoin::Amount {
         self.index.value(&self.out_id)
     }
@@ -113,12 +200,27 @@ impl<'a> TxOutHandle<'a> {
     pub fn script_pubkey_hash(&self) -> crate::ScriptPubkeyHash {
         self.index.script_pubkey_hash(&self.out_id)
     }
+
+    /// The address this output pays, on the index's network. `None` for scripts without an
+    /// address form, such as `OP_RETURN` or bare multisig.
+    pub fn address(&self) -> Option<bitcoin::Address> {
+        let script = bitcoin::ScriptBuf::from_bytes(self.index.script_pubkey_bytes(&self.out_id));
+        bitcoin::Address::from_script(&script, self.index.network()).ok()
+    }
 }
 
 impl<'a> HasScriptPubkey for TxOutHandle<'a> {
     fn script_pubkey_bytes(&self) -> Vec<u8> {
         self.index.script_pubkey_bytes(&self.out_id)
     }
+
+    fn script_pubkey_hash(&self) -> crate::ScriptPubkeyHash {
+        self.index.script_pubkey_hash(&self.out_id)
+    }
+
+    fn output_type(&self) -> OutputType {
+        self.index.output_type(&self.out_id)
+    }
 }
 
 impl<'a> HasValue for TxOutHandle<'a> {
@@ -127,6 +229,17 @@ impl<'a> HasValue for TxOutHandle<'a> {
     }
 }
 
+/// Age of a coin when it was spent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoinAge {
+    /// Blocks between the block that created the coin and the one that spent it.
+    pub blocks: u64,
+    /// Seconds between those blocks' timestamps, when their headers are indexed. Block
+    /// timestamps are not monotonic, so a spend timestamped before its funding block counts
+    /// as zero.
+    pub seconds: Option<u64>,
+}
+
 /// Handle for a transaction input in a unified index.
 pub struct TxInHandle<'a> {
     pub(crate) in_id: AnyInId,
@@ -160,8 +273,52 @@ impl<'a> TxInHandle<'a> {
         })
     }
 
+    /// Type of the output this input spends, with P2SH-wrapped segwit told apart from other
+    /// P2SH by this input's scriptSig. See [`crate::classify_spent_output`].
     pub fn output_type(&self) -> Option<OutputType> {
-        self.prev_txout().map(|prevout| prevout.output_type())
+        self.prev_txout().map(|prevout| {
+            crate::classify_spent_output(&prevout.script_pubkey_bytes(), &self.script_sig_bytes())
+        })
+    }
+
+    /// How long the spent coin sat in the UTXO set before this input spent it. `None` unless
+    /// this input is confirmed and the spent output is either confirmed within the indexed
+    /// range or created before it and known from undo data. For the latter only the height
+    /// is recorded, so the age in seconds is unknown.
+    pub fn coin_age(&self) -> Option<CoinAge> {
+        let spending = self.containing_tx();
+        let spent_at = spending.block_height()?;
+        let (funded_at, funded_time) = match self.prev_txout() {
+            Some(prevout) => {
+                let funding = prevout.containing_tx();
+                (funding.block_height()?, funding.block_time())
+            }
+            None => (self.index.undo_spent_height(&self.in_id)?, None),
+        };
+        let seconds = funded_time
+            .zip(spending.block_time())
+            .map(|(funded, spent)| spent.saturating_sub(funded) as u64);
+        Some(CoinAge {
+            blocks: spent_at.saturating_sub(funded_at),
+            seconds,
+        })
+    }
+
+    /// How this input unlocks the output it spends, see [`crate::decode_input_spend`]. `None`
+    /// if the script of the spent output is unknown.
+    pub fn spend(&self) -> Option<InputSpend> {
+        let prevout = self.spent_txout()?;
+        Some(crate::decode_input_spend(
+            prevout.script_pubkey.as_bytes(),
+            &self.script_sig_bytes(),
+            &self.witness_items(),
+        ))
+    }
+
+    /// The output this input spends, also when it lies outside the indexed range but its
+    /// value and script are known (see [`crate::dense::DenseStorage::spent_txout`]).
+    pub fn spent_txout(&self) -> Option<bitcoin::TxOut> {
+        self.index.spent_txout(&self.in_id)
     }
 }
 
@@ -246,10 +403,18 @@ impl<'a> AbstractTransaction for TxHandle<'a> {
         })
     }
 
+    fn weight(&self) -> bitcoin::Weight {
+        TxHandle::weight(self)
+    }
+
     fn is_coinbase(&self) -> bool {
         let mut inputs = self.inputs();
         if let Some(first_input) = inputs.next() {
-            return first_input.prev_txout_id().is_none();
+            // Inputs spending coins from before the first indexed block have no prevout
+            // either, so check for the null outpoint.
+            return first_input.prev_txout_id().is_none()
+                && first_input.prev_outpoint_vout() == u32::MAX
+                && first_input.prev_outpoint_txid_bytes() == [0; 32];
         }
 
         false
@@ -274,11 +439,12 @@ impl<'a> AbstractTxOut for TxOutHandle<'a> {}
 
 impl<'a> EnumerateInputValueInArbitraryOrder for TxHandle<'a> {
     fn input_values(&self) -> impl Iterator<Item = bitcoin::Amount> {
-        self.inputs().filter_map(|input| {
-            input
-                .prev_txout_id()
-                .map(|out_id| out_id.with(self.index).value())
-        })
+        // Indexed prevouts read the value column; only the rest fall back to undo data.
+        self.inputs()
+            .filter_map(|input| match input.prev_txout_id() {
+                Some(out_id) => Some(out_id.with(self.index).value()),
+                None => input.spent_txout().map(|txout| txout.value),
+            })
     }
 }
 
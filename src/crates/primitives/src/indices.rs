@@ -3,11 +3,21 @@ use std::io::{self, Seek, SeekFrom, Write};
 use std::os::unix::fs::FileExt;
 use std::path::Path;
 
-use crate::dense::TxId;
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{OutputType, ScriptPubkeyHash, dense::TxId};
 
 const TXPTR_LEN_BYTES: usize = 28;
 const BLOCK_TX_END_LEN_BYTES: usize = 4;
 const LINK_LEN_BYTES: usize = 8;
+const OUT_VALUE_LEN_BYTES: usize = 8;
+const OUT_TYPE_LEN_BYTES: usize = 1;
+const SPK_HASH_LEN_BYTES: usize = 20;
+const BLOCK_HASH_LEN_BYTES: usize = 32;
+const TX_HASH_LEN_BYTES: usize = 32;
+const BLOCK_HEADER_LEN_BYTES: usize = 80;
+const FILTER_END_LEN_BYTES: usize = 8;
+const FILTER_COMPLETE_LEN_BYTES: usize = 1;
 
 pub const OUTID_NONE: u64 = u64::MAX;
 pub const INID_NONE: u64 = u64::MAX;
@@ -90,6 +100,10 @@ impl TxPtr {
 struct FixedWidthIndex<const N: usize> {
     file: File,
     len: u64,
+    /// Read-only mapping of the first `mapped_len` entries. Entries appended after the
+    /// mapping was taken are read with a syscall until [`FixedWidthIndex::remap`] is called.
+    map: Option<Mmap>,
+    mapped_len: u64,
 }
 
 impl<const N: usize> FixedWidthIndex<N> {
@@ -100,17 +114,27 @@ impl<const N: usize> FixedWidthIndex<N> {
             .create(true)
             .truncate(true)
             .open(path)?;
-        Ok(Self { file, len: 0 })
+        Ok(Self::from_file(file, 0))
+    }
+
+    fn from_file(file: File, len: u64) -> Self {
+        Self {
+            file,
+            len,
+            map: None,
+            mapped_len: 0,
+        }
     }
 
+    /// Open an existing file for reads only.
     fn open(path: impl AsRef<Path>, len_error: &'static str) -> io::Result<Self> {
-        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file = OpenOptions::new().read(true).open(path)?;
         let len_bytes = file.metadata()?.len();
         if len_bytes % (N as u64) != 0 {
             return Err(io::Error::new(io::ErrorKind::InvalidData, len_error));
         }
         let len = len_bytes / (N as u64);
-        Ok(Self { file, len })
+        Ok(Self::from_file(file, len))
     }
 
     /// Open an existing file or create a new one without truncating existing content.
@@ -125,10 +149,7 @@ impl<const N: usize> FixedWidthIndex<N> {
         if len_bytes % (N as u64) != 0 {
             return Err(io::Error::new(io::ErrorKind::InvalidData, len_error));
         }
-        Ok(Self {
-            file,
-            len: len_bytes / (N as u64),
-        })
+        Ok(Self::from_file(file, len_bytes / (N as u64)))
     }
 
     fn len(&self) -> u64 {
@@ -152,13 +173,53 @@ impl<const N: usize> FixedWidthIndex<N> {
         self.file.write_all(bytes)
     }
 
+    /// Map every entry currently in the file, replacing any previous mapping.
+    fn remap(&mut self) -> io::Result<()> {
+        self.map = None;
+        self.mapped_len = 0;
+        if self.len == 0 {
+            return Ok(());
+        }
+        // SAFETY: index files are only written through `FixedWidthIndex`, which never
+        // shrinks the file below the mapped prefix without dropping the mapping first.
+        // Overwrites through `set_bytes` go through the same page cache the mapping reads.
+        let map = unsafe {
+            MmapOptions::new()
+                .len((self.len as usize) * N)
+                .map(&self.file)?
+        };
+        self.map = Some(map);
+        self.mapped_len = self.len;
+        Ok(())
+    }
+
+    /// Drop every entry at or after `len`.
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        if len >= self.len {
+            return Ok(());
+        }
+        if len < self.mapped_len {
+            self.map = None;
+            self.mapped_len = 0;
+        }
+        self.file.set_len(len * N as u64)?;
+        self.len = len;
+        Ok(())
+    }
+
     fn get_bytes(&self, index: u64) -> io::Result<Option<[u8; N]>> {
         if index >= self.len {
             return Ok(None);
         }
         let offset = index * N as u64;
         let mut buf = [0u8; N];
-        self.file.read_exact_at(&mut buf, offset)?;
+        match &self.map {
+            Some(map) if index < self.mapped_len => {
+                let offset = offset as usize;
+                buf.copy_from_slice(&map[offset..offset + N]);
+            }
+            _ => self.file.read_exact_at(&mut buf, offset)?,
+        }
         Ok(Some(buf))
     }
 }
@@ -216,6 +277,10 @@ impl ConfirmedTxPtrIndex {
         self.inner.is_empty()
     }
 
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
     pub fn append(&mut self, ptr: TxPtr) -> io::Result<TxId> {
         if self.inner.len() > u32::MAX as u64 {
             return Err(io::Error::new(
@@ -267,6 +332,10 @@ impl BlockTxIndex {
         self.inner.is_empty()
     }
 
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
     pub fn last(&self) -> io::Result<Option<u32>> {
         let len = self.inner.len();
         if len == 0 {
@@ -317,6 +386,10 @@ impl InPrevoutIndex {
         self.inner.is_empty()
     }
 
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
     pub fn append(&mut self, out_id: u64) -> io::Result<u64> {
         self.inner.append_bytes(&out_id.to_le_bytes())
     }
@@ -359,6 +432,10 @@ impl OutSpentByIndex {
         self.inner.is_empty()
     }
 
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
     pub fn append(&mut self, in_id: u64) -> io::Result<u64> {
         self.inner.append_bytes(&in_id.to_le_bytes())
     }
@@ -372,13 +449,598 @@ impl OutSpentByIndex {
     }
 }
 
-/// All four dense index files grouped under a single directory.
+/// Per-output value in satoshis, indexed by dense TxOutId.
+#[derive(Debug)]
+pub struct OutValueIndex {
+    inner: FixedWidthIndex<OUT_VALUE_LEN_BYTES>,
+}
+
+/// Per-output [`OutputType`], indexed by dense TxOutId.
+#[derive(Debug)]
+pub struct OutTypeIndex {
+    inner: FixedWidthIndex<OUT_TYPE_LEN_BYTES>,
+}
+
+/// Per-output hash160 of the script pubkey, indexed by dense TxOutId.
+#[derive(Debug)]
+pub struct OutSpkHashIndex {
+    inner: FixedWidthIndex<SPK_HASH_LEN_BYTES>,
+}
+
+impl OutValueIndex {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::create(path)?,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open(
+                path,
+                "out_value file length is not a multiple of 8 bytes",
+            )?,
+        })
+    }
+
+    fn open_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open_or_create(
+                path,
+                "out_value file length is not a multiple of 8 bytes",
+            )?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
+    pub fn append(&mut self, value_sat: u64) -> io::Result<u64> {
+        self.inner.append_bytes(&value_sat.to_le_bytes())
+    }
+
+    pub fn get(&self, out_id: u64) -> io::Result<Option<u64>> {
+        Ok(self.inner.get_bytes(out_id)?.map(u64::from_le_bytes))
+    }
+}
+
+impl OutTypeIndex {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::create(path)?,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open(path, "out_type file length is not a multiple of 1 byte")?,
+        })
+    }
+
+    fn open_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open_or_create(
+                path,
+                "out_type file length is not a multiple of 1 byte",
+            )?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
+    pub fn append(&mut self, output_type: OutputType) -> io::Result<u64> {
+        self.inner.append_bytes(&[output_type.storage_code()])
+    }
+
+    pub fn set(&mut self, out_id: u64, output_type: OutputType) -> io::Result<()> {
+        self.inner.set_bytes(out_id, &[output_type.storage_code()])
+    }
+
+    pub fn get(&self, out_id: u64) -> io::Result<Option<OutputType>> {
+        let Some([code]) = self.inner.get_bytes(out_id)? else {
+            return Ok(None);
+        };
+        OutputType::from_storage_code(code)
+            .map(Some)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown output type code: {code}"),
+                )
+            })
+    }
+}
+
+impl OutSpkHashIndex {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::create(path)?,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open(
+                path,
+                "out_spk_hash file length is not a multiple of 20 bytes",
+            )?,
+        })
+    }
+
+    fn open_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open_or_create(
+                path,
+                "out_spk_hash file length is not a multiple of 20 bytes",
+            )?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
+    pub fn append(&mut self, spk_hash: &ScriptPubkeyHash) -> io::Result<u64> {
+        self.inner.append_bytes(spk_hash)
+    }
+
+    pub fn get(&self, out_id: u64) -> io::Result<Option<ScriptPubkeyHash>> {
+        self.inner.get_bytes(out_id)
+    }
+}
+
+/// Hash of every indexed block, keyed by height relative to the first indexed block.
+#[derive(Debug)]
+pub struct BlockHashIndex {
+    inner: FixedWidthIndex<BLOCK_HASH_LEN_BYTES>,
+}
+
+impl BlockHashIndex {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::create(path)?,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open(
+                path,
+                "block hash file length is not a multiple of 32 bytes",
+            )?,
+        })
+    }
+
+    fn open_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open_or_create(
+                path,
+                "block hash file length is not a multiple of 32 bytes",
+            )?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
+    pub fn append(&mut self, block_hash: &[u8; 32]) -> io::Result<u64> {
+        self.inner.append_bytes(block_hash)
+    }
+
+    pub fn get(&self, height: u64) -> io::Result<Option<[u8; 32]>> {
+        self.inner.get_bytes(height)
+    }
+}
+
+/// Raw 80-byte header of every indexed block, keyed by height relative to the first indexed block.
+#[derive(Debug)]
+pub struct BlockHeaderIndex {
+    inner: FixedWidthIndex<BLOCK_HEADER_LEN_BYTES>,
+}
+
+impl BlockHeaderIndex {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::create(path)?,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open(
+                path,
+                "block header file length is not a multiple of 80 bytes",
+            )?,
+        })
+    }
+
+    fn open_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open_or_create(
+                path,
+                "block header file length is not a multiple of 80 bytes",
+            )?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
+    pub fn append(&mut self, header: &[u8; 80]) -> io::Result<u64> {
+        self.inner.append_bytes(header)
+    }
+
+    pub fn get(&self, height: u64) -> io::Result<Option<[u8; 80]>> {
+        self.inner.get_bytes(height)
+    }
+}
+
+/// BIP158 basic filter of every filtered block, keyed by height relative to the first indexed
+/// block. Filters are variable-length: `block_filter.dat` holds them back to back and
+/// `block_filter_end.bin` the end offset of each one. `block_filter_complete.bin` flags the
+/// filters that cover every spent script of their block.
+#[derive(Debug)]
+pub struct BlockFilterIndex {
+    end: FixedWidthIndex<FILTER_END_LEN_BYTES>,
+    complete: FixedWidthIndex<FILTER_COMPLETE_LEN_BYTES>,
+    data: File,
+}
+
+impl BlockFilterIndex {
+    const END_FILE_NAME: &'static str = "block_filter_end.bin";
+    const COMPLETE_FILE_NAME: &'static str = "block_filter_complete.bin";
+    const DATA_FILE_NAME: &'static str = "block_filter.dat";
+
+    /// Whether `dir` holds block filters.
+    pub fn exists(dir: impl AsRef<Path>) -> bool {
+        dir.as_ref().join(Self::END_FILE_NAME).exists()
+    }
+
+    /// Number of filtered blocks in `dir` without opening the filters for writing, or `None`
+    /// if `dir` holds no block filters.
+    pub fn stored_len(dir: impl AsRef<Path>) -> io::Result<Option<u64>> {
+        let dir = dir.as_ref();
+        let len = |name| match std::fs::metadata(dir.join(name)) {
+            Ok(meta) => Ok(Some(meta.len())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        };
+        let Some(end_bytes) = len(Self::END_FILE_NAME)? else {
+            return Ok(None);
+        };
+        let complete_bytes = len(Self::COMPLETE_FILE_NAME)?.unwrap_or(0);
+        Ok(Some(
+            (end_bytes / FILTER_END_LEN_BYTES as u64).min(complete_bytes),
+        ))
+    }
+
+    /// Open the filters in `dir`, or create empty files if absent. Data past the last recorded
+    /// filter, left behind by an interrupted append, is dropped.
+    pub fn open_or_create(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let mut end = FixedWidthIndex::open_or_create(
+            dir.join(Self::END_FILE_NAME),
+            "block filter end file length is not a multiple of 8 bytes",
+        )?;
+        let mut complete = FixedWidthIndex::open_or_create(
+            dir.join(Self::COMPLETE_FILE_NAME),
+            "block filter complete file length is not a multiple of 1 byte",
+        )?;
+        // Both are appended per filter; an interrupted append leaves one of them longer.
+        let len = end.len().min(complete.len());
+        end.truncate(len)?;
+        complete.truncate(len)?;
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .create(true)
+            .open(dir.join(Self::DATA_FILE_NAME))?;
+        let index = Self {
+            end,
+            complete,
+            data,
+        };
+        let data_end = index.data_end()?;
+        if index.data.metadata()?.len() < data_end {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "block filter data file is shorter than its offsets",
+            ));
+        }
+        index.data.set_len(data_end)?;
+        Ok(index)
+    }
+
+    /// Open the filters in `dir` for reads only, or `None` if `dir` holds no block filters.
+    /// Unlike [`Self::open_or_create`] nothing is dropped: a filter whose append was
+    /// interrupted is just not served.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let dir = dir.as_ref();
+        if !Self::exists(dir) {
+            return Ok(None);
+        }
+        let mut end = FixedWidthIndex::open(
+            dir.join(Self::END_FILE_NAME),
+            "block filter end file length is not a multiple of 8 bytes",
+        )?;
+        let mut complete = FixedWidthIndex::open(
+            dir.join(Self::COMPLETE_FILE_NAME),
+            "block filter complete file length is not a multiple of 1 byte",
+        )?;
+        let len = end.len().min(complete.len());
+        end.len = len;
+        complete.len = len;
+        let mut index = Self {
+            end,
+            complete,
+            data: File::open(dir.join(Self::DATA_FILE_NAME))?,
+        };
+        if index.data.metadata()?.len() < index.data_end()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "block filter data file is shorter than its offsets",
+            ));
+        }
+        index.remap()?;
+        Ok(Some(index))
+    }
+
+    /// Number of filtered blocks.
+    pub fn len(&self) -> u64 {
+        self.end.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end.is_empty()
+    }
+
+    fn end_offset(&self, block: u64) -> io::Result<Option<u64>> {
+        Ok(self.end.get_bytes(block)?.map(u64::from_le_bytes))
+    }
+
+    fn data_end(&self) -> io::Result<u64> {
+        match self.len() {
+            0 => Ok(0),
+            len => Ok(self.end_offset(len - 1)?.unwrap_or(0)),
+        }
+    }
+
+    /// Append the filter of the next block. `complete` is false if spent scripts of the block
+    /// were unknown and are missing from the filter.
+    pub fn append(&mut self, filter: &[u8], complete: bool) -> io::Result<u64> {
+        let start = self.data_end()?;
+        self.data.write_all_at(filter, start)?;
+        self.complete.append_bytes(&[complete as u8])?;
+        self.end
+            .append_bytes(&(start + filter.len() as u64).to_le_bytes())
+    }
+
+    /// Whether the filter of `block` covers every spent script of the block, or `None` if
+    /// the block is not filtered.
+    pub fn is_complete(&self, block: u64) -> io::Result<Option<bool>> {
+        Ok(self.complete.get_bytes(block)?.map(|[flag]| flag != 0))
+    }
+
+    pub fn get(&self, block: u64) -> io::Result<Option<Vec<u8>>> {
+        let Some(end) = self.end_offset(block)? else {
+            return Ok(None);
+        };
+        let start = match block {
+            0 => 0,
+            _ => self.end_offset(block - 1)?.unwrap_or(0),
+        };
+        let mut buf = vec![0u8; end.saturating_sub(start) as usize];
+        self.data.read_exact_at(&mut buf, start)?;
+        Ok(Some(buf))
+    }
+
+    /// Drop the filters of every block after the first `len`.
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.end.truncate(len)?;
+        self.complete.truncate(len)?;
+        self.data.set_len(self.data_end()?)
+    }
+
+    pub fn remap(&mut self) -> io::Result<()> {
+        self.end.remap()?;
+        self.complete.remap()
+    }
+}
+
+/// Txid of every indexed transaction in internal byte order, keyed by dense TxId.
+#[derive(Debug)]
+pub struct TxHashIndex {
+    inner: FixedWidthIndex<TX_HASH_LEN_BYTES>,
+}
+
+impl TxHashIndex {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::create(path)?,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open(
+                path,
+                "tx hash file length is not a multiple of 32 bytes",
+            )?,
+        })
+    }
+
+    fn open_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: FixedWidthIndex::open_or_create(
+                path,
+                "tx hash file length is not a multiple of 32 bytes",
+            )?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.inner.truncate(len)
+    }
+
+    pub fn append(&mut self, txid: &[u8; 32]) -> io::Result<TxId> {
+        let idx = self.inner.append_bytes(txid)?;
+        Ok(TxId::new(idx as u32))
+    }
+
+    pub fn get(&self, txid: TxId) -> io::Result<Option<[u8; 32]>> {
+        self.inner.get_bytes(txid.index() as u64)
+    }
+}
+
+/// Sync metadata stored next to the index files.
+///
+/// Together with [`BlockHashIndex`] this is what lets a later sync find the last indexed
+/// block and resume from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DenseIndexMeta {
+    /// Global height of the first indexed block.
+    pub start_height: u64,
+    /// Network the indexed chain belongs to. `None` until a sync names one.
+    pub network: Option<bitcoin::Network>,
+    /// The `out_type` column was written before P2PK, bare multisig, P2A and unknown witness
+    /// outputs were told apart, and still lists them as non-standard.
+    pub legacy_output_types: bool,
+}
+
+impl DenseIndexMeta {
+    const FILE_NAME: &'static str = "meta.bin";
+    /// Version 1 files end after the start height.
+    const VERSION_NO_NETWORK: u8 = 1;
+    /// Version 2 files add the network magic.
+    const VERSION_LEGACY_OUTPUT_TYPES: u8 = 2;
+    /// Same layout as version 2, with an all-zero magic for no network.
+    const VERSION: u8 = 3;
+    /// Version byte, start height, network magic.
+    const LEN_BYTES: usize = 13;
+
+    /// Read the metadata from `dir`, or `None` if no index has been written there yet.
+    pub fn read(dir: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let bytes = match std::fs::read(dir.as_ref().join(Self::FILE_NAME)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let unsupported = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported dense index meta file",
+            )
+        };
+        let magic = || {
+            let magic: [u8; 4] = bytes[9..13].try_into().expect("slice length");
+            match magic {
+                [0, 0, 0, 0] => Ok(None),
+                magic => bitcoin::Network::from_magic(bitcoin::p2p::Magic::from_bytes(magic))
+                    .map(Some)
+                    .ok_or_else(unsupported),
+            }
+        };
+        let (network, legacy_output_types) = match (bytes.first(), bytes.len()) {
+            (Some(&Self::VERSION_NO_NETWORK), 9) => (None, true),
+            (Some(&Self::VERSION_LEGACY_OUTPUT_TYPES), Self::LEN_BYTES) => (magic()?, true),
+            (Some(&Self::VERSION), Self::LEN_BYTES) => (magic()?, false),
+            _ => return Err(unsupported()),
+        };
+        let start_height = u64::from_le_bytes(bytes[1..9].try_into().expect("slice length"));
+        Ok(Some(Self {
+            start_height,
+            network,
+            legacy_output_types,
+        }))
+    }
+
+    /// Write the metadata to `dir`, in the oldest version that holds it.
+    pub fn write(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let mut bytes = [0u8; Self::LEN_BYTES];
+        bytes[1..9].copy_from_slice(&self.start_height.to_le_bytes());
+        if let Some(network) = self.network {
+            bytes[9..13].copy_from_slice(&network.magic().to_bytes());
+        }
+        let (version, len) = match (self.legacy_output_types, self.network) {
+            (true, None) => (Self::VERSION_NO_NETWORK, 9),
+            (true, Some(_)) => (Self::VERSION_LEGACY_OUTPUT_TYPES, Self::LEN_BYTES),
+            (false, _) => (Self::VERSION, Self::LEN_BYTES),
+        };
+        bytes[0] = version;
+        std::fs::write(dir.as_ref().join(Self::FILE_NAME), &bytes[..len])
+    }
+}
+
+/// All dense index files grouped under a single directory.
+///
+/// `txptr`/`tx_hash` and `block_tx` are keyed by dense TxId and block height, `in_prevout` by
+/// dense TxInId, and the `out_*` columns by dense TxOutId.
 #[derive(Debug)]
 pub struct DenseIndexSet {
     pub txptr: ConfirmedTxPtrIndex,
+    pub tx_hash: TxHashIndex,
     pub block_tx: BlockTxIndex,
     pub in_prevout: InPrevoutIndex,
     pub out_spent: OutSpentByIndex,
+    pub out_value: OutValueIndex,
+    pub out_type: OutTypeIndex,
+    pub out_spk_hash: OutSpkHashIndex,
+    pub block_hash: BlockHashIndex,
+    pub block_header: BlockHeaderIndex,
 }
 
 impl DenseIndexSet {
@@ -387,19 +1049,112 @@ impl DenseIndexSet {
         let dir = dir.as_ref();
         Ok(Self {
             txptr: ConfirmedTxPtrIndex::open_or_create(dir.join("txptr.bin"))?,
+            tx_hash: TxHashIndex::open_or_create(dir.join("tx_hash.bin"))?,
             block_tx: BlockTxIndex::open_or_create(dir.join("block_tx.bin"))?,
             in_prevout: InPrevoutIndex::open_or_create(dir.join("in_prevout.bin"))?,
             out_spent: OutSpentByIndex::open_or_create(dir.join("out_spent.bin"))?,
+            out_value: OutValueIndex::open_or_create(dir.join("out_value.bin"))?,
+            out_type: OutTypeIndex::open_or_create(dir.join("out_type.bin"))?,
+            out_spk_hash: OutSpkHashIndex::open_or_create(dir.join("out_spk_hash.bin"))?,
+            block_hash: BlockHashIndex::open_or_create(dir.join("block_hash.bin"))?,
+            block_header: BlockHeaderIndex::open_or_create(dir.join("block_header.bin"))?,
+        })
+    }
+
+    /// Open the existing index files for reads only. Fails if any of them is missing.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        Ok(Self {
+            txptr: ConfirmedTxPtrIndex::open(dir.join("txptr.bin"))?,
+            tx_hash: TxHashIndex::open(dir.join("tx_hash.bin"))?,
+            block_tx: BlockTxIndex::open(dir.join("block_tx.bin"))?,
+            in_prevout: InPrevoutIndex::open(dir.join("in_prevout.bin"))?,
+            out_spent: OutSpentByIndex::open(dir.join("out_spent.bin"))?,
+            out_value: OutValueIndex::open(dir.join("out_value.bin"))?,
+            out_type: OutTypeIndex::open(dir.join("out_type.bin"))?,
+            out_spk_hash: OutSpkHashIndex::open(dir.join("out_spk_hash.bin"))?,
+            block_hash: BlockHashIndex::open(dir.join("block_hash.bin"))?,
+            block_header: BlockHeaderIndex::open(dir.join("block_header.bin"))?,
         })
     }
+
+    /// Memory-map every index file for reads, covering all entries written so far.
+    ///
+    /// Lookups of entries appended afterwards still work but fall back to a read syscall
+    /// until the next call.
+    pub fn remap(&mut self) -> io::Result<()> {
+        self.txptr.inner.remap()?;
+        self.tx_hash.inner.remap()?;
+        self.block_tx.inner.remap()?;
+        self.in_prevout.inner.remap()?;
+        self.out_spent.inner.remap()?;
+        self.out_value.inner.remap()?;
+        self.out_type.inner.remap()?;
+        self.out_spk_hash.inner.remap()?;
+        self.block_hash.inner.remap()?;
+        self.block_header.inner.remap()?;
+        Ok(())
+    }
+
+    /// Number of indexed blocks.
+    pub fn block_count(&self) -> u64 {
+        self.block_tx.len()
+    }
+
+    /// Dense id boundaries `(tx, in, out)` right after the first `blocks` indexed blocks.
+    pub fn boundaries_after(&self, blocks: u64) -> io::Result<(u64, u64, u64)> {
+        if blocks == 0 {
+            return Ok((0, 0, 0));
+        }
+        let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "dense index is truncated");
+        let tx_end = self.block_tx.get(blocks - 1)?.ok_or_else(corrupt)?;
+        if tx_end == 0 {
+            return Ok((0, 0, 0));
+        }
+        let ptr = self.txptr.get(TxId::new(tx_end - 1))?.ok_or_else(corrupt)?;
+        Ok((tx_end as u64, ptr.tx_in_end(), ptr.tx_out_end()))
+    }
+
+    /// Drop every block after the first `keep_blocks`, and everything those blocks created.
+    ///
+    /// Outputs that survive but were spent by a dropped input are marked unspent again.
+    /// Callers that keep derived state keyed by output or tx (e.g. the script pubkey and txid
+    /// dbs) must clean it up before calling this, while `out_spk_hash` and `tx_hash` still
+    /// cover the dropped entries.
+    pub fn truncate_blocks(&mut self, keep_blocks: u64) -> io::Result<()> {
+        if keep_blocks >= self.block_count() {
+            return Ok(());
+        }
+        let (tx_end, in_end, out_end) = self.boundaries_after(keep_blocks)?;
+        for in_id in in_end..self.in_prevout.len() {
+            match self.in_prevout.get(in_id)? {
+                Some(out_id) if out_id != OUTID_NONE && out_id < out_end => {
+                    self.out_spent.set(out_id, INID_NONE)?;
+                }
+                _ => {}
+            }
+        }
+        self.in_prevout.truncate(in_end)?;
+        self.out_spent.truncate(out_end)?;
+        self.out_value.truncate(out_end)?;
+        self.out_type.truncate(out_end)?;
+        self.out_spk_hash.truncate(out_end)?;
+        self.txptr.truncate(tx_end)?;
+        self.tx_hash.truncate(tx_end)?;
+        self.block_tx.truncate(keep_blocks)?;
+        self.block_hash.truncate(keep_blocks)?;
+        self.block_header.truncate(keep_blocks)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        BlockTxIndex, ConfirmedTxPtrIndex, INID_NONE, InPrevoutIndex, OutSpentByIndex, TxPtr,
+        BlockFilterIndex, BlockTxIndex, ConfirmedTxPtrIndex, DenseIndexMeta, INID_NONE,
+        InPrevoutIndex, OutSpentByIndex, OutSpkHashIndex, OutTypeIndex, OutValueIndex, TxPtr,
     };
-    use crate::dense::TxId;
+    use crate::{OutputType, dense::TxId};
     use std::fs;
     use std::path::PathBuf;
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -481,6 +1236,52 @@ mod tests {
         let _ = fs::remove_file(&path);
     }
 
+    #[test]
+    fn block_filter_round_trip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut index = BlockFilterIndex::open_or_create(dir.path()).expect("create");
+        assert!(BlockFilterIndex::exists(dir.path()));
+        index.append(&[1, 2, 3], true).expect("append");
+        index.append(&[], false).expect("append");
+        index.append(&[4, 5], true).expect("append");
+        index.truncate(2).expect("truncate");
+        index.append(&[6], true).expect("append");
+        drop(index);
+
+        assert_eq!(
+            BlockFilterIndex::stored_len(dir.path()).expect("len"),
+            Some(3)
+        );
+        let reopened = BlockFilterIndex::open_or_create(dir.path()).expect("open");
+        assert_eq!(reopened.len(), 3);
+        assert_eq!(reopened.get(0).expect("get"), Some(vec![1, 2, 3]));
+        assert_eq!(reopened.get(1).expect("get"), Some(vec![]));
+        assert_eq!(reopened.get(2).expect("get"), Some(vec![6]));
+        assert_eq!(reopened.get(3).expect("get"), None);
+        assert_eq!(reopened.is_complete(0).expect("get"), Some(true));
+        assert_eq!(reopened.is_complete(1).expect("get"), Some(false));
+        assert_eq!(reopened.is_complete(3).expect("get"), None);
+        drop(reopened);
+
+        // An append interrupted after the flag was written is dropped on open.
+        let flags = dir.path().join("block_filter_complete.bin");
+        let mut bytes = fs::read(&flags).expect("read");
+        bytes.push(1);
+        fs::write(&flags, bytes).expect("write");
+        assert_eq!(
+            BlockFilterIndex::stored_len(dir.path()).expect("len"),
+            Some(3)
+        );
+        let reopened = BlockFilterIndex::open_or_create(dir.path()).expect("open");
+        assert_eq!(reopened.len(), 3);
+
+        let empty = tempfile::tempdir().expect("tempdir");
+        assert_eq!(
+            BlockFilterIndex::stored_len(empty.path()).expect("len"),
+            None
+        );
+    }
+
     #[test]
     fn out_spent_by_round_trip() {
         let path = temp_path();
@@ -501,4 +1302,107 @@ mod tests {
 
         let _ = fs::remove_file(&path);
     }
+
+    #[test]
+    fn out_columns_round_trip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let value_path = dir.path().join("out_value.bin");
+        let type_path = dir.path().join("out_type.bin");
+        let hash_path = dir.path().join("out_spk_hash.bin");
+        let mut values = OutValueIndex::create(&value_path).expect("create");
+        let mut types = OutTypeIndex::create(&type_path).expect("create");
+        let mut hashes = OutSpkHashIndex::create(&hash_path).expect("create");
+
+        assert_eq!(values.append(5_000).expect("append"), 0);
+        assert_eq!(values.append(u64::MAX).expect("append"), 1);
+        assert_eq!(types.append(OutputType::P2tr).expect("append"), 0);
+        assert_eq!(types.append(OutputType::OpReturn).expect("append"), 1);
+        let multisig = OutputType::Multisig { m: 2, n: 3 };
+        assert_eq!(types.append(multisig).expect("append"), 2);
+        assert_eq!(hashes.append(&[7u8; 20]).expect("append"), 0);
+
+        drop((values, types, hashes));
+
+        let values = OutValueIndex::open(&value_path).expect("open");
+        let types = OutTypeIndex::open(&type_path).expect("open");
+        let hashes = OutSpkHashIndex::open(&hash_path).expect("open");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values.get(1).expect("get"), Some(u64::MAX));
+        assert_eq!(types.get(0).expect("get"), Some(OutputType::P2tr));
+        assert_eq!(types.get(1).expect("get"), Some(OutputType::OpReturn));
+        assert_eq!(types.get(2).expect("get"), Some(multisig));
+        assert_eq!(types.get(3).expect("get"), None);
+        // One byte per output.
+        assert_eq!(fs::metadata(&type_path).expect("metadata").len(), 3);
+        assert_eq!(hashes.get(0).expect("get"), Some([7u8; 20]));
+    }
+
+    #[test]
+    fn mapped_reads_track_appends_overwrites_and_truncation() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut index = OutSpentByIndex::create(dir.path().join("out_spent.bin")).expect("create");
+        for in_id in 0..4 {
+            index.append(in_id).expect("append");
+        }
+        index.inner.remap().expect("remap");
+        assert_eq!(index.get(3).expect("get"), Some(3));
+
+        // Overwrites inside the mapped prefix are visible through the mapping.
+        index.set(1, INID_NONE).expect("set");
+        assert_eq!(index.get(1).expect("get"), Some(INID_NONE));
+
+        // Entries appended after the mapping are read from the file.
+        index.append(40).expect("append");
+        assert_eq!(index.get(4).expect("get"), Some(40));
+
+        // Truncating below the mapped prefix drops the mapping.
+        index.truncate(2).expect("truncate");
+        assert_eq!(index.get(2).expect("get"), None);
+        index.append(20).expect("append");
+        assert_eq!(index.get(2).expect("get"), Some(20));
+        assert_eq!(index.get(0).expect("get"), Some(0));
+    }
+
+    #[test]
+    fn meta_records_network_and_reads_older_versions() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        for network in [Some(bitcoin::Network::Signet), None] {
+            let meta = DenseIndexMeta {
+                start_height: 840_000,
+                network,
+                legacy_output_types: false,
+            };
+            meta.write(dir.path()).expect("write");
+            assert_eq!(DenseIndexMeta::read(dir.path()).expect("read"), Some(meta));
+        }
+
+        let mut v1 = vec![1u8];
+        v1.extend_from_slice(&840_000u64.to_le_bytes());
+        fs::write(dir.path().join("meta.bin"), &v1).expect("write v1");
+        let legacy = DenseIndexMeta {
+            start_height: 840_000,
+            network: None,
+            legacy_output_types: true,
+        };
+        assert_eq!(
+            DenseIndexMeta::read(dir.path()).expect("read"),
+            Some(legacy)
+        );
+
+        let mut v2 = v1;
+        v2[0] = 2;
+        v2.extend_from_slice(&bitcoin::Network::Signet.magic().to_bytes());
+        fs::write(dir.path().join("meta.bin"), &v2).expect("write v2");
+        let legacy = DenseIndexMeta {
+            network: Some(bitcoin::Network::Signet),
+            ..legacy
+        };
+        assert_eq!(
+            DenseIndexMeta::read(dir.path()).expect("read"),
+            Some(legacy)
+        );
+        // Legacy metadata is written back in its own version.
+        legacy.write(dir.path()).expect("write");
+        assert_eq!(fs::read(dir.path().join("meta.bin")).expect("read"), v2);
+    }
 }
@@ -0,0 +1,480 @@
+use bitcoin::opcodes::all::{
+    OP_CHECKSIG, OP_CHECKSIGADD, OP_CLTV, OP_CSV, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160, OP_HASH256,
+    OP_IF, OP_NOTIF, OP_NUMEQUAL, OP_RIPEMD160, OP_SHA256,
+};
+use bitcoin::script::{Instruction, Script, ScriptBuf};
+use bitcoin::taproot::{
+    TAPROOT_ANNEX_PREFIX, TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_MAX_NODE_COUNT,
+    TAPROOT_CONTROL_NODE_SIZE, TAPROOT_LEAF_MASK,
+};
+
+use crate::output_type::{OutputType, classify_spent_output, multisig_m_of_n};
+
+/// How an input unlocks the output it spends. See [`decode_input_spend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSpend {
+    /// A P2PKH, P2WPKH or P2SH-P2WPKH spend, revealing a single public key.
+    KeyHash,
+    /// The input runs `script`: the scriptPubKey itself for bare outputs (P2PK, multisig and
+    /// non-standard ones), the redeem script of a P2SH spend, or the witness script of a P2WSH
+    /// or P2SH-P2WSH spend.
+    Script {
+        script: ScriptBuf,
+        template: ScriptTemplate,
+    },
+    /// A taproot key-path spend.
+    TaprootKeyPath,
+    /// A taproot script-path spend of the leaf `script`. `control_block_depth` is the number
+    /// of merkle path nodes in the control block, i.e. the depth of the leaf in the script tree.
+    TaprootScriptPath {
+        leaf_version: u8,
+        control_block_depth: usize,
+        script: ScriptBuf,
+        template: ScriptTemplate,
+    },
+    /// Nothing to decode: pay-to-anchor and unknown witness versions reveal no script, and
+    /// spend data that does not fit the output type cannot be decoded.
+    Other,
+}
+
+impl InputSpend {
+    /// Template of the script the input runs, if it runs one.
+    pub fn template(&self) -> Option<ScriptTemplate> {
+        match self {
+            InputSpend::Script { template, .. }
+            | InputSpend::TaprootScriptPath { template, .. } => Some(*template),
+            _ => None,
+        }
+    }
+
+    /// `(m, n)` if the input runs an `m`-of-`n` multisig script, be it bare, P2SH or P2WSH
+    /// wrapped, or a tapscript leaf.
+    pub fn multisig(&self) -> Option<(u8, u8)> {
+        match self.template()? {
+            ScriptTemplate::Multisig { m, n } => Some((m, n)),
+            _ => None,
+        }
+    }
+}
+
+/// Common shape of a script run by an input. Recognition is structural rather than an exact
+/// match against e.g. the BOLT 3 scripts, so it also covers their variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptTemplate {
+    /// `<pubkey> OP_CHECKSIG`, with an x-only key in tapscript.
+    SingleKey,
+    /// `m`-of-`n` multisig, with `1 <= m <= n`: `OP_CHECKMULTISIG` with the same rules as
+    /// [`OutputType::Multisig`], or the tapscript form
+    /// `<key> OP_CHECKSIG (<key> OP_CHECKSIGADD)... <m> OP_NUMEQUAL` with up to 255 keys.
+    Multisig {
+        m: u8,
+        n: u8,
+    },
+    /// Hash time-locked contract: a script with branches and a hashlock (a hash opcode, a 20-
+    /// or 32-byte digest and `OP_EQUAL` or `OP_EQUALVERIFY`), as in Lightning HTLC outputs and
+    /// atomic swaps. `timelock` is the first timelock in the script, if any.
+    Htlc {
+        timelock: Option<Timelock>,
+    },
+    /// Any other script with a timelock, such as Lightning `to_local` and anchor outputs or
+    /// vault recovery paths. Holds the first timelock in the script.
+    Timelocked(Timelock),
+    Other,
+}
+
+/// Base of the [`ScriptTemplate::as_u32`] codes of multisig templates, which embed `m` and `n`.
+const MULTISIG_TEMPLATE_CODE: u32 = 0x1_0000;
+
+impl ScriptTemplate {
+    /// Stable numeric code for fingerprint vectors. Multisig embeds `m` and `n` like
+    /// [`OutputType::as_u32`]; of a timelock only its kind counts, not its value.
+    pub fn as_u32(self) -> u32 {
+        match self {
+            ScriptTemplate::SingleKey => 0,
+            ScriptTemplate::Htlc { timelock: None } => 1,
+            ScriptTemplate::Htlc {
+                timelock: Some(Timelock::Relative(_)),
+            } => 2,
+            ScriptTemplate::Htlc {
+                timelock: Some(Timelock::Absolute(_)),
+            } => 3,
+            ScriptTemplate::Timelocked(Timelock::Relative(_)) => 4,
+            ScriptTemplate::Timelocked(Timelock::Absolute(_)) => 5,
+            ScriptTemplate::Other => 6,
+            ScriptTemplate::Multisig { m, n } => {
+                MULTISIG_TEMPLATE_CODE + ((m as u32) << 8 | n as u32)
+            }
+        }
+    }
+}
+
+/// Argument of a timelock opcode pushed right before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Timelock {
+    /// `OP_CHECKSEQUENCEVERIFY` argument, in BIP68 `nSequence` encoding.
+    Relative(u32),
+    /// `OP_CHECKLOCKTIMEVERIFY` argument: a block height below 500,000,000, a Unix time
+    /// otherwise.
+    Absolute(u32),
+}
+
+/// Decode how an input unlocks the output it spends, given that output's scriptPubKey and the
+/// input's scriptSig and witness.
+pub fn decode_input_spend(spk: &[u8], script_sig: &[u8], witness: &[Vec<u8>]) -> InputSpend {
+    match classify_spent_output(spk, script_sig) {
+        OutputType::P2pkh | OutputType::P2wpkh | OutputType::P2shP2wpkh => InputSpend::KeyHash,
+        OutputType::P2pk | OutputType::Multisig { .. } | OutputType::NonStandard => {
+            script_spend(spk)
+        }
+        OutputType::P2sh => {
+            // The redeem script is the last push of a push-only scriptSig.
+            let script_sig = Script::from_bytes(script_sig);
+            match script_sig.instructions().last() {
+                Some(Ok(Instruction::PushBytes(redeem_script))) if script_sig.is_push_only() => {
+                    script_spend(redeem_script.as_bytes())
+                }
+                _ => InputSpend::Other,
+            }
+        }
+        OutputType::P2wsh | OutputType::P2shP2wsh => match witness.last() {
+            Some(witness_script) => script_spend(witness_script),
+            None => InputSpend::Other,
+        },
+        OutputType::P2tr => taproot_spend(witness),
+        OutputType::OpReturn | OutputType::P2a | OutputType::WitnessUnknown { .. } => {
+            InputSpend::Other
+        }
+    }
+}
+
+fn script_spend(script: &[u8]) -> InputSpend {
+    InputSpend::Script {
+        script: ScriptBuf::from_bytes(script.to_vec()),
+        template: classify_script_template(script),
+    }
+}
+
+/// Key path or script path of a taproot witness, as told apart by BIP341.
+fn taproot_spend(witness: &[Vec<u8>]) -> InputSpend {
+    let items = match witness {
+        [rest @ .., annex] if !rest.is_empty() && annex.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+            rest
+        }
+        items => items,
+    };
+    let (script, control_block) = match items {
+        [] => return InputSpend::Other,
+        [_signature] => return InputSpend::TaprootKeyPath,
+        [.., script, control_block] => (script, control_block),
+    };
+    let Some(path_len) = control_block.len().checked_sub(TAPROOT_CONTROL_BASE_SIZE) else {
+        return InputSpend::Other;
+    };
+    let control_block_depth = path_len / TAPROOT_CONTROL_NODE_SIZE;
+    if !path_len.is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+        || control_block_depth > TAPROOT_CONTROL_MAX_NODE_COUNT
+    {
+        return InputSpend::Other;
+    }
+    InputSpend::TaprootScriptPath {
+        leaf_version: control_block[0] & TAPROOT_LEAF_MASK,
+        control_block_depth,
+        script: ScriptBuf::from_bytes(script.clone()),
+        template: classify_script_template(script),
+    }
+}
+
+/// Recognise the template of a script an input runs, e.g. a redeem, witness or tapscript leaf
+/// script.
+pub fn classify_script_template(script: &[u8]) -> ScriptTemplate {
+    let script = Script::from_bytes(script);
+    let Ok(instructions) = script.instructions().collect::<Result<Vec<_>, _>>() else {
+        return ScriptTemplate::Other;
+    };
+    if let Some((m, n)) = multisig_m_of_n(script).or_else(|| checksigadd_m_of_n(&instructions)) {
+        return ScriptTemplate::Multisig { m, n };
+    }
+    if let [Instruction::PushBytes(key), Instruction::Op(OP_CHECKSIG)] = instructions[..]
+        && matches!(key.len(), 32 | 33 | 65)
+    {
+        return ScriptTemplate::SingleKey;
+    }
+
+    let timelock = instructions.windows(2).find_map(|pair| {
+        let argument = || u32::try_from(pair[0].script_num()?).ok();
+        match pair[1] {
+            Instruction::Op(OP_CSV) => argument().map(Timelock::Relative),
+            Instruction::Op(OP_CLTV) => argument().map(Timelock::Absolute),
+            _ => None,
+        }
+    });
+    let branches = instructions
+        .iter()
+        .any(|instruction| matches!(instruction, Instruction::Op(OP_IF | OP_NOTIF)));
+    let hashlock = instructions.windows(3).any(|triple| {
+        matches!(
+            triple,
+            [
+                Instruction::Op(OP_HASH160 | OP_RIPEMD160 | OP_SHA256 | OP_HASH256),
+                Instruction::PushBytes(digest),
+                Instruction::Op(OP_EQUAL | OP_EQUALVERIFY),
+            ] if matches!(digest.len(), 20 | 32)
+        )
+    });
+
+    if branches && hashlock {
+        ScriptTemplate::Htlc { timelock }
+    } else {
+        timelock.map_or(ScriptTemplate::Other, ScriptTemplate::Timelocked)
+    }
+}
+
+/// `(m, n)` of a tapscript multisig `<key> OP_CHECKSIG (<key> OP_CHECKSIGADD)... <m>
+/// OP_NUMEQUAL` over x-only keys.
+fn checksigadd_m_of_n(instructions: &[Instruction]) -> Option<(u8, u8)> {
+    let [
+        Instruction::PushBytes(first_key),
+        Instruction::Op(OP_CHECKSIG),
+        rest @ ..,
+        threshold,
+        Instruction::Op(OP_NUMEQUAL),
+    ] = instructions
+    else {
+        return None;
+    };
+    if first_key.len() != 32 || !rest.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut keys = 1usize;
+    for pair in rest.chunks(2) {
+        match pair {
+            [Instruction::PushBytes(key), Instruction::Op(OP_CHECKSIGADD)] if key.len() == 32 => {
+                keys += 1
+            }
+            _ => return None,
+        }
+    }
+    let n = u8::try_from(keys).ok()?;
+    let m = u8::try_from(threshold.script_num()?).ok()?;
+    (1 <= m && m <= n).then_some((m, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::opcodes::all::{
+        OP_CHECKMULTISIG, OP_DROP, OP_DUP, OP_ELSE, OP_ENDIF, OP_IFDUP, OP_PUSHBYTES_0, OP_SIZE,
+        OP_SWAP,
+    };
+    use bitcoin::script::Builder;
+
+    const KEY: [u8; 33] = [0x02; 33];
+    const XONLY_KEY: [u8; 32] = [0x03; 32];
+    const SIG: [u8; 71] = [0x30; 71];
+
+    fn push_only(pushes: &[&[u8]]) -> Vec<u8> {
+        pushes
+            .iter()
+            .fold(Builder::new(), |builder, push| {
+                builder.push_slice(<&bitcoin::script::PushBytes>::try_from(*push).unwrap())
+            })
+            .into_bytes()
+    }
+
+    #[test]
+    fn decodes_bare_and_wrapped_multisig() {
+        let multisig = Builder::new()
+            .push_int(2)
+            .push_slice(KEY)
+            .push_slice(KEY)
+            .push_slice(KEY)
+            .push_int(3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let p2sh = ScriptBuf::new_p2sh(&multisig.script_hash());
+        let p2wsh = ScriptBuf::new_p2wsh(&multisig.wscript_hash());
+        let witness = vec![vec![], SIG.to_vec(), SIG.to_vec(), multisig.to_bytes()];
+
+        let bare = decode_input_spend(multisig.as_bytes(), &push_only(&[&[], &SIG]), &[]);
+        assert_eq!(bare.multisig(), Some((2, 3)));
+        let redeemed = push_only(&[&[], &SIG, &SIG, multisig.as_bytes()]);
+        let wrapped = decode_input_spend(p2sh.as_bytes(), &redeemed, &[]);
+        assert_eq!(
+            wrapped,
+            InputSpend::Script {
+                script: multisig.clone(),
+                template: ScriptTemplate::Multisig { m: 2, n: 3 },
+            }
+        );
+        let segwit = decode_input_spend(p2wsh.as_bytes(), &[], &witness);
+        assert_eq!(segwit.multisig(), Some((2, 3)));
+        let nested = push_only(&[p2wsh.as_bytes()]);
+        assert_eq!(
+            decode_input_spend(p2sh.as_bytes(), &nested, &witness).multisig(),
+            Some((2, 3))
+        );
+
+        // P2SH scriptSigs must be push-only, and P2WSH spends need a witness script.
+        let mut not_push_only = redeemed.clone();
+        not_push_only.insert(0, OP_DUP.to_u8());
+        assert_eq!(
+            decode_input_spend(p2sh.as_bytes(), &not_push_only, &[]),
+            InputSpend::Other
+        );
+        assert_eq!(
+            decode_input_spend(p2wsh.as_bytes(), &[], &[]),
+            InputSpend::Other
+        );
+        let p2wpkh = ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_raw_hash(
+            bitcoin::hashes::Hash::all_zeros(),
+        ));
+        let key_hash = decode_input_spend(p2wpkh.as_bytes(), &[], &[SIG.to_vec(), KEY.to_vec()]);
+        assert_eq!(key_hash, InputSpend::KeyHash);
+        assert_eq!(key_hash.template(), None);
+    }
+
+    #[test]
+    fn decodes_taproot_key_and_script_paths() {
+        let mut p2tr = vec![0x51, 32];
+        p2tr.extend(XONLY_KEY);
+        let signature = vec![0x01; 64];
+        let annex = vec![TAPROOT_ANNEX_PREFIX, 0x00];
+
+        assert_eq!(
+            decode_input_spend(&p2tr, &[], std::slice::from_ref(&signature)),
+            InputSpend::TaprootKeyPath
+        );
+        assert_eq!(
+            decode_input_spend(&p2tr, &[], &[signature.clone(), annex.clone()]),
+            InputSpend::TaprootKeyPath
+        );
+        assert_eq!(decode_input_spend(&p2tr, &[], &[]), InputSpend::Other);
+
+        let leaf = Builder::new()
+            .push_slice(XONLY_KEY)
+            .push_opcode(OP_CHECKSIG)
+            .push_slice(XONLY_KEY)
+            .push_opcode(OP_CHECKSIGADD)
+            .push_slice(XONLY_KEY)
+            .push_opcode(OP_CHECKSIGADD)
+            .push_int(2)
+            .push_opcode(OP_NUMEQUAL)
+            .into_script();
+        let mut control_block = vec![0xc1];
+        control_block.extend(XONLY_KEY);
+        control_block.extend([0xaa; 2 * TAPROOT_CONTROL_NODE_SIZE]);
+        let witness = [
+            signature.clone(),
+            vec![],
+            signature.clone(),
+            leaf.to_bytes(),
+            control_block.clone(),
+            annex,
+        ];
+        assert_eq!(
+            decode_input_spend(&p2tr, &[], &witness),
+            InputSpend::TaprootScriptPath {
+                leaf_version: 0xc0,
+                control_block_depth: 2,
+                script: leaf.clone(),
+                template: ScriptTemplate::Multisig { m: 2, n: 3 },
+            }
+        );
+
+        control_block.pop();
+        assert_eq!(
+            decode_input_spend(&p2tr, &[], &[leaf.to_bytes(), control_block]),
+            InputSpend::Other
+        );
+    }
+
+    #[test]
+    fn recognises_lightning_templates() {
+        // BOLT 3 received HTLC output.
+        let received_htlc = Builder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice([0x11; 20])
+            .push_opcode(OP_EQUAL)
+            .push_opcode(OP_IF)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ELSE)
+            .push_slice(KEY)
+            .push_opcode(OP_SWAP)
+            .push_opcode(OP_SIZE)
+            .push_int(32)
+            .push_opcode(OP_EQUAL)
+            .push_opcode(OP_IF)
+            .push_opcode(OP_HASH160)
+            .push_slice([0x22; 20])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_int(2)
+            .push_opcode(OP_SWAP)
+            .push_slice(KEY)
+            .push_int(2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .push_opcode(OP_ELSE)
+            .push_opcode(OP_DROP)
+            .push_int(800_000)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        assert_eq!(
+            classify_script_template(received_htlc.as_bytes()),
+            ScriptTemplate::Htlc {
+                timelock: Some(Timelock::Absolute(800_000))
+            }
+        );
+
+        let to_local = Builder::new()
+            .push_opcode(OP_IF)
+            .push_slice(KEY)
+            .push_opcode(OP_ELSE)
+            .push_int(144)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_slice(KEY)
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        assert_eq!(
+            classify_script_template(to_local.as_bytes()),
+            ScriptTemplate::Timelocked(Timelock::Relative(144))
+        );
+
+        let anchor = Builder::new()
+            .push_slice(KEY)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_IFDUP)
+            .push_opcode(OP_NOTIF)
+            .push_int(16)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        assert_eq!(
+            classify_script_template(anchor.as_bytes()),
+            ScriptTemplate::Timelocked(Timelock::Relative(16))
+        );
+
+        let single_key = Builder::new()
+            .push_slice(XONLY_KEY)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        assert_eq!(
+            classify_script_template(single_key.as_bytes()),
+            ScriptTemplate::SingleKey
+        );
+        assert_eq!(
+            classify_script_template(&[OP_PUSHBYTES_0.to_u8(), OP_DROP.to_u8()]),
+            ScriptTemplate::Other
+        );
+        // A truncated push cannot be decoded.
+        assert_eq!(
+            classify_script_template(&[0x02, 0x01]),
+            ScriptTemplate::Other
+        );
+    }
+}
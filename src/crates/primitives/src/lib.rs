@@ -3,12 +3,14 @@ pub mod dense;
 pub mod hamming_weight;
 pub mod handle;
 pub mod indices;
+pub mod input_script;
 pub mod loose;
 pub mod output_type;
 pub mod parser;
 pub mod sled;
 pub mod test_utils;
 pub mod traits;
+pub mod txindex;
 pub mod unified;
 
 #[cfg(any(test, feature = "test-utils"))]
@@ -17,7 +19,10 @@ pub mod integration;
 #[cfg(test)]
 mod tests;
 
-pub use output_type::{OutputType, classify_script_pubkey};
+pub use input_script::{
+    InputSpend, ScriptTemplate, Timelock, classify_script_template, decode_input_spend,
+};
+pub use output_type::{OutputType, classify_script_pubkey, classify_spent_output};
 pub use traits::abstract_types::{
     AbstractTransaction, AbstractTxIn, AbstractTxOut, HasPrevOutpoint, HasScriptPubkey,
     HasScriptSig, HasSequence, HasValue, HasVersion, HasWitness,
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use bitcoin::Amount;
-use bitcoin_slices::{Visit, Visitor, bsl};
+use bitcoin::{Amount, Weight};
+use bitcoin_slices::{Parse, Visit, Visitor, bitcoin_hashes::Hash, bsl};
 use core::ops::ControlFlow;
 
 use crate::{
@@ -21,7 +21,9 @@ use crate::{
 /// no full `bitcoin::Transaction` allocation, ever. Each method does one
 /// visitor pass over the bytes, extracting only the data it needs.
 ///
-/// Suitable for small/test chains only. For mainnet use the dense storage path.
+/// Keeping many of these in an [`InMemoryIndex`](crate::loose::InMemoryIndex) is only
+/// suitable for small/test chains. [`DenseStorage`](crate::dense::DenseStorage) hands them
+/// out on demand, reading the bytes straight from the blk file.
 pub struct ConfirmedTx {
     bytes: Arc<[u8]>,
 }
@@ -45,6 +47,8 @@ struct ParsedTx {
     outputs: Vec<ConfirmedTxOut>,
     locktime: u32,
     version: i32,
+    /// Input whose witness is currently being visited.
+    witness_vin: usize,
 }
 
 impl Visitor for ParsedTx {
@@ -56,7 +60,7 @@ impl Visitor for ParsedTx {
             prev_txid_bytes,
             prev_vout: prevout.vout(),
             sequence: tx_in.sequence(),
-            // TODO: upstream does not provide witness data in the bsl::TxIn struct. ?
+            // Filled in by the witness callbacks, which run after all inputs are visited.
             witness_items: vec![],
             script_sig_bytes: tx_in.script_sig().to_vec(),
         });
@@ -71,6 +75,17 @@ impl Visitor for ParsedTx {
         ControlFlow::Continue(())
     }
 
+    fn visit_witness(&mut self, vin: usize) -> ControlFlow<()> {
+        self.witness_vin = vin;
+        ControlFlow::Continue(())
+    }
+
+    fn visit_witness_element(&mut self, _witness_i: usize, witness_element: &[u8]) {
+        if let Some(input) = self.inputs.get_mut(self.witness_vin) {
+            input.witness_items.push(witness_element.to_vec());
+        }
+    }
+
     fn visit_transaction(&mut self, tx: &bsl::Transaction<'_>) -> ControlFlow<()> {
         self.locktime = tx.locktime();
         self.version = tx.version();
@@ -178,6 +193,21 @@ impl AbstractTransaction for ConfirmedTx {
             .map(|i| i.prev_vout == u32::MAX && i.prev_txid_bytes.iter().all(|b| *b == 0))
             .unwrap_or(false)
     }
+
+    fn txid_bytes(&self) -> [u8; 32] {
+        bsl::Transaction::parse(&self.bytes)
+            .expect("ConfirmedTx: bytes must be a valid serialized transaction")
+            .parsed_owned()
+            .txid()
+            .to_byte_array()
+    }
+
+    fn weight(&self) -> Weight {
+        let tx = bsl::Transaction::parse(&self.bytes)
+            .expect("ConfirmedTx: bytes must be a valid serialized transaction")
+            .parsed_owned();
+        Weight::from_wu(tx.weight())
+    }
 }
 
 impl HasNLockTime for ConfirmedTx {
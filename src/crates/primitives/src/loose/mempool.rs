@@ -0,0 +1,323 @@
+use std::{io, path::Path};
+
+use bitcoin::{
+    Transaction, Txid,
+    consensus::{Decodable, encode::VarInt},
+    hashes::Hash,
+};
+
+use crate::{
+    loose::{InMemoryIndex, LooseIndexSink},
+    traits::IndexSink,
+};
+
+/// `mempool.dat` written before Bitcoin Core v28, without obfuscation.
+const MEMPOOL_DUMP_VERSION_NO_XOR_KEY: u64 = 1;
+/// `mempool.dat` whose contents after the key are XOR-obfuscated.
+const MEMPOOL_DUMP_VERSION: u64 = 2;
+
+#[derive(Debug)]
+pub enum MempoolError {
+    Io(io::Error),
+    Decode(bitcoin::consensus::encode::Error),
+    UnsupportedVersion(u64),
+}
+
+impl std::error::Error for MempoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MempoolError::Io(e) => Some(e),
+            MempoolError::Decode(e) => Some(e),
+            MempoolError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MempoolError::Io(e) => write!(f, "io: {}", e),
+            MempoolError::Decode(e) => write!(f, "decode: {}", e),
+            MempoolError::UnsupportedVersion(v) => write!(f, "unsupported mempool.dat version {v}"),
+        }
+    }
+}
+
+impl From<bitcoin::consensus::encode::Error> for MempoolError {
+    fn from(e: bitcoin::consensus::encode::Error) -> Self {
+        MempoolError::Decode(e)
+    }
+}
+
+impl From<bitcoin::io::Error> for MempoolError {
+    fn from(e: bitcoin::io::Error) -> Self {
+        MempoolError::Decode(e.into())
+    }
+}
+
+/// Node-local metadata Bitcoin Core keeps for a mempool transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MempoolTxInfo {
+    /// Unix time the transaction entered the mempool.
+    pub time: i64,
+    /// Fee adjustment from `prioritisetransaction`, in satoshis.
+    pub fee_delta: i64,
+}
+
+/// Contents of a Bitcoin Core `mempool.dat` file.
+#[derive(Debug)]
+pub struct MempoolSnapshot {
+    /// Transactions in file order, which puts parents before their children.
+    pub txs: Vec<(Transaction, MempoolTxInfo)>,
+    /// Fee deltas for transactions that were not in the mempool when it was dumped.
+    pub fee_deltas: Vec<(Txid, i64)>,
+    /// Transactions the node had not yet seen relayed back by a peer.
+    pub unbroadcast: Vec<Txid>,
+}
+
+impl MempoolSnapshot {
+    /// Read and decode `mempool.dat`, undoing its XOR obfuscation if present.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, MempoolError> {
+        let bytes = std::fs::read(path).map_err(MempoolError::Io)?;
+        Self::parse(bytes)
+    }
+
+    fn parse(mut bytes: Vec<u8>) -> Result<Self, MempoolError> {
+        let mut header: &[u8] = &bytes;
+        let version = u64::consensus_decode(&mut header)?;
+        let xor_key = match version {
+            MEMPOOL_DUMP_VERSION_NO_XOR_KEY => Vec::new(),
+            MEMPOOL_DUMP_VERSION => Vec::<u8>::consensus_decode(&mut header)?,
+            other => return Err(MempoolError::UnsupportedVersion(other)),
+        };
+        let body_start = bytes.len() - header.len();
+        apply_xor(&xor_key, &mut bytes[body_start..], body_start);
+
+        let mut reader: &[u8] = &bytes[body_start..];
+        let count = u64::consensus_decode(&mut reader)?;
+        let mut txs = Vec::new();
+        for _ in 0..count {
+            let tx = Transaction::consensus_decode(&mut reader)?;
+            let time = i64::consensus_decode(&mut reader)?;
+            let fee_delta = i64::consensus_decode(&mut reader)?;
+            txs.push((tx, MempoolTxInfo { time, fee_delta }));
+        }
+
+        let delta_count = VarInt::consensus_decode(&mut reader)?.0;
+        let mut fee_deltas = Vec::new();
+        for _ in 0..delta_count {
+            let txid = Txid::consensus_decode(&mut reader)?;
+            let delta = i64::consensus_decode(&mut reader)?;
+            fee_deltas.push((txid, delta));
+        }
+
+        let unbroadcast_count = VarInt::consensus_decode(&mut reader)?.0;
+        let mut unbroadcast = Vec::new();
+        for _ in 0..unbroadcast_count {
+            unbroadcast.push(Txid::consensus_decode(&mut reader)?);
+        }
+
+        Ok(Self {
+            txs,
+            fee_deltas,
+            unbroadcast,
+        })
+    }
+
+    /// Build an [`InMemoryIndex`] holding every transaction of the snapshot, with inputs
+    /// linked to the outputs of their in-mempool parents and [`MempoolTxInfo`] recorded in
+    /// [`InMemoryIndex::mempool_info`].
+    pub fn into_index(self) -> InMemoryIndex {
+        let mut sink = LooseIndexSink::new();
+        for (tx, _) in &self.txs {
+            let Ok(()) = feed_tx(&mut sink, tx);
+        }
+        let mut index = sink.finish();
+        for (tx, info) in self.txs {
+            let loose_txid = index
+                .loose_txid(&tx.compute_txid().to_byte_array())
+                .expect("every snapshot transaction was fed to the sink");
+            index.mempool_info.insert(loose_txid, info);
+        }
+        index
+    }
+}
+
+fn feed_tx<S: IndexSink>(sink: &mut S, tx: &Transaction) -> Result<(), S::Error> {
+    for (vin, input) in tx.input.iter().enumerate() {
+        sink.on_input(
+            vin,
+            &input.previous_output.txid.to_byte_array(),
+            input.previous_output.vout,
+        )?;
+    }
+    for (vout, output) in tx.output.iter().enumerate() {
+        sink.on_output(vout, output.value.to_sat(), output.script_pubkey.as_bytes())?;
+    }
+    let bytes = bitcoin::consensus::serialize(tx);
+    sink.on_transaction(
+        &tx.compute_txid().to_byte_array(),
+        0,
+        0,
+        bytes.len() as u32,
+        &bytes,
+    )
+}
+
+/// XOR `data`, which starts at absolute file position `file_offset`, with the cyclic `key`.
+fn apply_xor(key: &[u8], data: &mut [u8], file_offset: usize) {
+    if key.is_empty() {
+        return;
+    }
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[(file_offset + i) % key.len()];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::TxOutId;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, TxIn, TxOut, Witness,
+        absolute::LockTime,
+        consensus::{Encodable, serialize},
+        transaction::Version,
+    };
+
+    fn tx(prevout: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                witness: Witness::from_slice(&[[0xaa; 72]]),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new_op_return([]),
+            }],
+        }
+    }
+
+    /// Serialize a snapshot the way Bitcoin Core's `DumpMempool` does.
+    fn dump(snapshot: &MempoolSnapshot, xor_key: Option<[u8; 8]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let version = match xor_key {
+            Some(_) => MEMPOOL_DUMP_VERSION,
+            None => MEMPOOL_DUMP_VERSION_NO_XOR_KEY,
+        };
+        version.consensus_encode(&mut out).unwrap();
+        if let Some(key) = xor_key {
+            key.to_vec().consensus_encode(&mut out).unwrap();
+        }
+        let body_start = out.len();
+        (snapshot.txs.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (tx, info) in &snapshot.txs {
+            tx.consensus_encode(&mut out).unwrap();
+            info.time.consensus_encode(&mut out).unwrap();
+            info.fee_delta.consensus_encode(&mut out).unwrap();
+        }
+        VarInt(snapshot.fee_deltas.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (txid, delta) in &snapshot.fee_deltas {
+            txid.consensus_encode(&mut out).unwrap();
+            delta.consensus_encode(&mut out).unwrap();
+        }
+        VarInt(snapshot.unbroadcast.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for txid in &snapshot.unbroadcast {
+            txid.consensus_encode(&mut out).unwrap();
+        }
+        if let Some(key) = xor_key {
+            apply_xor(&key, &mut out[body_start..], body_start);
+        }
+        out
+    }
+
+    fn sample() -> MempoolSnapshot {
+        let parent = tx(OutPoint::new(Txid::from_byte_array([1; 32]), 0), 5_000);
+        let child = tx(OutPoint::new(parent.compute_txid(), 0), 4_000);
+        let unbroadcast = vec![child.compute_txid()];
+        MempoolSnapshot {
+            txs: vec![
+                (
+                    parent,
+                    MempoolTxInfo {
+                        time: 1_700_000_000,
+                        fee_delta: 0,
+                    },
+                ),
+                (
+                    child,
+                    MempoolTxInfo {
+                        time: 1_700_000_060,
+                        fee_delta: -250,
+                    },
+                ),
+            ],
+            fee_deltas: vec![(Txid::from_byte_array([9; 32]), 1_000)],
+            unbroadcast,
+        }
+    }
+
+    fn assert_same(got: &MempoolSnapshot, want: &MempoolSnapshot) {
+        assert_eq!(got.txs, want.txs);
+        assert_eq!(got.fee_deltas, want.fee_deltas);
+        assert_eq!(got.unbroadcast, want.unbroadcast);
+    }
+
+    #[test]
+    fn reads_unobfuscated_dump() {
+        let want = sample();
+        let got = MempoolSnapshot::parse(dump(&want, None)).unwrap();
+        assert_same(&got, &want);
+    }
+
+    #[test]
+    fn reads_xor_obfuscated_dump() {
+        let want = sample();
+        let bytes = dump(&want, Some([0x13, 0x37, 0, 0xff, 0x42, 0x99, 0x01, 0x7e]));
+        // The transactions must not be readable without undoing the obfuscation.
+        let raw_tx = serialize(&want.txs[0].0);
+        assert!(!bytes.windows(raw_tx.len()).any(|w| w == raw_tx.as_slice()));
+
+        let got = MempoolSnapshot::parse(bytes).unwrap();
+        assert_same(&got, &want);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut bytes = dump(&sample(), None);
+        bytes[0] = 3;
+        assert!(matches!(
+            MempoolSnapshot::parse(bytes),
+            Err(MempoolError::UnsupportedVersion(3))
+        ));
+    }
+
+    #[test]
+    fn index_links_children_to_parents_and_keeps_metadata() {
+        let snapshot = sample();
+        let infos: Vec<_> = snapshot.txs.iter().map(|(_, info)| *info).collect();
+        let txs: Vec<_> = snapshot.txs.iter().map(|(tx, _)| tx.clone()).collect();
+        let index = snapshot.into_index();
+
+        let [parent, child] = [&txs[0], &txs[1]].map(|tx| {
+            index
+                .loose_txid(&tx.compute_txid().to_byte_array())
+                .unwrap()
+        });
+        let parent_out = TxOutId::new(parent, 0);
+        let child_in = child.txin_id(0);
+        assert_eq!(index.prev_txouts.get(&child_in), Some(&parent_out));
+        assert_eq!(index.spending_txins.get(&parent_out), Some(&child_in));
+        assert_eq!(index.mempool_info.get(&parent), Some(&infos[0]));
+        assert_eq!(index.mempool_info.get(&child), Some(&infos[1]));
+    }
+}
@@ -1,7 +1,11 @@
 pub mod confirmed_tx;
+pub mod mempool;
 pub mod sink;
+pub mod snapshot;
 pub use confirmed_tx::ConfirmedTx;
+pub use mempool::{MempoolError, MempoolSnapshot, MempoolTxInfo};
 pub use sink::LooseIndexSink;
+pub use snapshot::SnapshotError;
 
 use crate::handle::TxHandle;
 use crate::parser::{BlockFileError, Parser, collect_file_hints};
@@ -10,17 +14,17 @@ use crate::traits::graph_index::{
     TxIndex, TxIoIndex, TxOutDataIndex,
 };
 use crate::{
-    AnyInId, AnyOutId, AnyTxId, ScriptPubkeyHash, traits::abstract_types::AbstractTransaction,
+    AnyInId, AnyOutId, AnyTxId, OutputType, ScriptPubkeyHash,
+    traits::abstract_types::AbstractTransaction,
 };
-use bitcoin::Amount;
-
-use std::path::PathBuf;
-use std::{
-    collections::HashMap,
-    hash::{DefaultHasher, Hasher},
-    sync::Arc,
+use bitcoin::{
+    Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    absolute::LockTime, hashes::Hash, transaction::Version,
 };
 
+use std::path::{Path, PathBuf};
+use std::{collections::HashMap, sync::Arc};
+
 // TBD whether this is a generic or u32 specifically
 /// Sum of the short id of the txid and vout.
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Ord, PartialOrd)]
@@ -87,6 +91,9 @@ impl TxId {
     }
 }
 
+/// Largest loose key; [`AnyTxId`] stores loose keys as negative `i32`s and reserves zero.
+const MAX_LOOSE_KEY: u32 = i32::MAX as u32;
+
 impl IndexedGraph for InMemoryIndex {}
 
 pub struct InMemoryIndex {
@@ -94,8 +101,16 @@ pub struct InMemoryIndex {
     pub spending_txins: HashMap<TxOutId, TxInId>,
     // TODO: test that insertion order does not make a difference
     pub txs: HashMap<TxId, Arc<dyn AbstractTransaction + Send + Sync>>,
-    /// Index mapping script pubkey hash (20 bytes) and the first transaction output ID that uses it
-    pub spk_to_txout_ids: HashMap<ScriptPubkeyHash, TxOutId>,
+    /// Keys of `txs` in the order the transactions were added.
+    pub tx_order: Vec<TxId>,
+    /// Index mapping script pubkey hash (20 bytes) to every transaction output ID that uses it,
+    /// in insertion order
+    pub spk_to_txout_ids: HashMap<ScriptPubkeyHash, Vec<TxOutId>>,
+    /// Entry time and fee delta of transactions loaded from a node's `mempool.dat`
+    pub mempool_info: HashMap<TxId, MempoolTxInfo>,
+    /// Loose key of every transaction by txid (internal byte order). Each txid is indexed once;
+    /// adding it again resolves to the key of the first copy.
+    pub loose_txids: HashMap<[u8; 32], TxId>,
 }
 
 pub struct LooseIndexBuilder {
@@ -144,6 +159,20 @@ impl LooseIndexBuilder {
         Ok(sink.finish())
     }
 
+    /// Build an [`InMemoryIndex`] from a Bitcoin Core `mempool.dat` snapshot.
+    ///
+    /// `path` is usually `<datadir>/mempool.dat`, written by the node on shutdown or by the
+    /// `savemempool` RPC. Inputs spending other mempool transactions are linked; inputs spending
+    /// confirmed outputs are left unresolved.
+    pub fn from_mempool(path: impl AsRef<Path>) -> Result<InMemoryIndex, MempoolError> {
+        Ok(MempoolSnapshot::read(path)?.into_index())
+    }
+
+    /// Load an [`InMemoryIndex`] saved with [`InMemoryIndex::save_snapshot`].
+    pub fn from_snapshot(path: impl AsRef<Path>) -> Result<InMemoryIndex, SnapshotError> {
+        InMemoryIndex::load_snapshot(path)
+    }
+
     pub fn add_tx(&mut self, tx: Arc<dyn AbstractTransaction + Send + Sync>) -> &mut Self {
         self.txs.push(tx);
         self
@@ -186,61 +215,129 @@ impl InMemoryIndex {
             prev_txouts: HashMap::new(),
             spending_txins: HashMap::new(),
             txs: HashMap::new(),
+            tx_order: Vec::new(),
             spk_to_txout_ids: HashMap::new(),
+            mempool_info: HashMap::new(),
+            loose_txids: HashMap::new(),
         }
     }
 
-    // FIXME: check all the keys before inserting. Lets not modify anything before checking for all dup checks
+    /// Index `tx` and return a handle to it. A transaction whose txid is already indexed is
+    /// left untouched and the handle points at the existing copy.
     pub fn add_tx<'a>(
         &'a mut self,
         tx: Arc<dyn AbstractTransaction + Send + Sync>,
     ) -> TxHandle<'a> {
-        let loose_txid = TxId::new(self.txs.len() as u32 + 1);
+        let txid = tx.txid_bytes();
+        if let Some(existing) = self.loose_txid(&txid) {
+            return AnyTxId::from(existing).with(self);
+        }
+        // Builder transactions prefer sequential keys so that tests can name them by insertion
+        // order.
+        let loose_txid = self.free_key(TxId::new(self.txs.len() as u32 + 1));
         let tx_id = AnyTxId::from(loose_txid);
+        self.loose_txids.insert(txid, loose_txid);
 
-        // Process inputs to build the index before storing.
-        // DummyTxInWrapper encodes its TxId surrogate as LE u32 in bytes[0..4];
-        // surrogate 0 means unset (no prevout / coinbase).
+        // Link inputs to prevouts indexed before this tx; coinbase and unknown prevouts stay
+        // unresolved. An output keeps its first spender.
         for (vin, txin) in tx.inputs().enumerate() {
-            let vin_id = loose_txid.txin_id(vin as u32);
             let prev_txid_bytes = txin.prev_outpoint_txid_bytes();
             let prev_vout = txin.prev_outpoint_vout();
-            let surrogate = u32::from_le_bytes(prev_txid_bytes[..4].try_into().unwrap());
-            if surrogate == 0 || prev_vout == u32::MAX {
+            if prev_vout == u32::MAX && prev_txid_bytes == [0; 32] {
                 continue;
             }
-            let prev_loose_txid = TxId::new(surrogate);
+            let Some(prev_loose_txid) = self.loose_txid(&prev_txid_bytes) else {
+                continue;
+            };
+            let vin_id = loose_txid.txin_id(vin as u32);
             let prev_outid = TxOutId::new(prev_loose_txid, prev_vout);
-            self.spending_txins.insert(prev_outid, vin_id);
+            self.spending_txins.entry(prev_outid).or_insert(vin_id);
             self.prev_txouts.insert(vin_id, prev_outid);
         }
 
         // Process outputs to build SPK index
-        // Only keep track of the first transaction output ID that uses the given script pubkey.
-        // The rest can be clustered via same address clustering.
         for (vout_idx, output) in tx.outputs().enumerate() {
             let spk_hash = output.script_pubkey_hash();
             self.spk_to_txout_ids
                 .entry(spk_hash)
-                .or_insert_with(|| TxOutId::new(loose_txid, vout_idx as u32));
+                .or_default()
+                .push(TxOutId::new(loose_txid, vout_idx as u32));
         }
 
-        let result = self.txs.insert(loose_txid, tx);
-        if result.is_some() {
-            panic!("Transaction with id {:?} already exists!", tx_id);
-        }
+        self.txs.insert(loose_txid, tx);
+        self.tx_order.push(loose_txid);
 
         tx_id.with(self)
     }
 
-    // TODO: once we need stable id, we may need to manage the random key ourselves. Once we need to persist things solve this TODO
-    pub fn compute_txid(txid: impl Into<[u8; 4]>) -> TxId {
-        let mut hasher = DefaultHasher::new();
-        let mut buf = Vec::new();
-        buf.extend_from_slice(&txid.into());
-        hasher.write(buf.as_slice());
-        let hash = hasher.finish();
-        TxId(hash as u32)
+    /// Loose key of the transaction with the given txid (internal byte order), if indexed.
+    pub fn loose_txid(&self, txid: &[u8; 32]) -> Option<TxId> {
+        self.loose_txids.get(txid).copied()
+    }
+
+    /// Preferred loose key for a txid: its first four bytes, cut to the positive `i32` range
+    /// that [`AnyTxId`] can hold. The txid is already a hash, so these are uniformly spread.
+    ///
+    /// The key a transaction actually gets may differ, see [`Self::assign_key`].
+    pub fn compute_txid(txid: &[u8; 32]) -> TxId {
+        let k32 = u32::from_le_bytes(txid[..4].try_into().expect("4 bytes")) & MAX_LOOSE_KEY;
+        TxId::new(k32.max(1))
+    }
+
+    /// Reserve a loose key for `txid` and record it in [`Self::loose_txids`]. The caller must
+    /// check that the txid is not indexed yet.
+    ///
+    /// Two txids whose [`compute_txid`](Self::compute_txid) keys collide would otherwise alias
+    /// each other's inputs and outputs, so an occupied key is resolved by probing the following
+    /// keys in order. Which transaction ends up probing depends only on insertion order, so
+    /// adding the same transactions in the same order always yields the same keys.
+    pub(crate) fn assign_key(&mut self, txid: &[u8; 32]) -> TxId {
+        debug_assert!(!self.loose_txids.contains_key(txid));
+        let key = self.free_key(Self::compute_txid(txid));
+        self.loose_txids.insert(*txid, key);
+        key
+    }
+
+    /// `preferred` if no transaction holds it yet, otherwise the next free key after it.
+    fn free_key(&self, preferred: TxId) -> TxId {
+        assert!(
+            self.txs.len() < MAX_LOOSE_KEY as usize,
+            "loose index is full"
+        );
+        let mut key = preferred.index();
+        while self.txs.contains_key(&TxId::new(key)) {
+            key = if key >= MAX_LOOSE_KEY { 1 } else { key + 1 };
+        }
+        TxId::new(key)
+    }
+}
+
+/// Rebuild a wire transaction from the fields [`AbstractTransaction`] exposes.
+///
+/// Prevout txids are copied verbatim, so the made-up txids of test transactions survive the trip.
+pub(crate) fn to_bitcoin_tx<T: AbstractTransaction + ?Sized>(tx: &T) -> Transaction {
+    Transaction {
+        version: Version(tx.version()),
+        lock_time: LockTime::from_consensus(tx.locktime()),
+        input: tx
+            .inputs()
+            .map(|txin| TxIn {
+                previous_output: OutPoint::new(
+                    Txid::from_byte_array(txin.prev_outpoint_txid_bytes()),
+                    txin.prev_outpoint_vout(),
+                ),
+                script_sig: ScriptBuf::from_bytes(txin.script_sig_bytes()),
+                sequence: Sequence(txin.sequence()),
+                witness: Witness::from_slice(&txin.witness_items()),
+            })
+            .collect(),
+        output: tx
+            .outputs()
+            .map(|txout| TxOut {
+                value: txout.value(),
+                script_pubkey: ScriptBuf::from_bytes(txout.script_pubkey_bytes()),
+            })
+            .collect(),
     }
 }
 
@@ -277,9 +374,17 @@ impl ScriptPubkeyIndex for InMemoryIndex {
     fn script_pubkey_to_txout_id(&self, script_pubkey: &ScriptPubkeyHash) -> Option<AnyOutId> {
         self.spk_to_txout_ids
             .get(script_pubkey)
-            .cloned()
+            .and_then(|ids| ids.first())
+            .copied()
             .map(AnyOutId::from)
     }
+
+    fn outputs_for_script_pubkey(&self, script_pubkey: &ScriptPubkeyHash) -> Vec<AnyOutId> {
+        self.spk_to_txout_ids
+            .get(script_pubkey)
+            .map(|ids| ids.iter().copied().map(AnyOutId::from).collect())
+            .unwrap_or_default()
+    }
 }
 
 impl TxIndex for InMemoryIndex {
@@ -370,6 +475,18 @@ impl TxIoIndex for InMemoryIndex {
         None
     }
 
+    fn block_time(&self, _txid: &AnyTxId) -> Option<u32> {
+        None
+    }
+
+    fn block_hash(&self, _txid: &AnyTxId) -> Option<bitcoin::BlockHash> {
+        None
+    }
+
+    fn median_time_past(&self, _txid: &AnyTxId) -> Option<u32> {
+        None
+    }
+
     fn prev_outpoint_vout(&self, in_id: &AnyInId) -> u32 {
         let loose_in = in_id
             .loose_id()
@@ -391,6 +508,21 @@ impl TxIoIndex for InMemoryIndex {
             .expect("sane vin")
             .prev_outpoint_txid_bytes()
     }
+
+    fn spent_txout(&self, in_id: &AnyInId) -> Option<bitcoin::TxOut> {
+        let loose_in = in_id
+            .loose_id()
+            .expect("loose storage only supports loose txin ids");
+        let prev_out = AnyOutId::from(*self.prev_txouts.get(&loose_in)?);
+        Some(bitcoin::TxOut {
+            value: self.value(&prev_out),
+            script_pubkey: bitcoin::ScriptBuf::from_bytes(self.script_pubkey_bytes(&prev_out)),
+        })
+    }
+
+    fn undo_spent_height(&self, _in_id: &AnyInId) -> Option<u64> {
+        None
+    }
 }
 
 impl OutpointIndex for InMemoryIndex {
@@ -444,4 +576,18 @@ impl TxOutDataIndex for InMemoryIndex {
             .expect("txout should be present if index is built correctly");
         output.script_pubkey_bytes()
     }
+
+    fn output_type(&self, out_id: &AnyOutId) -> OutputType {
+        let loose_out = out_id
+            .loose_id()
+            .expect("loose storage only supports loose outids");
+        let tx = self
+            .txs
+            .get(&loose_out.txid())
+            .expect("loose txid not found in storage");
+        let output = tx
+            .output_at(loose_out.vout() as usize)
+            .expect("txout should be present if index is built correctly");
+        output.output_type()
+    }
 }
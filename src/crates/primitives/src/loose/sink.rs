@@ -1,11 +1,10 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 
 use bitcoin::hashes::{Hash, hash160};
 
 use crate::{
     ScriptPubkeyHash,
-    loose::{InMemoryIndex, TxId, TxInId, TxOutId, confirmed_tx::ConfirmedTx},
+    loose::{InMemoryIndex, TxInId, TxOutId, confirmed_tx::ConfirmedTx},
     traits::IndexSink,
 };
 
@@ -19,7 +18,6 @@ use crate::{
 /// dense storage path.
 pub struct LooseIndexSink {
     index: InMemoryIndex,
-    txid_to_loose: HashMap<[u8; 32], TxId>,
     // Per-tx staging (cleared in on_transaction)
     current_inputs: Vec<([u8; 32], u32)>,
     current_spk_hashes: Vec<ScriptPubkeyHash>,
@@ -29,7 +27,6 @@ impl LooseIndexSink {
     pub fn new() -> Self {
         Self {
             index: InMemoryIndex::new(),
-            txid_to_loose: HashMap::new(),
             current_inputs: Vec::new(),
             current_spk_hashes: Vec::new(),
         }
@@ -60,7 +57,12 @@ impl IndexSink for LooseIndexSink {
         Ok(())
     }
 
-    fn on_output(&mut self, _vout: usize, script_pubkey: &[u8]) -> Result<(), Self::Error> {
+    fn on_output(
+        &mut self,
+        _vout: usize,
+        _value: u64,
+        script_pubkey: &[u8],
+    ) -> Result<(), Self::Error> {
         let spk_hash = hash160::Hash::hash(script_pubkey).to_byte_array();
         self.current_spk_hashes.push(spk_hash);
         Ok(())
@@ -74,7 +76,14 @@ impl IndexSink for LooseIndexSink {
         _tx_len: u32,
         tx_bytes: &[u8],
     ) -> Result<(), Self::Error> {
-        let loose_txid = TxId::new(self.index.txs.len() as u32 + 1);
+        // A txid seen before (e.g. the duplicate coinbases BIP30 later ruled out) keeps the key
+        // of its first copy.
+        if self.index.loose_txid(txid).is_some() {
+            self.current_inputs.clear();
+            self.current_spk_hashes.clear();
+            return Ok(());
+        }
+        let loose_txid = self.index.assign_key(txid);
 
         // Build prev_txouts / spending_txins from staged inputs.
         for (vin, (prev_txid_raw, prev_vout)) in self.current_inputs.drain(..).enumerate() {
@@ -82,11 +91,15 @@ impl IndexSink for LooseIndexSink {
             if prev_vout == u32::MAX && prev_txid_raw.iter().all(|b| *b == 0) {
                 continue;
             }
-            if let Some(&prev_loose_txid) = self.txid_to_loose.get(&prev_txid_raw) {
+            if let Some(prev_loose_txid) = self.index.loose_txid(&prev_txid_raw) {
                 let prev_out_id = TxOutId::new(prev_loose_txid, prev_vout);
                 let vin_id = TxInId::new(loose_txid, vin as u32);
                 self.index.prev_txouts.insert(vin_id, prev_out_id);
-                self.index.spending_txins.insert(prev_out_id, vin_id);
+                // An output keeps its first spender.
+                self.index
+                    .spending_txins
+                    .entry(prev_out_id)
+                    .or_insert(vin_id);
             }
         }
 
@@ -96,7 +109,8 @@ impl IndexSink for LooseIndexSink {
             self.index
                 .spk_to_txout_ids
                 .entry(spk_hash)
-                .or_insert(out_id);
+                .or_default()
+                .push(out_id);
         }
 
         // Store raw bytes for lazy deserialization at query time.
@@ -104,12 +118,112 @@ impl IndexSink for LooseIndexSink {
         self.index
             .txs
             .insert(loose_txid, Arc::new(ConfirmedTx::new(arc_bytes)));
+        self.index.tx_order.push(loose_txid);
 
-        self.txid_to_loose.insert(*txid, loose_txid);
         Ok(())
     }
 
-    fn on_block_end(&mut self, _block_tx_count: u64) -> Result<(), Self::Error> {
+    fn on_block_end(
+        &mut self,
+        _header: &[u8; 80],
+        _block_hash: &[u8; 32],
+        _block_tx_count: u64,
+    ) -> Result<(), Self::Error> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::TxId;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Txid, absolute::LockTime,
+        consensus::serialize, transaction::Version,
+    };
+
+    fn tx_bytes(prevout: OutPoint) -> Vec<u8> {
+        serialize(&Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new_op_return([]),
+            }],
+        })
+    }
+
+    fn feed(sink: &mut LooseIndexSink, txid: [u8; 32], prevout: OutPoint) {
+        let bytes = tx_bytes(prevout);
+        let Ok(()) = sink.on_input(0, prevout.txid.as_ref(), prevout.vout);
+        let Ok(()) = sink.on_output(0, 1_000, &[0x6a]);
+        let Ok(()) = sink.on_transaction(&txid, 0, 0, bytes.len() as u32, &bytes);
+    }
+
+    #[test]
+    fn colliding_txids_get_distinct_keys() {
+        // Both txids share their first four bytes, and with them their preferred key.
+        let mut first = [0x11; 32];
+        let mut second = [0x11; 32];
+        first[31] = 1;
+        second[31] = 2;
+        assert_eq!(
+            InMemoryIndex::compute_txid(&first),
+            InMemoryIndex::compute_txid(&second)
+        );
+
+        let mut sink = LooseIndexSink::new();
+        let funding = OutPoint::new(Txid::from_byte_array([0xff; 32]), 0);
+        feed(&mut sink, first, funding);
+        feed(&mut sink, second, funding);
+        // Spends the output of the transaction that had to probe.
+        feed(
+            &mut sink,
+            [0x22; 32],
+            OutPoint::new(Txid::from_byte_array(second), 0),
+        );
+        let index = sink.finish();
+
+        let first_key = index.loose_txid(&first).unwrap();
+        let second_key = index.loose_txid(&second).unwrap();
+        assert_eq!(first_key, InMemoryIndex::compute_txid(&first));
+        assert_eq!(second_key, TxId::new(first_key.index() + 1));
+        assert_eq!(index.txs.len(), 3);
+
+        let spender = index.loose_txid(&[0x22; 32]).unwrap().txin_id(0);
+        assert_eq!(
+            index.prev_txouts.get(&spender),
+            Some(&second_key.txout_id(0))
+        );
+        assert_eq!(
+            index.spending_txins.get(&second_key.txout_id(0)),
+            Some(&spender)
+        );
+        assert_eq!(index.spending_txins.get(&first_key.txout_id(0)), None);
+    }
+
+    #[test]
+    fn double_spend_keeps_the_first_spender() {
+        let mut sink = LooseIndexSink::new();
+        let funding = [0x33; 32];
+        feed(
+            &mut sink,
+            funding,
+            OutPoint::new(Txid::from_byte_array([0xff; 32]), 0),
+        );
+        let prevout = OutPoint::new(Txid::from_byte_array(funding), 0);
+        feed(&mut sink, [0x44; 32], prevout);
+        feed(&mut sink, [0x55; 32], prevout);
+        let index = sink.finish();
+
+        let spent = index.loose_txid(&funding).unwrap().txout_id(0);
+        let first = index.loose_txid(&[0x44; 32]).unwrap().txin_id(0);
+        let second = index.loose_txid(&[0x55; 32]).unwrap().txin_id(0);
+        assert_eq!(index.spending_txins.get(&spent), Some(&first));
+        assert_eq!(index.prev_txouts.get(&second), Some(&spent));
+    }
+}
@@ -0,0 +1,338 @@
+//! Saving an [`InMemoryIndex`] to disk and loading it back.
+//!
+//! A snapshot holds every transaction together with the index's id assignments, so ids in a
+//! reloaded index match the ones handed out when it was built. All integers are encoded with
+//! Bitcoin's consensus encoding (little endian, `VarInt` counts).
+//!
+//! Layout:
+//!   magic:            4 bytes, [`SNAPSHOT_MAGIC`]
+//!   version:          u32, [`SNAPSHOT_VERSION`]
+//!   txs:              VarInt count, then (loose txid u32, serialized tx) in insertion order
+//!   prev_txouts:      VarInt count, then (txid u32, vin u32, prev txid u32, prev vout u32)
+//!   spending_txins:   VarInt count, then (txid u32, vout u32, spender txid u32, vin u32)
+//!   spk_to_txout_ids: VarInt count, then (20-byte hash, VarInt count, (txid u32, vout u32)*)
+//!   mempool_info:     VarInt count, then (txid u32, time i64, fee delta i64)
+//!   loose_txids:      VarInt count, then (32-byte txid, loose txid u32) sorted by txid
+
+use std::{io, path::Path, sync::Arc};
+
+use bitcoin::{
+    Transaction,
+    consensus::{Decodable, Encodable, encode::VarInt},
+};
+
+use crate::{
+    ScriptPubkeyHash,
+    loose::{ConfirmedTx, InMemoryIndex, MempoolTxInfo, TxId, TxInId, TxOutId, to_bitcoin_tx},
+};
+
+const SNAPSHOT_MAGIC: [u8; 4] = *b"LIDX";
+const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Decode(bitcoin::consensus::encode::Error),
+    /// The file does not start with [`SNAPSHOT_MAGIC`].
+    NotASnapshot,
+    UnsupportedVersion(u32),
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => Some(e),
+            SnapshotError::Decode(e) => Some(e),
+            SnapshotError::NotASnapshot | SnapshotError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "io: {}", e),
+            SnapshotError::Decode(e) => write!(f, "decode: {}", e),
+            SnapshotError::NotASnapshot => write!(f, "not a loose index snapshot"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported loose index snapshot version {v}")
+            }
+        }
+    }
+}
+
+impl From<bitcoin::consensus::encode::Error> for SnapshotError {
+    fn from(e: bitcoin::consensus::encode::Error) -> Self {
+        SnapshotError::Decode(e)
+    }
+}
+
+impl From<bitcoin::io::Error> for SnapshotError {
+    fn from(e: bitcoin::io::Error) -> Self {
+        SnapshotError::Decode(e.into())
+    }
+}
+
+impl InMemoryIndex {
+    /// Write the index to `path`, replacing any existing file.
+    ///
+    /// Entries are written in id order, so saving the same index twice produces identical files.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        std::fs::write(path, self.encode_snapshot()).map_err(SnapshotError::Io)
+    }
+
+    /// Load an index written by [`save_snapshot`](Self::save_snapshot).
+    ///
+    /// Transactions come back as [`ConfirmedTx`] regardless of the type they were added as;
+    /// ids, links and script pubkey lists are restored exactly as saved.
+    pub fn load_snapshot(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let bytes = std::fs::read(path).map_err(SnapshotError::Io)?;
+        Self::decode_snapshot(&bytes)
+    }
+
+    fn encode_snapshot(&self) -> Vec<u8> {
+        // Writing to a Vec cannot fail.
+        let mut out = Vec::new();
+        out.extend_from_slice(&SNAPSHOT_MAGIC);
+        SNAPSHOT_VERSION.consensus_encode(&mut out).unwrap();
+
+        VarInt(self.tx_order.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for id in &self.tx_order {
+            let tx = &self.txs[id];
+            id.0.consensus_encode(&mut out).unwrap();
+            bitcoin::consensus::serialize(&to_bitcoin_tx(tx.as_ref()))
+                .consensus_encode(&mut out)
+                .unwrap();
+        }
+
+        let mut prev_txouts: Vec<_> = self.prev_txouts.iter().collect();
+        prev_txouts.sort_unstable();
+        VarInt(prev_txouts.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (txin, txout) in prev_txouts {
+            encode_pair(&mut out, txin.txid(), txin.vin());
+            encode_pair(&mut out, txout.txid(), txout.vout());
+        }
+
+        let mut spending_txins: Vec<_> = self.spending_txins.iter().collect();
+        spending_txins.sort_unstable();
+        VarInt(spending_txins.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (txout, txin) in spending_txins {
+            encode_pair(&mut out, txout.txid(), txout.vout());
+            encode_pair(&mut out, txin.txid(), txin.vin());
+        }
+
+        let mut spks: Vec<_> = self.spk_to_txout_ids.iter().collect();
+        spks.sort_unstable_by_key(|(hash, _)| **hash);
+        VarInt(spks.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (hash, txouts) in spks {
+            out.extend_from_slice(hash);
+            // Insertion order is part of the index, so the list is kept as is.
+            VarInt(txouts.len() as u64)
+                .consensus_encode(&mut out)
+                .unwrap();
+            for txout in txouts {
+                encode_pair(&mut out, txout.txid(), txout.vout());
+            }
+        }
+
+        let mut mempool_info: Vec<_> = self.mempool_info.iter().collect();
+        mempool_info.sort_unstable_by_key(|(id, _)| **id);
+        VarInt(mempool_info.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (id, info) in mempool_info {
+            id.0.consensus_encode(&mut out).unwrap();
+            info.time.consensus_encode(&mut out).unwrap();
+            info.fee_delta.consensus_encode(&mut out).unwrap();
+        }
+
+        let mut loose_txids: Vec<_> = self.loose_txids.iter().collect();
+        loose_txids.sort_unstable();
+        VarInt(loose_txids.len() as u64)
+            .consensus_encode(&mut out)
+            .unwrap();
+        for (txid, id) in loose_txids {
+            out.extend_from_slice(txid);
+            id.0.consensus_encode(&mut out).unwrap();
+        }
+        out
+    }
+
+    fn decode_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let Some(mut reader) = bytes.strip_prefix(&SNAPSHOT_MAGIC[..]) else {
+            return Err(SnapshotError::NotASnapshot);
+        };
+        let version = u32::consensus_decode(&mut reader)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut index = InMemoryIndex::new();
+
+        for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+            let id = TxId(u32::consensus_decode(&mut reader)?);
+            let tx_bytes = Vec::<u8>::consensus_decode(&mut reader)?;
+            // Reject bytes ConfirmedTx would panic on later.
+            bitcoin::consensus::deserialize::<Transaction>(&tx_bytes)?;
+            index
+                .txs
+                .insert(id, Arc::new(ConfirmedTx::new(tx_bytes.into())));
+            index.tx_order.push(id);
+        }
+
+        for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+            let (txid, vin) = decode_pair(&mut reader)?;
+            let (prev_txid, prev_vout) = decode_pair(&mut reader)?;
+            index
+                .prev_txouts
+                .insert(TxInId::new(txid, vin), TxOutId::new(prev_txid, prev_vout));
+        }
+
+        for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+            let (txid, vout) = decode_pair(&mut reader)?;
+            let (spender, vin) = decode_pair(&mut reader)?;
+            index
+                .spending_txins
+                .insert(TxOutId::new(txid, vout), TxInId::new(spender, vin));
+        }
+
+        for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+            let mut hash: ScriptPubkeyHash = [0; 20];
+            bitcoin::io::Read::read_exact(&mut reader, &mut hash)?;
+            let mut txouts = Vec::new();
+            for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+                let (txid, vout) = decode_pair(&mut reader)?;
+                txouts.push(TxOutId::new(txid, vout));
+            }
+            index.spk_to_txout_ids.insert(hash, txouts);
+        }
+
+        for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+            let id = TxId(u32::consensus_decode(&mut reader)?);
+            let time = i64::consensus_decode(&mut reader)?;
+            let fee_delta = i64::consensus_decode(&mut reader)?;
+            index
+                .mempool_info
+                .insert(id, MempoolTxInfo { time, fee_delta });
+        }
+
+        for _ in 0..VarInt::consensus_decode(&mut reader)?.0 {
+            let mut txid = [0; 32];
+            bitcoin::io::Read::read_exact(&mut reader, &mut txid)?;
+            let id = TxId(u32::consensus_decode(&mut reader)?);
+            index.loose_txids.insert(txid, id);
+        }
+
+        if !reader.is_empty() {
+            return Err(SnapshotError::Decode(
+                bitcoin::consensus::encode::Error::ParseFailed("trailing data after snapshot"),
+            ));
+        }
+        Ok(index)
+    }
+}
+
+fn encode_pair(out: &mut Vec<u8>, txid: TxId, n: u32) {
+    txid.0.consensus_encode(out).unwrap();
+    n.consensus_encode(out).unwrap();
+}
+
+fn decode_pair(reader: &mut &[u8]) -> Result<(TxId, u32), SnapshotError> {
+    let txid = TxId(u32::consensus_decode(reader)?);
+    let n = u32::consensus_decode(reader)?;
+    Ok((txid, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        loose::LooseIndexBuilder,
+        test_utils::{DummyTxData, DummyTxOutData, temp_dir},
+    };
+
+    fn sample_index() -> InMemoryIndex {
+        let mut builder = LooseIndexBuilder::new();
+        builder
+            .add_tx(Arc::new(
+                DummyTxData::new_with_outputs(vec![
+                    DummyTxOutData::new_with_script(5_000, 0, vec![0x51]),
+                    DummyTxOutData::new_with_script(7_000, 1, vec![0x52]),
+                ])
+                .with_txid(TxId::new(1)),
+            ))
+            .add_tx(Arc::new(DummyTxData::new(
+                vec![DummyTxOutData::new_with_script(4_000, 0, vec![0x51])],
+                vec![TxOutId::new(TxId::new(1), 1)],
+                500_000,
+            )));
+        let mut index = builder.build();
+        index.mempool_info.insert(
+            TxId::new(2),
+            MempoolTxInfo {
+                time: 1_700_000_000,
+                fee_delta: -250,
+            },
+        );
+        index
+    }
+
+    #[test]
+    fn roundtrip_preserves_ids_links_and_transactions() {
+        let index = sample_index();
+        let path = temp_dir("loose_snapshot").join("index.snapshot");
+        index.save_snapshot(&path).unwrap();
+        let loaded = InMemoryIndex::load_snapshot(&path).unwrap();
+
+        assert_eq!(loaded.prev_txouts, index.prev_txouts);
+        assert_eq!(loaded.spending_txins, index.spending_txins);
+        assert_eq!(loaded.spk_to_txout_ids, index.spk_to_txout_ids);
+        assert_eq!(loaded.mempool_info, index.mempool_info);
+        assert_eq!(loaded.loose_txids, index.loose_txids);
+        assert_eq!(loaded.tx_order, index.tx_order);
+        assert_eq!(loaded.txs.len(), index.txs.len());
+        for (id, tx) in &index.txs {
+            let got = &loaded.txs[id];
+            assert_eq!(
+                to_bitcoin_tx(got.as_ref()),
+                to_bitcoin_tx(tx.as_ref()),
+                "tx {id:?}"
+            );
+        }
+
+        // A reloaded index saves to the same bytes.
+        assert_eq!(loaded.encode_snapshot(), index.encode_snapshot());
+    }
+
+    #[test]
+    fn rejects_foreign_files_and_unknown_versions() {
+        let mut bytes = sample_index().encode_snapshot();
+        assert!(matches!(
+            InMemoryIndex::decode_snapshot(&bytes[1..]),
+            Err(SnapshotError::NotASnapshot)
+        ));
+
+        bytes[SNAPSHOT_MAGIC.len()] = 1;
+        assert!(matches!(
+            InMemoryIndex::decode_snapshot(&bytes),
+            Err(SnapshotError::UnsupportedVersion(1))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_snapshot() {
+        let bytes = sample_index().encode_snapshot();
+        assert!(matches!(
+            InMemoryIndex::decode_snapshot(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Decode(_))
+        ));
+    }
+}
@@ -1,19 +1,138 @@
-use bitcoin::Script;
+use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_1, OP_PUSHNUM_16};
+use bitcoin::script::Instruction;
+use bitcoin::{Script, WitnessVersion};
+
+/// Pay-to-anchor script pubkey: `OP_1 <0x4e73>`.
+const P2A_SCRIPT: [u8; 4] = [0x51, 0x02, 0x4e, 0x73];
+
+/// Codes of [`OutputType::WitnessUnknown`] are this plus the witness version.
+const WITNESS_UNKNOWN_CODE: u32 = 0x100;
+/// Codes of [`OutputType::Multisig`] are this plus `m << 8 | n`.
+const MULTISIG_CODE: u32 = 0x1_0000;
+
+/// Storage codes of [`OutputType::WitnessUnknown`] are this plus the witness version.
+const WITNESS_UNKNOWN_STORAGE_CODE: u8 = 11;
+/// Storage codes of [`OutputType::Multisig`] are this plus the rank of `(m, n)` when ordered
+/// by `n`, then `m`.
+const MULTISIG_STORAGE_CODE: u8 = WITNESS_UNKNOWN_STORAGE_CODE + 17;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OutputType {
-    P2pkh = 0,
-    P2sh = 1,
-    P2wpkh = 2,
-    P2wsh = 3,
-    P2tr = 4,
-    OpReturn = 5,
-    NonStandard = 6,
-    // TODO: pay2anchor
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    OpReturn,
+    NonStandard,
+    /// Pay-to-pubkey, with a 33- or 65-byte key.
+    P2pk,
+    /// Pay-to-anchor (`OP_1 <0x4e73>`), the keyless output used for fee bumping.
+    P2a,
+    /// A P2SH output whose spending scriptSig reveals a P2WPKH redeem script. Only known at
+    /// spend time, see [`classify_spent_output`].
+    P2shP2wpkh,
+    /// A P2SH output whose spending scriptSig reveals a P2WSH redeem script. Only known at
+    /// spend time, see [`classify_spent_output`].
+    P2shP2wsh,
+    /// Bare `m`-of-`n` multisig, with `1 <= m <= n <= 16`.
+    Multisig {
+        m: u8,
+        n: u8,
+    },
+    /// Witness program of a version or length without defined semantics yet.
+    WitnessUnknown {
+        version: u8,
+    },
 }
 
 impl OutputType {
+    /// Stable numeric code, used in fingerprint vectors.
+    ///
+    /// Data-less types have codes below 16. [`OutputType::WitnessUnknown`] and
+    /// [`OutputType::Multisig`] embed their fields, so e.g. 1-of-2 and 2-of-3 multisig differ.
     pub fn as_u32(self) -> u32 {
-        self as u32
+        match self {
+            OutputType::P2pkh => 0,
+            OutputType::P2sh => 1,
+            OutputType::P2wpkh => 2,
+            OutputType::P2wsh => 3,
+            OutputType::P2tr => 4,
+            OutputType::OpReturn => 5,
+            OutputType::NonStandard => 6,
+            OutputType::P2pk => 7,
+            OutputType::P2a => 8,
+            OutputType::P2shP2wpkh => 9,
+            OutputType::P2shP2wsh => 10,
+            OutputType::WitnessUnknown { version } => WITNESS_UNKNOWN_CODE + version as u32,
+            OutputType::Multisig { m, n } => MULTISIG_CODE + ((m as u32) << 8 | n as u32),
+        }
+    }
+
+    /// Inverse of [`OutputType::as_u32`].
+    pub fn from_u32(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(OutputType::P2pkh),
+            1 => Some(OutputType::P2sh),
+            2 => Some(OutputType::P2wpkh),
+            3 => Some(OutputType::P2wsh),
+            4 => Some(OutputType::P2tr),
+            5 => Some(OutputType::OpReturn),
+            6 => Some(OutputType::NonStandard),
+            7 => Some(OutputType::P2pk),
+            8 => Some(OutputType::P2a),
+            9 => Some(OutputType::P2shP2wpkh),
+            10 => Some(OutputType::P2shP2wsh),
+            c if (WITNESS_UNKNOWN_CODE..=WITNESS_UNKNOWN_CODE + 16).contains(&c) => {
+                Some(OutputType::WitnessUnknown {
+                    version: (c - WITNESS_UNKNOWN_CODE) as u8,
+                })
+            }
+            c if (MULTISIG_CODE..MULTISIG_CODE + 0x1_0000).contains(&c) => {
+                let m = ((c - MULTISIG_CODE) >> 8) as u8;
+                let n = c as u8;
+                (1 <= m && m <= n && n <= 16).then_some(OutputType::Multisig { m, n })
+            }
+            _ => None,
+        }
+    }
+
+    /// One-byte code of the dense output type column.
+    ///
+    /// The 11 data-less types keep their [`OutputType::as_u32`] code, so columns written
+    /// before the extended types existed still decode. The 17 witness versions and the 136
+    /// valid multisig `(m, n)` pairs follow, 164 codes in all.
+    pub(crate) fn storage_code(self) -> u8 {
+        match self {
+            OutputType::WitnessUnknown { version } => WITNESS_UNKNOWN_STORAGE_CODE + version,
+            OutputType::Multisig { m, n } => MULTISIG_STORAGE_CODE + n * (n - 1) / 2 + (m - 1),
+            other => other.as_u32() as u8,
+        }
+    }
+
+    /// Inverse of [`OutputType::storage_code`].
+    pub(crate) fn from_storage_code(code: u8) -> Option<Self> {
+        match code {
+            c if c < WITNESS_UNKNOWN_STORAGE_CODE => Self::from_u32(c as u32),
+            c if c < MULTISIG_STORAGE_CODE => Some(OutputType::WitnessUnknown {
+                version: c - WITNESS_UNKNOWN_STORAGE_CODE,
+            }),
+            c => {
+                let rank = c - MULTISIG_STORAGE_CODE;
+                // `n` is the largest count whose pairs start at or before `rank`.
+                let n = (1..=16u8).take_while(|n| n * (n - 1) / 2 <= rank).last()?;
+                let m = rank - n * (n - 1) / 2 + 1;
+                (m <= n).then_some(OutputType::Multisig { m, n })
+            }
+        }
+    }
+
+    /// The type as far as the scriptPubKey alone tells: wrapped segwit is plain P2SH.
+    pub fn script_pubkey_type(self) -> OutputType {
+        match self {
+            OutputType::P2shP2wpkh | OutputType::P2shP2wsh => OutputType::P2sh,
+            other => other,
+        }
     }
 
     pub fn is_spendable(self) -> bool {
@@ -22,6 +141,9 @@ impl OutputType {
 }
 
 /// Classify a scriptPubKey by type from raw bytes.
+///
+/// P2SH-wrapped segwit cannot be told apart from other P2SH outputs here; use
+/// [`classify_spent_output`] once the output is spent.
 pub fn classify_script_pubkey(spk: &[u8]) -> OutputType {
     let script = Script::from_bytes(spk);
 
@@ -43,6 +165,236 @@ pub fn classify_script_pubkey(spk: &[u8]) -> OutputType {
     if script.is_p2tr() {
         return OutputType::P2tr;
     }
+    if spk == P2A_SCRIPT {
+        return OutputType::P2a;
+    }
+    // v0 programs other than P2WPKH and P2WSH have an invalid length and are unspendable.
+    if let Some(version) = script.witness_version()
+        && version != WitnessVersion::V0
+    {
+        return OutputType::WitnessUnknown {
+            version: version.to_num(),
+        };
+    }
+    if script.is_p2pk() {
+        return OutputType::P2pk;
+    }
+    if let Some((m, n)) = multisig_m_of_n(script) {
+        return OutputType::Multisig { m, n };
+    }
 
     OutputType::NonStandard
 }
+
+/// Classify the output an input spends, given the output's scriptPubKey and the input's
+/// scriptSig.
+///
+/// Same as [`classify_script_pubkey`], except that a P2SH output redeemed with a P2WPKH or
+/// P2WSH script is reported as [`OutputType::P2shP2wpkh`] or [`OutputType::P2shP2wsh`].
+pub fn classify_spent_output(spk: &[u8], script_sig: &[u8]) -> OutputType {
+    let output_type = classify_script_pubkey(spk);
+    if output_type != OutputType::P2sh {
+        return output_type;
+    }
+    // A wrapped segwit spend pushes the redeem script and nothing else.
+    let mut instructions = Script::from_bytes(script_sig).instructions();
+    let (Some(Ok(Instruction::PushBytes(redeem_script))), None) =
+        (instructions.next(), instructions.next())
+    else {
+        return output_type;
+    };
+    let redeem_script = Script::from_bytes(redeem_script.as_bytes());
+    if redeem_script.is_p2wpkh() {
+        OutputType::P2shP2wpkh
+    } else if redeem_script.is_p2wsh() {
+        OutputType::P2shP2wsh
+    } else {
+        output_type
+    }
+}
+
+/// `(m, n)` of a bare multisig script `OP_m <pubkey>... OP_n OP_CHECKMULTISIG`, matching
+/// what Bitcoin Core's standardness rules accept as multisig.
+pub(crate) fn multisig_m_of_n(script: &Script) -> Option<(u8, u8)> {
+    let pushnum = |instruction| match instruction {
+        Some(Ok(Instruction::Op(op)))
+            if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) =>
+        {
+            Some(op.to_u8() - OP_PUSHNUM_1.to_u8() + 1)
+        }
+        _ => None,
+    };
+
+    let mut instructions = script.instructions().peekable();
+    let m = pushnum(instructions.next())?;
+    let mut keys = 0u8;
+    while let Some(Ok(Instruction::PushBytes(key))) = instructions.peek() {
+        if !matches!(key.len(), 33 | 65) {
+            return None;
+        }
+        keys += 1;
+        instructions.next();
+    }
+    let n = pushnum(instructions.next())?;
+    match (instructions.next(), instructions.next()) {
+        (Some(Ok(Instruction::Op(OP_CHECKMULTISIG))), None) if n == keys && m <= n => Some((m, n)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    fn pubkey(len: usize) -> Vec<u8> {
+        let mut key = vec![0x02; len];
+        if len == 65 {
+            key[0] = 0x04;
+        }
+        key
+    }
+
+    fn multisig(m: u8, keys: &[Vec<u8>]) -> Vec<u8> {
+        let mut spk = vec![0x50 + m];
+        for key in keys {
+            spk.push(key.len() as u8);
+            spk.extend_from_slice(key);
+        }
+        spk.push(0x50 + keys.len() as u8);
+        spk.push(OP_CHECKMULTISIG.to_u8());
+        spk
+    }
+
+    #[test]
+    fn classifies_extended_types() {
+        let mut p2pk = vec![33];
+        p2pk.extend(pubkey(33));
+        p2pk.push(0xac);
+        assert_eq!(classify_script_pubkey(&p2pk), OutputType::P2pk);
+
+        assert_eq!(classify_script_pubkey(&P2A_SCRIPT), OutputType::P2a);
+
+        let mut witness_v2 = vec![0x52, 32];
+        witness_v2.extend([0xaa; 32]);
+        assert_eq!(
+            classify_script_pubkey(&witness_v2),
+            OutputType::WitnessUnknown { version: 2 }
+        );
+        // A v0 program of neither 20 nor 32 bytes is not a witness output.
+        let mut witness_v0 = vec![0x00, 24];
+        witness_v0.extend([0xaa; 24]);
+        assert_eq!(classify_script_pubkey(&witness_v0), OutputType::NonStandard);
+        // A v1 program that is neither taproot nor an anchor.
+        assert_eq!(
+            classify_script_pubkey(&[0x51, 0x02, 0x00, 0x00]),
+            OutputType::WitnessUnknown { version: 1 }
+        );
+
+        let keys = [pubkey(33), pubkey(65), pubkey(33)];
+        assert_eq!(
+            classify_script_pubkey(&multisig(2, &keys)),
+            OutputType::Multisig { m: 2, n: 3 }
+        );
+        // More signatures than keys, or keys of the wrong size, are not multisig.
+        assert_eq!(
+            classify_script_pubkey(&multisig(3, &keys[..2])),
+            OutputType::NonStandard
+        );
+        assert_eq!(
+            classify_script_pubkey(&multisig(1, &[pubkey(20)])),
+            OutputType::NonStandard
+        );
+    }
+
+    #[test]
+    fn detects_wrapped_segwit_at_spend_time() {
+        let p2sh = bitcoin::ScriptBuf::new_p2sh(&bitcoin::ScriptHash::from_byte_array([0; 20]));
+        let p2wpkh =
+            bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([0; 20]));
+        let p2wsh = bitcoin::ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::from_byte_array([0; 32]));
+        let push = |script: &bitcoin::ScriptBuf| {
+            let mut script_sig = vec![script.len() as u8];
+            script_sig.extend_from_slice(script.as_bytes());
+            script_sig
+        };
+
+        assert_eq!(
+            classify_spent_output(p2sh.as_bytes(), &push(&p2wpkh)),
+            OutputType::P2shP2wpkh
+        );
+        assert_eq!(
+            classify_spent_output(p2sh.as_bytes(), &push(&p2wsh)),
+            OutputType::P2shP2wsh
+        );
+        // A legacy P2SH spend pushes signatures before the redeem script.
+        let mut legacy = vec![0x00];
+        legacy.extend(push(&p2wpkh));
+        assert_eq!(
+            classify_spent_output(p2sh.as_bytes(), &legacy),
+            OutputType::P2sh
+        );
+        assert_eq!(
+            classify_spent_output(p2wpkh.as_bytes(), &push(&p2wpkh)),
+            OutputType::P2wpkh
+        );
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        let types = [
+            OutputType::P2pkh,
+            OutputType::P2sh,
+            OutputType::P2wpkh,
+            OutputType::P2wsh,
+            OutputType::P2tr,
+            OutputType::OpReturn,
+            OutputType::NonStandard,
+            OutputType::P2pk,
+            OutputType::P2a,
+            OutputType::P2shP2wpkh,
+            OutputType::P2shP2wsh,
+            OutputType::Multisig { m: 1, n: 1 },
+            OutputType::Multisig { m: 2, n: 3 },
+            OutputType::Multisig { m: 16, n: 16 },
+            OutputType::WitnessUnknown { version: 0 },
+            OutputType::WitnessUnknown { version: 16 },
+        ];
+        for t in types {
+            assert_eq!(OutputType::from_u32(t.as_u32()), Some(t), "{t:?}");
+        }
+        assert_eq!(OutputType::from_u32(11), None);
+        assert_eq!(OutputType::from_u32(MULTISIG_CODE + (3 << 8 | 2)), None);
+        assert_eq!(OutputType::from_u32(WITNESS_UNKNOWN_CODE + 17), None);
+    }
+
+    #[test]
+    fn storage_codes_fit_a_byte_and_round_trip() {
+        let decoded: Vec<_> = (0..=u8::MAX)
+            .filter_map(|code| Some((code, OutputType::from_storage_code(code)?)))
+            .collect();
+        assert_eq!(decoded.len(), 164);
+        for (code, t) in &decoded {
+            assert_eq!(t.storage_code(), *code, "{t:?}");
+        }
+        // Codes written before the extended types existed keep their meaning.
+        for code in 0..=6 {
+            assert_eq!(
+                OutputType::from_storage_code(code).map(OutputType::as_u32),
+                Some(code as u32)
+            );
+        }
+        assert_eq!(
+            OutputType::from_storage_code(MULTISIG_STORAGE_CODE),
+            Some(OutputType::Multisig { m: 1, n: 1 })
+        );
+        assert_eq!(
+            OutputType::from_storage_code(163),
+            Some(OutputType::Multisig { m: 16, n: 16 })
+        );
+        assert_eq!(
+            OutputType::Multisig { m: 2, n: 3 }.storage_code(),
+            MULTISIG_STORAGE_CODE + 4
+        );
+    }
+}
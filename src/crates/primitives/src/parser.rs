@@ -6,7 +6,12 @@ use bitcoin_slices::{Visit, Visitor, bsl};
 use core::ops::ControlFlow;
 
 use crate::{
-    blk_file::BlkFileStore, dense::BlockFileId, sled::spk_db::SledScriptPubkeyDbError,
+    blk_file::BlkFileStore,
+    dense::BlockFileId,
+    sled::{
+        spent_output_db::SledSpentOutputDbError, spk_db::SledScriptPubkeyDbError,
+        txid_db::SledTxidDbError,
+    },
     traits::IndexSink,
 };
 
@@ -27,6 +32,10 @@ pub fn collect_file_hints(
         let info = index
             .block_file_info(file_no)
             .map_err(BlockFileError::BlockIndex)?;
+        // Pruning resets a file's info, so a file without blocks no longer exists on disk.
+        if info.n_blocks == 0 {
+            continue;
+        }
         if (info.height_last as u64) < start_height {
             continue;
         }
@@ -55,6 +64,47 @@ pub struct BlkFileHint {
     pub data_len: Option<usize>,
 }
 
+/// Collect an [`UndoHint`] for every block of the best chain in the inclusive height range
+/// `[start_height, end_height]` ending at `tip_hash` that has undo data on disk.
+pub fn collect_undo_hints(
+    index: &mut bitcoin_block_index::BlockIndex,
+    tip_hash: &[u8; 32],
+    start_height: u64,
+    end_height: u64,
+) -> Result<Vec<UndoHint>, BlockFileError> {
+    let mut hash = *tip_hash;
+    let mut loc = index
+        .block_location(&hash)
+        .map_err(BlockFileError::BlockIndex)?;
+    while loc.height as u64 > end_height {
+        hash = loc.prev_hash;
+        loc = index
+            .block_location(&hash)
+            .map_err(BlockFileError::BlockIndex)?;
+    }
+    let chain = index
+        .walk_back(&hash, (end_height - start_height) as u32)
+        .map_err(BlockFileError::BlockIndex)?;
+    Ok(chain
+        .into_iter()
+        .filter_map(|loc| {
+            loc.undo_pos.map(|undo_pos| UndoHint {
+                height: loc.height as u64,
+                file_no: loc.n_file,
+                undo_pos,
+            })
+        })
+        .collect())
+}
+
+/// Location of a block's undo record in the rev*.dat files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoHint {
+    pub height: u64,
+    pub file_no: u32,
+    pub undo_pos: u32,
+}
+
 impl Default for BlkFileHint {
     fn default() -> Self {
         Self {
@@ -70,6 +120,7 @@ impl Default for BlkFileHint {
 pub struct Parser {
     store: BlkFileStore,
     file_hints: Vec<BlkFileHint>,
+    threads: usize,
 }
 
 impl Parser {
@@ -77,6 +128,7 @@ impl Parser {
         Self {
             store: BlkFileStore::open(blocks_dir),
             file_hints: Vec::new(),
+            threads: 1,
         }
     }
 
@@ -85,6 +137,17 @@ impl Parser {
         self
     }
 
+    /// Decode up to `threads` blk files concurrently. The sink still sees every block in
+    /// chain order, so the emitted events do not depend on the thread count.
+    ///
+    /// Each in-flight file is held in memory while it waits to be replayed into the sink,
+    /// and the next batch is decoded while the current one is replayed, so up to
+    /// `2 * threads` files are buffered at once.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
     pub fn blocks_dir(&self) -> &Path {
         self.store.blocks_dir()
     }
@@ -109,128 +172,298 @@ impl Parser {
         } else {
             &self.file_hints
         };
+        let last_height = range.end.saturating_sub(1);
+        let hints_in_range = hints
+            .iter()
+            .take_while(|hint| hint.height_first as u64 <= last_height)
+            .count();
+        let hints = &hints[..hints_in_range];
         log::debug!("Starting to parse blocks in range: {:?}", range);
         let parse_start = std::time::Instant::now();
 
-        'files: for hint in hints {
-            let file_no = hint.file_no;
-            let file_first = hint.height_first as u64;
-            if file_first > range.end.saturating_sub(1) {
+        if self.threads <= 1 {
+            self.parse_sequential(hints, &range, sink)?;
+        } else {
+            self.parse_parallel(hints, &range, sink)?;
+        }
+
+        log::debug!(
+            "Parsing blocks took {} seconds",
+            parse_start.elapsed().as_secs_f64()
+        );
+        Ok(())
+    }
+
+    /// Decode and replay one block at a time.
+    fn parse_sequential<S>(
+        &self,
+        hints: &[BlkFileHint],
+        range: &std::ops::Range<u64>,
+        sink: &mut S,
+    ) -> Result<(), BlockFileError>
+    where
+        S: IndexSink,
+        BlockFileError: From<S::Error>,
+    {
+        for hint in hints {
+            let reached_end =
+                for_each_block_in_range(&self.store, hint, range, |block| block.replay(sink))?;
+            if reached_end {
                 break;
             }
+        }
+        Ok(())
+    }
 
-            let file_id = BlockFileId(file_no);
-            let mut global_height = file_first;
+    /// Decode `threads` files at a time on worker threads and replay them in file order.
+    fn parse_parallel<S>(
+        &self,
+        hints: &[BlkFileHint],
+        range: &std::ops::Range<u64>,
+        sink: &mut S,
+    ) -> Result<(), BlockFileError>
+    where
+        S: IndexSink,
+        BlockFileError: From<S::Error>,
+    {
+        let store = &self.store;
+        std::thread::scope(|scope| {
+            let spawn_batch = |batch: &[BlkFileHint]| {
+                batch
+                    .iter()
+                    .map(|&hint| scope.spawn(move || decode_file(store, &hint, range)))
+                    .collect::<Vec<_>>()
+            };
+            let mut batches = hints.chunks(self.threads);
+            let mut in_flight = batches.next().map(spawn_batch);
+            while let Some(handles) = in_flight.take() {
+                in_flight = batches.next().map(spawn_batch);
+                for handle in handles {
+                    let file = handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+                    for block in &file.blocks {
+                        block.replay(sink)?;
+                    }
+                    if file.reached_end {
+                        // Later files are still joined by the scope, but never replayed.
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
 
-            // `global_height` is being used a loop counter
-            #[allow(clippy::explicit_counter_loop)]
-            for result in self.store.iter_blocks(file_no, hint.data_len) {
-                let (block_start, block_bytes) = result.map_err(BlockFileError::Io)?;
+/// Every in-range block of one blk file, decoded off the sink's thread.
+struct DecodedFile {
+    blocks: Vec<DecodedBlock>,
+    /// The file contained the last block of the range, so later files are not needed.
+    reached_end: bool,
+}
 
-                if global_height >= range.end {
-                    break 'files;
-                }
+fn decode_file(
+    store: &BlkFileStore,
+    hint: &BlkFileHint,
+    range: &std::ops::Range<u64>,
+) -> Result<DecodedFile, BlockFileError> {
+    let mut blocks = Vec::new();
+    let reached_end = for_each_block_in_range(store, hint, range, |block| {
+        blocks.push(block);
+        Ok(())
+    })?;
+    Ok(DecodedFile {
+        blocks,
+        reached_end,
+    })
+}
 
-                if global_height >= range.start {
-                    let tx_count = {
-                        let mut collector = TxIdCollector {
-                            block_file: file_id,
-                            block_start_in_file: block_start,
-                            block_slice: &block_bytes,
-                            sink,
-                            error: None,
-                            tx_count: 0,
-                        };
-                        bsl::Block::visit(&block_bytes, &mut collector)
-                            .map_err(BlockFileError::Parse)?;
-                        if let Some(error) = collector.error.take() {
-                            return Err(error);
-                        }
-                        collector.tx_count
-                    };
-                    sink.on_block_end(tx_count).map_err(BlockFileError::from)?;
-                }
+/// Decode every block of `hint`'s file whose height falls in `range` and pass it to `f`.
+/// Returns whether the end of the range was reached within this file.
+fn for_each_block_in_range(
+    store: &BlkFileStore,
+    hint: &BlkFileHint,
+    range: &std::ops::Range<u64>,
+    mut f: impl FnMut(DecodedBlock) -> Result<(), BlockFileError>,
+) -> Result<bool, BlockFileError> {
+    let file_id = BlockFileId(hint.file_no);
+    let mut global_height = hint.height_first as u64;
 
-                global_height += 1;
-            }
+    // `global_height` is being used a loop counter
+    #[allow(clippy::explicit_counter_loop)]
+    for result in store.iter_blocks(hint.file_no, hint.data_len) {
+        let (block_start, block_bytes) = result.map_err(BlockFileError::Io)?;
+
+        if global_height >= range.end {
+            return Ok(true);
         }
 
-        log::debug!(
-            "Parsing blocks took {} seconds",
-            parse_start.elapsed().as_secs_f64()
-        );
+        if global_height >= range.start {
+            f(DecodedBlock::decode(file_id, block_start, block_bytes)?)?;
+        }
+
+        global_height += 1;
+    }
+    Ok(false)
+}
+
+/// A block with everything an [`IndexSink`] needs already extracted, so the expensive
+/// part of parsing (visiting and txid hashing) can run away from the sink.
+struct DecodedBlock {
+    bytes: Vec<u8>,
+    header: [u8; 80],
+    block_hash: [u8; 32],
+    txs: Vec<DecodedTx>,
+    /// `(prev_txid, prev_vout)` of every input in the block, in order.
+    inputs: Vec<([u8; 32], u32)>,
+    /// `(value, script_pubkey range in bytes)` of every output in the block, in order.
+    outputs: Vec<(u64, std::ops::Range<usize>)>,
+}
+
+struct DecodedTx {
+    txid: [u8; 32],
+    blk_file_no: u32,
+    blk_file_off: u32,
+    bytes: std::ops::Range<usize>,
+    /// End of this tx's entries in [`DecodedBlock::inputs`].
+    inputs_end: usize,
+    /// End of this tx's entries in [`DecodedBlock::outputs`].
+    outputs_end: usize,
+}
+
+impl DecodedBlock {
+    fn decode(
+        block_file: BlockFileId,
+        block_start_in_file: u64,
+        bytes: Vec<u8>,
+    ) -> Result<Self, BlockFileError> {
+        let mut collector = BlockDecoder {
+            block_file,
+            block_start_in_file,
+            block_slice: &bytes,
+            error: None,
+            header: [0u8; 80],
+            block_hash: [0u8; 32],
+            txs: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+        bsl::Block::visit(&bytes, &mut collector).map_err(BlockFileError::Parse)?;
+        if let Some(error) = collector.error.take() {
+            return Err(error);
+        }
+        let BlockDecoder {
+            header,
+            block_hash,
+            txs,
+            inputs,
+            outputs,
+            ..
+        } = collector;
+        Ok(Self {
+            bytes,
+            header,
+            block_hash,
+            txs,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Emit this block's events to `sink` in the order the parser always has: per tx its
+    /// inputs, its outputs and then the tx itself, followed by the end of the block.
+    fn replay<S>(&self, sink: &mut S) -> Result<(), BlockFileError>
+    where
+        S: IndexSink,
+        BlockFileError: From<S::Error>,
+    {
+        let mut inputs_start = 0;
+        let mut outputs_start = 0;
+        for tx in &self.txs {
+            for (vin, (prev_txid, prev_vout)) in
+                self.inputs[inputs_start..tx.inputs_end].iter().enumerate()
+            {
+                sink.on_input(vin, prev_txid, *prev_vout)?;
+            }
+            for (vout, (value, script_pubkey)) in self.outputs[outputs_start..tx.outputs_end]
+                .iter()
+                .enumerate()
+            {
+                sink.on_output(vout, *value, &self.bytes[script_pubkey.clone()])?;
+            }
+            sink.on_transaction(
+                &tx.txid,
+                tx.blk_file_no,
+                tx.blk_file_off,
+                tx.bytes.len() as u32,
+                &self.bytes[tx.bytes.clone()],
+            )?;
+            inputs_start = tx.inputs_end;
+            outputs_start = tx.outputs_end;
+        }
+        sink.on_block_end(&self.header, &self.block_hash, self.txs.len() as u64)?;
         Ok(())
     }
 }
 
-struct TxIdCollector<'a, S> {
+struct BlockDecoder<'a> {
     block_file: BlockFileId,
     block_start_in_file: u64,
     block_slice: &'a [u8],
-    sink: &'a mut S,
     error: Option<BlockFileError>,
-    tx_count: u64,
+    header: [u8; 80],
+    block_hash: [u8; 32],
+    txs: Vec<DecodedTx>,
+    inputs: Vec<([u8; 32], u32)>,
+    outputs: Vec<(u64, std::ops::Range<usize>)>,
+}
+
+impl BlockDecoder<'_> {
+    fn offset_in_block(&self, slice: &[u8]) -> usize {
+        slice.as_ptr() as usize - self.block_slice.as_ptr() as usize
+    }
 }
 
-impl<S> Visitor for TxIdCollector<'_, S>
-where
-    S: IndexSink,
-    BlockFileError: From<S::Error>,
-{
-    fn visit_tx_in(&mut self, vin: usize, tx_in: &bsl::TxIn<'_>) -> ControlFlow<()> {
+impl Visitor for BlockDecoder<'_> {
+    fn visit_block_header(&mut self, header: &bsl::BlockHeader<'_>) -> ControlFlow<()> {
+        self.header.copy_from_slice(header.as_ref());
+        self.block_hash = header.block_hash().to_byte_array();
+        ControlFlow::Continue(())
+    }
+
+    fn visit_tx_in(&mut self, _vin: usize, tx_in: &bsl::TxIn<'_>) -> ControlFlow<()> {
         let prevout = tx_in.prevout();
-        let prev_txid = <&[u8; 32]>::try_from(prevout.txid()).expect("prevout txid is 32 bytes");
-        if let Err(e) = self
-            .sink
-            .on_input(vin, prev_txid, prevout.vout())
-            .map_err(BlockFileError::from)
-        {
-            self.error = Some(e);
-            return ControlFlow::Break(());
-        }
+        let prev_txid = <[u8; 32]>::try_from(prevout.txid()).expect("prevout txid is 32 bytes");
+        self.inputs.push((prev_txid, prevout.vout()));
         ControlFlow::Continue(())
     }
 
-    fn visit_tx_out(&mut self, vout: usize, tx_out: &bsl::TxOut<'_>) -> ControlFlow<()> {
-        if let Err(e) = self
-            .sink
-            .on_output(vout, tx_out.script_pubkey())
-            .map_err(BlockFileError::from)
-        {
-            self.error = Some(e);
-            return ControlFlow::Break(());
-        }
+    fn visit_tx_out(&mut self, _vout: usize, tx_out: &bsl::TxOut<'_>) -> ControlFlow<()> {
+        let script_pubkey = tx_out.script_pubkey();
+        let start = self.offset_in_block(script_pubkey);
+        self.outputs
+            .push((tx_out.value(), start..start + script_pubkey.len()));
         ControlFlow::Continue(())
     }
 
     fn visit_transaction(&mut self, tx: &bsl::Transaction<'_>) -> ControlFlow<()> {
-        if self.error.is_some() {
-            return ControlFlow::Break(());
-        }
         let tx_slice = tx.as_ref();
         let tx_len = tx_slice.len();
         if tx_len > u32::MAX as usize {
             self.error = Some(BlockFileError::CorruptId());
             return ControlFlow::Break(());
         }
-        let offset_in_block = tx_slice.as_ptr() as usize - self.block_slice.as_ptr() as usize;
+        let offset_in_block = self.offset_in_block(tx_slice);
         let file_offset = self.block_start_in_file + offset_in_block as u64;
-        let txid = tx.txid().to_byte_array();
-        if let Err(e) = self
-            .sink
-            .on_transaction(
-                &txid,
-                self.block_file.0,
-                file_offset as u32,
-                tx_len as u32,
-                tx_slice,
-            )
-            .map_err(BlockFileError::from)
-        {
-            self.error = Some(e);
-            return ControlFlow::Break(());
-        }
-        self.tx_count += 1;
+        self.txs.push(DecodedTx {
+            txid: tx.txid().to_byte_array(),
+            blk_file_no: self.block_file.0,
+            blk_file_off: file_offset as u32,
+            bytes: offset_in_block..offset_in_block + tx_len,
+            inputs_end: self.inputs.len(),
+            outputs_end: self.outputs.len(),
+        });
         ControlFlow::Continue(())
     }
 }
@@ -238,11 +471,22 @@ where
 #[derive(Debug)]
 pub enum BlockFileError {
     Io(std::io::Error),
-    UnexpectedEof { offset: usize, len: usize },
+    UnexpectedEof {
+        offset: usize,
+        len: usize,
+    },
     Parse(bitcoin_slices::Error),
     SpkDb(SledScriptPubkeyDbError),
+    TxidDb(SledTxidDbError),
+    SpentOutputDb(SledSpentOutputDbError),
     CorruptId(),
     BlockIndex(bitcoin_block_index::Error),
+    /// The node pruned blocks the index needs: `height` is required but only blocks from
+    /// `first_stored` on are still on disk.
+    Pruned {
+        height: u64,
+        first_stored: u64,
+    },
 }
 
 impl From<std::convert::Infallible> for BlockFileError {
@@ -260,8 +504,17 @@ impl std::fmt::Display for BlockFileError {
             }
             BlockFileError::Parse(e) => write!(f, "parse: {:?}", e),
             BlockFileError::SpkDb(e) => write!(f, "spk db: {:?}", e),
+            BlockFileError::TxidDb(e) => write!(f, "txid db: {:?}", e),
+            BlockFileError::SpentOutputDb(e) => write!(f, "spent output db: {:?}", e),
             BlockFileError::CorruptId() => write!(f, "corrupt id"),
             BlockFileError::BlockIndex(e) => write!(f, "block index: {e}"),
+            BlockFileError::Pruned {
+                height,
+                first_stored,
+            } => write!(
+                f,
+                "block {height} was pruned; the node only stores blocks from height {first_stored}"
+            ),
         }
     }
 }
@@ -273,8 +526,11 @@ impl std::error::Error for BlockFileError {
             BlockFileError::Parse(_)
             | BlockFileError::UnexpectedEof { .. }
             | BlockFileError::SpkDb(_)
+            | BlockFileError::TxidDb(_)
+            | BlockFileError::SpentOutputDb(_)
             | BlockFileError::CorruptId()
-            | BlockFileError::BlockIndex(_) => None,
+            | BlockFileError::BlockIndex(_)
+            | BlockFileError::Pruned { .. } => None,
         }
     }
 }
@@ -1,6 +1,8 @@
 use std::path::Path;
 
-use crate::sled::spk_db::{SPK_TREE_NAME, SledScriptPubkeyDb};
+use crate::sled::spent_output_db::{SPENT_OUTPUT_TREE_NAME, SledSpentOutputDb};
+use crate::sled::spk_db::{LEGACY_SPK_TREE_NAME, SPK_TREE_NAME, SledScriptPubkeyDb};
+use crate::sled::txid_db::{SledTxidDb, TXID_TREE_NAME};
 use sled::Db;
 
 pub struct SledDBFactory {
@@ -18,4 +20,28 @@ impl SledDBFactory {
             self.db.open_tree(SPK_TREE_NAME)?,
         ))
     }
+
+    /// Whether the database still holds the first-output-only script pubkey tree, which
+    /// must be rebuilt from the indexed outputs before the spk db can be used.
+    pub fn has_legacy_spk_tree(&self) -> bool {
+        self.db
+            .tree_names()
+            .iter()
+            .any(|name| name.as_ref() == LEGACY_SPK_TREE_NAME.as_bytes())
+    }
+
+    pub fn drop_legacy_spk_tree(&self) -> Result<(), sled::Error> {
+        self.db.drop_tree(LEGACY_SPK_TREE_NAME)?;
+        Ok(())
+    }
+
+    pub fn txid_db(&self) -> Result<SledTxidDb, sled::Error> {
+        Ok(SledTxidDb::from_tree(self.db.open_tree(TXID_TREE_NAME)?))
+    }
+
+    pub fn spent_output_db(&self) -> Result<SledSpentOutputDb, sled::Error> {
+        Ok(SledSpentOutputDb::from_tree(
+            self.db.open_tree(SPENT_OUTPUT_TREE_NAME)?,
+        ))
+    }
 }
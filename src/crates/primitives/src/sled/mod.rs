@@ -1,2 +1,4 @@
 pub mod db;
+pub mod spent_output_db;
 pub mod spk_db;
+pub mod txid_db;
@@ -0,0 +1,144 @@
+use bitcoin_block_index::undo::SpentOutput;
+use sled::{IVec, Tree};
+
+use crate::dense::TxInId;
+use crate::traits::SpentOutputDb;
+
+pub const SPENT_OUTPUT_TREE_NAME: &str = "undo_spent_outputs";
+/// value (8) || height (4) || is_coinbase (1), followed by the script pubkey.
+const FIXED_LEN: usize = 13;
+
+#[derive(Debug)]
+pub enum SledSpentOutputDbError {
+    Backend(sled::Error),
+    Serialization(String),
+}
+
+impl std::error::Error for SledSpentOutputDbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SledSpentOutputDbError::Backend(e) => Some(e),
+            SledSpentOutputDbError::Serialization(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SledSpentOutputDbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SledSpentOutputDbError::Backend(e) => write!(f, "backend error: {}", e),
+            SledSpentOutputDbError::Serialization(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+/// Sled tree holding the spent outputs of dense inputs whose prevout lies before the indexed
+/// range, keyed by big-endian TxInId so range removals follow chain order.
+pub struct SledSpentOutputDb {
+    tree: Tree,
+}
+
+impl SledSpentOutputDb {
+    pub fn from_tree(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    fn encode(spent: &SpentOutput) -> IVec {
+        let mut value = Vec::with_capacity(FIXED_LEN + spent.script_pubkey.len());
+        value.extend_from_slice(&spent.value.to_le_bytes());
+        value.extend_from_slice(&spent.height.to_le_bytes());
+        value.push(spent.is_coinbase as u8);
+        value.extend_from_slice(&spent.script_pubkey);
+        IVec::from(value)
+    }
+
+    fn decode(raw: &[u8]) -> Result<SpentOutput, SledSpentOutputDbError> {
+        if raw.len() < FIXED_LEN {
+            return Err(SledSpentOutputDbError::Serialization(format!(
+                "expected at least {FIXED_LEN} bytes, got {}",
+                raw.len()
+            )));
+        }
+        Ok(SpentOutput {
+            value: u64::from_le_bytes(raw[0..8].try_into().expect("8 bytes")),
+            height: u32::from_le_bytes(raw[8..12].try_into().expect("4 bytes")),
+            is_coinbase: raw[12] != 0,
+            script_pubkey: raw[FIXED_LEN..].to_vec(),
+        })
+    }
+}
+
+impl SpentOutputDb for SledSpentOutputDb {
+    type Error = SledSpentOutputDbError;
+
+    fn get(&self, in_id: TxInId) -> Result<Option<SpentOutput>, Self::Error> {
+        let value = self
+            .tree
+            .get(in_id.index().to_be_bytes())
+            .map_err(SledSpentOutputDbError::Backend)?;
+        value.map(|raw| Self::decode(raw.as_ref())).transpose()
+    }
+
+    fn insert(&mut self, in_id: TxInId, spent: &SpentOutput) -> Result<(), Self::Error> {
+        self.tree
+            .insert(in_id.index().to_be_bytes(), Self::encode(spent))
+            .map_err(SledSpentOutputDbError::Backend)?;
+        Ok(())
+    }
+
+    fn remove_from(&mut self, in_id: TxInId) -> Result<(), Self::Error> {
+        for entry in self.tree.range(in_id.index().to_be_bytes()..) {
+            let (key, _) = entry.map_err(SledSpentOutputDbError::Backend)?;
+            self.tree
+                .remove(key)
+                .map_err(SledSpentOutputDbError::Backend)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_tree() -> (tempfile::TempDir, Tree) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let tree = db.open_tree(SPENT_OUTPUT_TREE_NAME).unwrap();
+        (dir, tree)
+    }
+
+    fn spent(value: u64) -> SpentOutput {
+        SpentOutput {
+            height: 700_000,
+            is_coinbase: value.is_multiple_of(2),
+            value,
+            script_pubkey: vec![0x00, 0x14, value as u8],
+        }
+    }
+
+    #[test]
+    fn insert_then_get_roundtrips() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledSpentOutputDb::from_tree(tree);
+
+        assert_eq!(db.get(TxInId::new(5)).unwrap(), None);
+        db.insert(TxInId::new(5), &spent(1_234)).unwrap();
+        assert_eq!(db.get(TxInId::new(5)).unwrap(), Some(spent(1_234)));
+    }
+
+    #[test]
+    fn remove_from_drops_later_inputs_only() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledSpentOutputDb::from_tree(tree);
+        for id in [1, 255, 256, 1_000] {
+            db.insert(TxInId::new(id), &spent(id)).unwrap();
+        }
+
+        db.remove_from(TxInId::new(256)).unwrap();
+        assert_eq!(db.get(TxInId::new(1)).unwrap(), Some(spent(1)));
+        assert_eq!(db.get(TxInId::new(255)).unwrap(), Some(spent(255)));
+        assert_eq!(db.get(TxInId::new(256)).unwrap(), None);
+        assert_eq!(db.get(TxInId::new(1_000)).unwrap(), None);
+    }
+}
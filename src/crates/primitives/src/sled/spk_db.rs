@@ -2,8 +2,13 @@ use crate::traits::ScriptPubkeyDb;
 use crate::{ScriptPubkeyHash, dense::TxOutId};
 use sled::{IVec, Tree};
 
-pub const SPK_TREE_NAME: &str = "script_pubkey_index";
+pub const SPK_TREE_NAME: &str = "script_pubkey_outputs";
+/// Tree of indexes built before every output was indexed, holding only the first output of
+/// each script pubkey. It is rebuilt into [`SPK_TREE_NAME`] on open.
+pub const LEGACY_SPK_TREE_NAME: &str = "script_pubkey_index";
+const SPK_HASH_LEN: usize = 20;
 const OUT_ID_LEN: usize = 8;
+const KEY_LEN: usize = SPK_HASH_LEN + OUT_ID_LEN;
 
 #[derive(Debug)]
 pub enum SledScriptPubkeyDbError {
@@ -29,6 +34,9 @@ impl std::fmt::Display for SledScriptPubkeyDbError {
     }
 }
 
+/// Sled tree holding one empty-valued entry per output, keyed by
+/// `spk_hash || out_id` with the out id big-endian, so a prefix scan over a script pubkey
+/// hash yields its outputs in chain order.
 pub struct SledScriptPubkeyDb {
     tree: Tree,
 }
@@ -38,24 +46,36 @@ impl SledScriptPubkeyDb {
         Self { tree }
     }
 
-    fn key_bytes(spk_hash: &ScriptPubkeyHash) -> &[u8] {
-        spk_hash.as_ref()
+    fn key_bytes(spk_hash: &ScriptPubkeyHash, out_id: TxOutId) -> [u8; KEY_LEN] {
+        let mut key = [0u8; KEY_LEN];
+        key[..SPK_HASH_LEN].copy_from_slice(spk_hash);
+        key[SPK_HASH_LEN..].copy_from_slice(&out_id.index().to_be_bytes());
+        key
     }
 
-    fn encode_out_id(out_id: TxOutId) -> IVec {
-        IVec::from(out_id.index().to_le_bytes().to_vec())
+    /// Every `(spk_hash, out_id)` entry, ordered by script pubkey hash.
+    pub fn entries(
+        &self,
+    ) -> impl Iterator<Item = Result<(ScriptPubkeyHash, TxOutId), SledScriptPubkeyDbError>> + '_
+    {
+        self.tree.iter().map(|entry| {
+            let (key, _) = entry.map_err(SledScriptPubkeyDbError::Backend)?;
+            let out_id = Self::decode_out_id(key.as_ref())?;
+            let spk_hash = key[..SPK_HASH_LEN].try_into().expect("key length checked");
+            Ok((spk_hash, out_id))
+        })
     }
 
     fn decode_out_id(raw: &[u8]) -> Result<TxOutId, SledScriptPubkeyDbError> {
-        if raw.len() != OUT_ID_LEN {
+        if raw.len() != KEY_LEN {
             return Err(SledScriptPubkeyDbError::Serilaization(format!(
-                "expected {OUT_ID_LEN}-byte TxOutId, got {} bytes",
+                "expected {KEY_LEN}-byte key, got {} bytes",
                 raw.len()
             )));
         }
         let mut bytes = [0u8; OUT_ID_LEN];
-        bytes.copy_from_slice(raw);
-        Ok(TxOutId::new(u64::from_le_bytes(bytes)))
+        bytes.copy_from_slice(&raw[SPK_HASH_LEN..]);
+        Ok(TxOutId::new(u64::from_be_bytes(bytes)))
     }
 }
 
@@ -63,31 +83,44 @@ impl ScriptPubkeyDb for SledScriptPubkeyDb {
     type Error = SledScriptPubkeyDbError;
 
     fn get(&self, spk_hash: &ScriptPubkeyHash) -> Result<Option<TxOutId>, Self::Error> {
-        let key = Self::key_bytes(spk_hash);
-        let value = self
-            .tree
-            .get(key)
-            .map_err(SledScriptPubkeyDbError::Backend)?;
-        match value {
-            Some(raw) => Ok(Some(Self::decode_out_id(raw.as_ref())?)),
+        match self.tree.scan_prefix(spk_hash).next() {
+            Some(entry) => {
+                let (key, _) = entry.map_err(SledScriptPubkeyDbError::Backend)?;
+                Ok(Some(Self::decode_out_id(key.as_ref())?))
+            }
             None => Ok(None),
         }
     }
 
-    fn insert_if_absent(
+    fn out_ids(&self, spk_hash: &ScriptPubkeyHash) -> Result<Vec<TxOutId>, Self::Error> {
+        self.tree
+            .scan_prefix(spk_hash)
+            .map(|entry| {
+                let (key, _) = entry.map_err(SledScriptPubkeyDbError::Backend)?;
+                Self::decode_out_id(key.as_ref())
+            })
+            .collect()
+    }
+
+    fn insert(
         &mut self,
         spk_hash: ScriptPubkeyHash,
         out_id: TxOutId,
     ) -> Result<(), SledScriptPubkeyDbError> {
-        let key = Self::key_bytes(&spk_hash);
-        let value = Self::encode_out_id(out_id);
-        let result = self
-            .tree
-            .compare_and_swap(key, None as Option<&[u8]>, Some(value))
+        self.tree
+            .insert(Self::key_bytes(&spk_hash, out_id), IVec::default())
+            .map_err(SledScriptPubkeyDbError::Backend)?;
+        Ok(())
+    }
+
+    fn remove(
+        &mut self,
+        spk_hash: &ScriptPubkeyHash,
+        out_id: TxOutId,
+    ) -> Result<(), SledScriptPubkeyDbError> {
+        self.tree
+            .remove(Self::key_bytes(spk_hash, out_id))
             .map_err(SledScriptPubkeyDbError::Backend)?;
-        if result.is_err() {
-            return Ok(());
-        }
         Ok(())
     }
 }
@@ -122,25 +155,44 @@ mod tests {
         let key = spk_hash([2u8; 20]);
         let out_id = TxOutId::new(100);
 
-        db.insert_if_absent(key, out_id).unwrap();
+        db.insert(key, out_id).unwrap();
         let got = db.get(&key).unwrap();
         assert_eq!(got, Some(out_id));
     }
 
     #[test]
-    fn insert_if_absent_does_not_overwrite() {
+    fn get_returns_first_out_id() {
         let (_dir, tree) = temp_tree();
         let mut db = SledScriptPubkeyDb::from_tree(tree);
         let key = spk_hash([3u8; 20]);
         let first = TxOutId::new(10);
         let second = TxOutId::new(20);
 
-        db.insert_if_absent(key, first).unwrap();
-        db.insert_if_absent(key, second).unwrap();
+        db.insert(key, second).unwrap();
+        db.insert(key, first).unwrap();
         let got = db.get(&key).unwrap();
         assert_eq!(got, Some(first));
     }
 
+    #[test]
+    fn out_ids_are_in_chain_order() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledScriptPubkeyDb::from_tree(tree);
+        let key = spk_hash([8u8; 20]);
+        let other = spk_hash([9u8; 20]);
+
+        for id in [300, 2, 256, 1] {
+            db.insert(key, TxOutId::new(id)).unwrap();
+        }
+        db.insert(other, TxOutId::new(3)).unwrap();
+        assert_eq!(
+            db.out_ids(&key).unwrap(),
+            [1, 2, 256, 300].map(TxOutId::new).to_vec()
+        );
+        assert_eq!(db.out_ids(&other).unwrap(), vec![TxOutId::new(3)]);
+        assert!(db.out_ids(&spk_hash([0u8; 20])).unwrap().is_empty());
+    }
+
     #[test]
     fn different_spk_hashes_stored_separately() {
         let (_dir, tree) = temp_tree();
@@ -154,12 +206,29 @@ mod tests {
         let out_a = TxOutId::new(1);
         let out_b = TxOutId::new(2);
 
-        db.insert_if_absent(key_a, out_a).unwrap();
-        db.insert_if_absent(key_b, out_b).unwrap();
+        db.insert(key_a, out_a).unwrap();
+        db.insert(key_b, out_b).unwrap();
         assert_eq!(db.get(&key_a).unwrap(), Some(out_a));
         assert_eq!(db.get(&key_b).unwrap(), Some(out_b));
     }
 
+    #[test]
+    fn remove_only_drops_matching_out_id() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledScriptPubkeyDb::from_tree(tree);
+        let key = spk_hash([7u8; 20]);
+        let first = TxOutId::new(10);
+        let second = TxOutId::new(20);
+
+        db.insert(key, first).unwrap();
+        db.insert(key, second).unwrap();
+        db.remove(&key, first).unwrap();
+        assert_eq!(db.out_ids(&key).unwrap(), vec![second]);
+
+        db.remove(&key, second).unwrap();
+        assert_eq!(db.get(&key).unwrap(), None);
+    }
+
     #[test]
     fn roundtrip_high_index() {
         let (_dir, tree) = temp_tree();
@@ -167,7 +236,7 @@ mod tests {
         let key = spk_hash([6u8; 20]);
         let out_id = TxOutId::new(u64::MAX);
 
-        db.insert_if_absent(key, out_id).unwrap();
+        db.insert(key, out_id).unwrap();
         assert_eq!(db.get(&key).unwrap(), Some(out_id));
     }
 }
@@ -0,0 +1,174 @@
+use crate::dense::TxId;
+use crate::traits::TxidDb;
+use sled::{IVec, Tree};
+
+pub const TXID_TREE_NAME: &str = "txid_index";
+const TX_ID_LEN: usize = 4;
+
+#[derive(Debug)]
+pub enum SledTxidDbError {
+    Backend(sled::Error),
+    Serialization(String),
+}
+
+impl std::error::Error for SledTxidDbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SledTxidDbError::Backend(e) => Some(e),
+            SledTxidDbError::Serialization(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SledTxidDbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SledTxidDbError::Backend(e) => write!(f, "backend error: {}", e),
+            SledTxidDbError::Serialization(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+/// Sled tree mapping txids (internal byte order) to dense TxIds.
+///
+/// A txid indexed again (the BIP30 duplicate coinbases) resolves to the latest copy. The
+/// mapping it replaced is kept under the txid followed by the new TxId, so removing the
+/// latest copy restores it.
+pub struct SledTxidDb {
+    tree: Tree,
+}
+
+impl SledTxidDb {
+    pub fn from_tree(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    fn encode_tx_id(txid: TxId) -> IVec {
+        IVec::from(txid.index().to_le_bytes().to_vec())
+    }
+
+    fn shadowed_key(txid: &[u8; 32], dense_txid: TxId) -> Vec<u8> {
+        [&txid[..], &dense_txid.index().to_le_bytes()].concat()
+    }
+
+    fn decode_tx_id(raw: &[u8]) -> Result<TxId, SledTxidDbError> {
+        if raw.len() != TX_ID_LEN {
+            return Err(SledTxidDbError::Serialization(format!(
+                "expected {TX_ID_LEN}-byte TxId, got {} bytes",
+                raw.len()
+            )));
+        }
+        let mut bytes = [0u8; TX_ID_LEN];
+        bytes.copy_from_slice(raw);
+        Ok(TxId::new(u32::from_le_bytes(bytes)))
+    }
+}
+
+impl TxidDb for SledTxidDb {
+    type Error = SledTxidDbError;
+
+    fn get(&self, txid: &[u8; 32]) -> Result<Option<TxId>, Self::Error> {
+        let value = self.tree.get(txid).map_err(SledTxidDbError::Backend)?;
+        match value {
+            Some(raw) => Ok(Some(Self::decode_tx_id(raw.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(&mut self, txid: [u8; 32], dense_txid: TxId) -> Result<(), Self::Error> {
+        let encoded = Self::encode_tx_id(dense_txid);
+        let previous = self
+            .tree
+            .insert(txid, encoded.clone())
+            .map_err(SledTxidDbError::Backend)?;
+        if let Some(previous) = previous
+            && previous != encoded
+        {
+            self.tree
+                .insert(Self::shadowed_key(&txid, dense_txid), previous)
+                .map_err(SledTxidDbError::Backend)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, txid: &[u8; 32], dense_txid: TxId) -> Result<(), Self::Error> {
+        let expected = Self::encode_tx_id(dense_txid);
+        let shadowed = self
+            .tree
+            .remove(Self::shadowed_key(txid, dense_txid))
+            .map_err(SledTxidDbError::Backend)?;
+        // A mismatch means a later duplicate of this txid owns the entry.
+        let _ = self
+            .tree
+            .compare_and_swap(txid, Some(expected), shadowed)
+            .map_err(SledTxidDbError::Backend)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_tree() -> (tempfile::TempDir, Tree) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let tree = db.open_tree(TXID_TREE_NAME).unwrap();
+        (dir, tree)
+    }
+
+    #[test]
+    fn insert_then_get_returns_tx_id() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledTxidDb::from_tree(tree);
+        let txid = [1u8; 32];
+
+        assert_eq!(db.get(&txid).unwrap(), None);
+        db.insert(txid, TxId::new(42)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), Some(TxId::new(42)));
+    }
+
+    #[test]
+    fn duplicate_txid_resolves_to_latest() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledTxidDb::from_tree(tree);
+        let txid = [2u8; 32];
+
+        db.insert(txid, TxId::new(1)).unwrap();
+        db.insert(txid, TxId::new(2)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), Some(TxId::new(2)));
+    }
+
+    #[test]
+    fn remove_only_drops_matching_tx_id() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledTxidDb::from_tree(tree);
+        let txid = [3u8; 32];
+
+        db.insert(txid, TxId::new(7)).unwrap();
+        db.remove(&txid, TxId::new(8)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), Some(TxId::new(7)));
+
+        db.remove(&txid, TxId::new(7)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), None);
+    }
+
+    #[test]
+    fn removing_a_duplicate_restores_the_earlier_tx_id() {
+        let (_dir, tree) = temp_tree();
+        let mut db = SledTxidDb::from_tree(tree);
+        let txid = [4u8; 32];
+
+        db.insert(txid, TxId::new(1)).unwrap();
+        db.insert(txid, TxId::new(5)).unwrap();
+        db.insert(txid, TxId::new(9)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), Some(TxId::new(9)));
+
+        db.remove(&txid, TxId::new(9)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), Some(TxId::new(5)));
+        db.remove(&txid, TxId::new(5)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), Some(TxId::new(1)));
+        db.remove(&txid, TxId::new(1)).unwrap();
+        assert_eq!(db.get(&txid).unwrap(), None);
+    }
+}
@@ -1,4 +1,4 @@
-use bitcoin::Amount;
+use bitcoin::{Amount, hashes::Hash};
 
 use crate::{
     AnyOutId, HasSequence, HasValue, HasVersion, HasWitness,
@@ -19,6 +19,16 @@ pub struct DummyTxData {
     /// The outputs that are spent by this transaction
     spent_coins: Vec<TxOutId>,
     n_locktime: u32,
+    /// Made-up txid set by [`DummyTxData::with_txid`]
+    txid: Option<TxId>,
+}
+
+/// Made-up txid in internal byte order for the dummy transaction `txid`: its number as LE
+/// u32 in bytes[0..4]. Inputs of [`DummyTxData`] refer to their parents by this txid.
+pub fn dummy_txid_bytes(txid: TxId) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes[..4].copy_from_slice(&txid.0.to_le_bytes());
+    bytes
 }
 
 impl DummyTxData {
@@ -28,6 +38,7 @@ impl DummyTxData {
             outputs,
             spent_coins,
             n_locktime,
+            txid: None,
         }
     }
     /// Tx with explicit outputs, no spent coins.
@@ -36,6 +47,7 @@ impl DummyTxData {
             outputs,
             spent_coins: vec![],
             n_locktime: 0,
+            txid: None,
         }
     }
 
@@ -55,6 +67,14 @@ impl DummyTxData {
         Self::new(base.outputs, spent_coins, 0)
     }
 
+    /// Give the transaction the txid its spenders refer to it by, see [`dummy_txid_bytes`].
+    /// Fixtures usually pick the key the loose index numbers it with, i.e. its position
+    /// counting from 1, so one `TxId` names it both ways.
+    pub fn with_txid(mut self, txid: TxId) -> Self {
+        self.txid = Some(txid);
+        self
+    }
+
     pub fn spent_coins(&self) -> &[TxOutId] {
         &self.spent_coins
     }
@@ -81,10 +101,7 @@ struct DummyTxInWrapper {
 
 impl HasPrevOutpoint for DummyTxInWrapper {
     fn prev_outpoint_txid_bytes(&self) -> [u8; 32] {
-        // TxId surrogate encoded as LE u32 in bytes[0..4]; not a real wire txid.
-        let mut bytes = [0u8; 32];
-        bytes[..4].copy_from_slice(&self.prev_txid.0.to_le_bytes());
-        bytes
+        dummy_txid_bytes(self.prev_txid)
     }
 
     fn prev_outpoint_vout(&self) -> u32 {
@@ -202,6 +219,15 @@ impl AbstractTransaction for DummyTxData {
     fn is_coinbase(&self) -> bool {
         self.spent_coins.is_empty()
     }
+
+    fn txid_bytes(&self) -> [u8; 32] {
+        match self.txid {
+            Some(txid) => dummy_txid_bytes(txid),
+            None => crate::loose::to_bitcoin_tx(self)
+                .compute_txid()
+                .to_byte_array(),
+        }
+    }
 }
 
 impl OutputCount for DummyTxData {
@@ -237,18 +263,195 @@ pub fn temp_dir(prefix: &str) -> std::path::PathBuf {
 }
 
 pub fn write_single_block_file(dir: &std::path::Path, block: &[u8]) -> std::io::Result<()> {
+    write_raw_blocks(dir, &[block])
+}
+
+/// Write `blocks` back to back into `blk00000.dat`, in chain order.
+pub fn write_block_file(dir: &std::path::Path, blocks: &[bitcoin::Block]) -> std::io::Result<()> {
+    let raw: Vec<Vec<u8>> = blocks.iter().map(bitcoin::consensus::serialize).collect();
+    write_raw_blocks(dir, &raw.iter().map(Vec::as_slice).collect::<Vec<_>>())
+}
+
+/// Write the undo records of `blocks`, in chain order from genesis, into `rev00000.dat` and
+/// return an [`UndoHint`](crate::parser::UndoHint) for each. Like Bitcoin Core, the genesis
+/// block gets no record.
+pub fn write_undo_file(
+    dir: &std::path::Path,
+    blocks: &[bitcoin::Block],
+) -> std::io::Result<Vec<crate::parser::UndoHint>> {
+    use bitcoin_block_index::undo::{BlockUndo, SpentOutput, TxUndo};
+
+    let mut created: std::collections::HashMap<_, (u32, bool, &bitcoin::TxOut)> =
+        std::collections::HashMap::new();
+    let mut rev = Vec::new();
+    let mut hints = Vec::new();
+    for (height, block) in (0u32..).zip(blocks) {
+        let undo = BlockUndo {
+            txs: block.txdata[1..]
+                .iter()
+                .map(|tx| TxUndo {
+                    spent: tx
+                        .input
+                        .iter()
+                        .map(|input| {
+                            let (height, is_coinbase, txout) = created[&input.previous_output];
+                            SpentOutput {
+                                height,
+                                is_coinbase,
+                                value: txout.value.to_sat(),
+                                script_pubkey: txout.script_pubkey.to_bytes(),
+                            }
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        for (position, tx) in block.txdata.iter().enumerate() {
+            let txid = tx.compute_txid();
+            for (vout, txout) in (0..).zip(&tx.output) {
+                created.insert(
+                    bitcoin::OutPoint::new(txid, vout),
+                    (height, position == 0, txout),
+                );
+            }
+        }
+        if height == 0 {
+            continue;
+        }
+        let payload = undo.encode();
+        rev.extend_from_slice(&[0xF9, 0xBE, 0xB4, 0xD9]);
+        rev.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        hints.push(crate::parser::UndoHint {
+            height: u64::from(height),
+            file_no: 0,
+            undo_pos: rev.len() as u32,
+        });
+        rev.extend_from_slice(&payload);
+        // The checksum is not verified when reading.
+        rev.extend_from_slice(&[0u8; 32]);
+    }
+    std::fs::write(dir.join("rev00000.dat"), rev)?;
+    Ok(hints)
+}
+
+fn write_raw_blocks(dir: &std::path::Path, blocks: &[&[u8]]) -> std::io::Result<()> {
     use std::fs::File;
     use std::io::Write;
 
     let path = dir.join("blk00000.dat");
     let mut file = File::create(path)?;
-    file.write_all(&[0xF9, 0xBE, 0xB4, 0xD9])?;
-    let size = u32::try_from(block.len()).expect("block too large for u32");
-    file.write_all(&size.to_le_bytes())?;
-    file.write_all(block)?;
+    for block in blocks {
+        file.write_all(&[0xF9, 0xBE, 0xB4, 0xD9])?;
+        let size = u32::try_from(block.len()).expect("block too large for u32");
+        file.write_all(&size.to_le_bytes())?;
+        file.write_all(block)?;
+    }
     Ok(())
 }
 
+/// A block on top of `prev` holding a coinbase paying `coinbase_value` to an anyone-can-spend
+/// script, followed by `txs`. The coinbase commits to `height` so every block's coinbase has a
+/// distinct txid. The block is not mined; the index never checks proof of work.
+pub fn unmined_block(
+    prev: bitcoin::BlockHash,
+    height: u32,
+    coinbase_value: Amount,
+    txs: Vec<bitcoin::Transaction>,
+) -> bitcoin::Block {
+    let coinbase = bitcoin::Transaction {
+        version: bitcoin::transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![bitcoin::TxIn {
+            script_sig: bitcoin::script::Builder::new()
+                .push_int(height as i64)
+                .into_script(),
+            ..Default::default()
+        }],
+        output: vec![bitcoin::TxOut {
+            value: coinbase_value,
+            script_pubkey: bitcoin::ScriptBuf::from_bytes(vec![0x51]),
+        }],
+    };
+    let mut block = bitcoin::Block {
+        header: bitcoin::block::Header {
+            version: bitcoin::block::Version::TWO,
+            prev_blockhash: prev,
+            merkle_root: bitcoin::TxMerkleNode::from_byte_array([0; 32]),
+            time: 1_600_000_000 + height * 600,
+            bits: bitcoin::CompactTarget::from_consensus(0x207f_ffff),
+            nonce: 0,
+        },
+        txdata: std::iter::once(coinbase).chain(txs).collect(),
+    };
+    block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+    block
+}
+
+/// Three unmined blocks, 600 seconds apart. Each block's coinbase pays 5,000 sat to
+/// `OP_TRUE`. Block 1 spends the block 0 coinbase into a 4,000 sat output plus an empty
+/// `OP_RETURN`, and block 2 spends that output into 3,000 sat. Indexed from genesis, dense
+/// txids 0, 1 and 3 are the coinbases and 2 and 4 the spends.
+pub fn spend_chain_blocks() -> Vec<bitcoin::Block> {
+    let spend = |prev: &bitcoin::Transaction, value: u64, extra: Vec<bitcoin::TxOut>| {
+        let mut output = vec![bitcoin::TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: bitcoin::ScriptBuf::from_bytes(vec![0x51]),
+        }];
+        output.extend(extra);
+        bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::new(prev.compute_txid(), 0),
+                ..Default::default()
+            }],
+            output,
+        }
+    };
+    let block0 = unmined_block(
+        bitcoin::BlockHash::from_byte_array([0; 32]),
+        0,
+        Amount::from_sat(5_000),
+        vec![],
+    );
+    let first = spend(
+        &block0.txdata[0],
+        4_000,
+        vec![bitcoin::TxOut {
+            value: Amount::ZERO,
+            script_pubkey: bitcoin::ScriptBuf::new_op_return([]),
+        }],
+    );
+    let block1 = unmined_block(
+        block0.block_hash(),
+        1,
+        Amount::from_sat(5_000),
+        vec![first.clone()],
+    );
+    let second = spend(&first, 3_000, vec![]);
+    let block2 = unmined_block(
+        block1.block_hash(),
+        2,
+        Amount::from_sat(5_000),
+        vec![second],
+    );
+    vec![block0, block1, block2]
+}
+
+/// Temp datadir whose only blk file holds `blocks`, with an empty `index` dir next to
+/// `blocks` for [`crate::dense::DenseStorageBuilder::new`].
+pub fn block_file_datadir(
+    prefix: &str,
+    blocks: &[bitcoin::Block],
+) -> std::io::Result<std::path::PathBuf> {
+    let datadir = temp_dir(prefix);
+    let blocks_dir = datadir.join("blocks");
+    std::fs::create_dir_all(&blocks_dir)?;
+    write_block_file(&blocks_dir, blocks)?;
+    std::fs::create_dir_all(datadir.join("index"))?;
+    Ok(datadir)
+}
+
 impl EnumerateSpentTxOuts for DummyTxData {
     fn spent_coins(&self) -> impl Iterator<Item = AnyOutId> {
         self.spent_coins.iter().copied().map(AnyOutId::from)
@@ -4,28 +4,877 @@ mod primitive_tests {
     use bitcoin::{Amount, hashes::Hash};
     use std::{
         fs,
-        path::PathBuf,
+        path::{Path, PathBuf},
         sync::{Arc, Mutex},
     };
 
     use crate::integration::run_harness;
-    use crate::parser::BlkFileHint;
-    use crate::test_utils::temp_dir;
+    use crate::parser::{BlkFileHint, UndoHint};
+    use crate::test_utils::{
+        block_file_datadir, spend_chain_blocks, temp_dir, write_single_block_file, write_undo_file,
+    };
+    use crate::traits::graph_index::{TxIndex, TxOutDataIndex};
     use crate::{
-        UnifiedStorage,
-        dense::{DenseStorageBuilder, TxId, TxOutId},
+        HasScriptPubkey, UnifiedStorage, classify_script_pubkey,
+        dense::{DenseStorage, DenseStorageBuilder, TxId, TxOutId},
     };
 
-    /// Path to the multi-blk-file fixture (acts as a Bitcoin Core datadir).
-    fn fixture_dir() -> PathBuf {
-        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/multiple_block_files")
+    /// Copy of the multi-blk-file fixture (a Bitcoin Core datadir) in a fresh temp dir.
+    /// Opening the LevelDB block index rewrites its log, so tests never open the fixture in
+    /// place.
+    fn fixture_dir(prefix: &str) -> Result<PathBuf> {
+        let source =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/multiple_block_files");
+        let datadir = temp_dir(prefix);
+        for dir in ["blocks", "blocks/index"] {
+            fs::create_dir_all(datadir.join(dir))?;
+            for entry in fs::read_dir(source.join(dir))? {
+                let path = entry?.path();
+                if path.is_file() {
+                    fs::copy(&path, datadir.join(dir).join(path.file_name().unwrap()))?;
+                }
+            }
+        }
+        Ok(datadir)
+    }
+
+    /// Dense storage over a datadir whose only blk file holds mainnet block 702861.
+    fn mainnet_702861_storage(prefix: &str) -> Result<DenseStorage> {
+        let datadir = temp_dir(prefix);
+        let blocks_dir = datadir.join("blocks");
+        fs::create_dir_all(&blocks_dir)?;
+        write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861())?;
+        let index_dir = datadir.join("index");
+        fs::create_dir_all(&index_dir)?;
+        Ok(DenseStorageBuilder::new(datadir, index_dir, 0..1, vec![]).build()?)
+    }
+
+    #[test]
+    fn dense_tx_access_matches_deserialized_block() -> Result<()> {
+        let storage: UnifiedStorage = mainnet_702861_storage("dense_tx_access")?.into();
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+        assert_eq!(storage.dense_txids_len(), block.txdata.len());
+
+        for (txid, expected) in storage.dense_txids_from(0).zip(&block.txdata) {
+            let tx = storage.tx(txid);
+            assert!(TxIndex::tx(&storage, &txid).is_some());
+            assert_eq!(tx.is_coinbase(), expected.is_coinbase());
+            assert_eq!(tx.version(), expected.version.0);
+            assert_eq!(tx.locktime(), expected.lock_time.to_consensus_u32());
+            assert_eq!(tx.input_len(), expected.input.len());
+            assert_eq!(tx.output_len(), expected.output.len());
+
+            for (input, want) in tx.inputs().zip(&expected.input) {
+                assert_eq!(
+                    input.prev_outpoint_txid_bytes(),
+                    want.previous_output.txid.to_byte_array()
+                );
+                assert_eq!(input.prev_outpoint_vout(), want.previous_output.vout);
+                assert_eq!(input.sequence(), want.sequence.0);
+                assert_eq!(input.script_sig_bytes(), want.script_sig.to_bytes());
+                assert_eq!(input.witness_items(), want.witness.to_vec());
+            }
+            for (output, want) in tx.outputs().zip(&expected.output) {
+                assert_eq!(output.value(), want.value);
+                assert_eq!(output.script_pubkey_bytes(), want.script_pubkey.to_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dense_output_columns_match_block() -> Result<()> {
+        let storage: UnifiedStorage = mainnet_702861_storage("dense_output_columns")?.into();
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+
+        for (txid, expected) in storage.dense_txids_from(0).zip(&block.txdata) {
+            let out_ids = storage.tx_out_ids(txid);
+            assert_eq!(out_ids.len(), expected.output.len());
+            for (out_id, want) in out_ids.iter().zip(&expected.output) {
+                assert_eq!(storage.value(out_id), want.value);
+                assert_eq!(
+                    storage.script_pubkey_hash(out_id),
+                    want.script_pubkey_hash()
+                );
+                assert_eq!(
+                    storage.output_type(out_id),
+                    classify_script_pubkey(want.script_pubkey.as_bytes())
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dense_txid_lookups_match_block() -> Result<()> {
+        let storage = mainnet_702861_storage("dense_txid_lookups")?;
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+
+        assert_eq!(storage.tx_count(), block.txdata.len() as u64);
+        for (i, expected) in block.txdata.iter().enumerate() {
+            let txid = TxId::new(i as u32);
+            assert_eq!(storage.tx_hash(txid), expected.compute_txid());
+            assert_eq!(storage.txid_for_hash(&expected.compute_txid()), Some(txid));
+        }
+        assert_eq!(
+            storage.txid_for_hash(&bitcoin::Txid::from_byte_array([0xab; 32])),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn loose_outputs_for_script_pubkey_in_insertion_order() {
+        use crate::loose::{LooseIndexBuilder, TxId as LooseTxId, TxOutId as LooseTxOutId};
+        use crate::test_utils::{DummyTxData, DummyTxOutData};
+        use crate::unified::AnyOutId;
+
+        let other_spk = vec![0x51];
+        let mut builder = LooseIndexBuilder::new();
+        builder.add_tx(Arc::new(DummyTxData::new_with_outputs(vec![
+            DummyTxOutData::new_with_script(1_000, 0, other_spk.clone()),
+            DummyTxOutData::new(2_000, 1),
+        ])));
+        builder.add_tx(Arc::new(DummyTxData::new_with_amounts(vec![3_000, 4_000])));
+        let storage = UnifiedStorage::from(builder);
+
+        let reused = AnyOutId::from(LooseTxOutId::new(LooseTxId::new(2), 1));
+        let want: Vec<AnyOutId> = [(1, 1), (2, 0), (2, 1)]
+            .into_iter()
+            .map(|(tx, vout)| LooseTxOutId::new(LooseTxId::new(tx), vout).into())
+            .collect();
+        let spk_hash = storage.script_pubkey_hash(&reused);
+        assert_eq!(storage.outputs_for_script_pubkey(&spk_hash), want);
+        assert_eq!(storage.script_pubkey_to_txout_id(&spk_hash), Some(want[0]));
+
+        let handle = reused.with(&storage);
+        let same_spk: Vec<AnyOutId> = handle
+            .outputs_with_same_spk()
+            .iter()
+            .map(|out| out.id())
+            .collect();
+        assert_eq!(same_spk, want);
+
+        let other_hash = DummyTxOutData::new_with_script(0, 0, other_spk).script_pubkey_hash();
+        assert_eq!(storage.outputs_for_script_pubkey(&other_hash).len(), 1);
+    }
+
+    #[test]
+    fn loose_outputs_render_and_resolve_addresses() {
+        use crate::loose::LooseIndexBuilder;
+        use crate::test_utils::{DummyTxData, DummyTxOutData};
+
+        let p2wpkh =
+            bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([0x42; 20]));
+        let mut builder = LooseIndexBuilder::new();
+        builder.add_tx(Arc::new(DummyTxData::new_with_outputs(vec![
+            DummyTxOutData::new_with_script(1_000, 0, p2wpkh.to_bytes()),
+            DummyTxOutData::new_with_script(0, 1, vec![0x6a]),
+        ])));
+        let txid = crate::unified::AnyTxId::from(crate::loose::TxId::new(1));
+        let storage = UnifiedStorage::from(builder.build())
+            .with_network(bitcoin::Network::Regtest)
+            .unwrap();
+
+        let outputs: Vec<_> = txid.with(&storage).outputs().collect();
+        let address = outputs[0].address().expect("p2wpkh has an address");
+        assert!(address.to_string().starts_with("bcrt1q"));
+        assert_eq!(outputs[1].address(), None);
+
+        assert_eq!(
+            storage.outputs_for_address(&address.to_string()).unwrap(),
+            vec![outputs[0].id()]
+        );
+        let mainnet = bitcoin::Address::from_script(&p2wpkh, bitcoin::Network::Bitcoin).unwrap();
+        assert!(storage.outputs_for_address(&mainnet.to_string()).is_err());
+        assert!(storage.outputs_for_address("not an address").is_err());
+    }
+
+    #[test]
+    fn dense_index_remembers_its_network() -> Result<()> {
+        let datadir = temp_dir("dense_network");
+        let blocks_dir = datadir.join("blocks");
+        fs::create_dir_all(&blocks_dir)?;
+        write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861())?;
+        let index_dir = datadir.join("index");
+        fs::create_dir_all(&index_dir)?;
+        let storage = DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..1, vec![])
+            .with_network(bitcoin::Network::Signet)
+            .build()?;
+        assert_eq!(storage.network(), Some(bitcoin::Network::Signet));
+        drop(storage);
+
+        let reopened = DenseStorageBuilder::open(datadir.clone(), index_dir.clone())?.build()?;
+        let unified = UnifiedStorage::from(reopened);
+        assert_eq!(unified.network(), bitcoin::Network::Signet);
+        // The indexed network cannot be overridden afterwards.
+        let unified = unified.with_network(bitcoin::Network::Signet)?;
+        assert!(matches!(
+            unified.with_network(bitcoin::Network::Bitcoin),
+            Err(crate::unified::SyncError::NetworkMismatch {
+                indexed: bitcoin::Network::Signet,
+                requested: bitcoin::Network::Bitcoin,
+            })
+        ));
+
+        let mismatch = DenseStorageBuilder::open(datadir, index_dir)?
+            .with_network(bitcoin::Network::Bitcoin)
+            .build();
+        assert!(matches!(
+            mismatch,
+            Err(crate::unified::SyncError::NetworkMismatch {
+                indexed: bitcoin::Network::Signet,
+                requested: bitcoin::Network::Bitcoin,
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn index_without_network_records_one_only_when_supplied() -> Result<()> {
+        let datadir = temp_dir("dense_no_network");
+        let blocks_dir = datadir.join("blocks");
+        fs::create_dir_all(&blocks_dir)?;
+        write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861())?;
+        let index_dir = datadir.join("index");
+        fs::create_dir_all(&index_dir)?;
+        let meta_path = index_dir.join("meta.bin");
+        let storage =
+            DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..1, vec![]).build()?;
+        assert_eq!(storage.network(), None);
+        drop(storage);
+        let unset = fs::read(&meta_path)?;
+
+        let reopened = DenseStorageBuilder::open(datadir.clone(), index_dir.clone())?.build()?;
+        assert_eq!(reopened.network(), None);
+        assert_eq!(fs::read(&meta_path)?, unset);
+        let unified = UnifiedStorage::from(reopened).with_network(bitcoin::Network::Regtest)?;
+        assert_eq!(unified.network(), bitcoin::Network::Regtest);
+        drop(unified);
+        assert_eq!(fs::read(&meta_path)?, unset);
+
+        let named = DenseStorageBuilder::open(datadir.clone(), index_dir.clone())?
+            .with_network(bitcoin::Network::Signet)
+            .build()?;
+        assert_eq!(named.network(), Some(bitcoin::Network::Signet));
+        drop(named);
+        let reopened = DenseStorageBuilder::open(datadir, index_dir)?.build()?;
+        assert_eq!(reopened.network(), Some(bitcoin::Network::Signet));
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_output_types_are_reclassified_on_open() -> Result<()> {
+        use crate::OutputType;
+        use crate::indices::{DenseIndexMeta, DenseIndexSet};
+
+        let datadir = temp_dir("dense_legacy_output_types");
+        let blocks_dir = datadir.join("blocks");
+        fs::create_dir_all(&blocks_dir)?;
+        write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861())?;
+        let index_dir = datadir.join("index");
+        fs::create_dir_all(&index_dir)?;
+        let storage =
+            DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..1, vec![]).build()?;
+        let out_count = storage
+            .tx_out_range(TxId::new(storage.tx_count() as u32 - 1))
+            .1;
+        let want: Vec<OutputType> = (0..out_count)
+            .map(|out_id| storage.output_type(TxOutId::new(out_id)))
+            .collect();
+        drop(storage);
+
+        // Leave the index the way an older version lists outputs it could not classify.
+        {
+            let mut indices = DenseIndexSet::new(&index_dir)?;
+            for out_id in 0..out_count {
+                indices.out_type.set(out_id, OutputType::NonStandard)?;
+            }
+            let meta = DenseIndexMeta::read(&index_dir)?.expect("meta");
+            DenseIndexMeta {
+                legacy_output_types: true,
+                ..meta
+            }
+            .write(&index_dir)?;
+        }
+        assert!(DenseStorage::open_read_only(datadir.clone(), index_dir.clone()).is_err());
+
+        let storage = DenseStorageBuilder::open(datadir, index_dir.clone())?.build()?;
+        let got: Vec<OutputType> = (0..out_count)
+            .map(|out_id| storage.output_type(TxOutId::new(out_id)))
+            .collect();
+        assert_eq!(got, want);
+        assert!(
+            !DenseIndexMeta::read(&index_dir)?
+                .expect("meta")
+                .legacy_output_types
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_spk_index_is_rebuilt_on_open() -> Result<()> {
+        use crate::sled::spk_db::{LEGACY_SPK_TREE_NAME, SPK_TREE_NAME};
+
+        let datadir = temp_dir("dense_legacy_spk_index");
+        let blocks_dir = datadir.join("blocks");
+        fs::create_dir_all(&blocks_dir)?;
+        write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861())?;
+        let index_dir = datadir.join("index");
+        fs::create_dir_all(&index_dir)?;
+        let storage =
+            DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..1, vec![]).build()?;
+        let out_count = storage
+            .tx_out_range(TxId::new(storage.tx_count() as u32 - 1))
+            .1;
+        drop(storage);
+
+        // Leave the spk db the way an index with only the first-output tree has it.
+        {
+            let db = sled::open(index_dir.join("spk_db"))?;
+            db.open_tree(SPK_TREE_NAME)?.clear()?;
+            db.open_tree(LEGACY_SPK_TREE_NAME)?
+                .insert([0u8; 20], &[0u8; 8])?;
+        }
+
+        let storage = DenseStorageBuilder::new(datadir, index_dir.clone(), 1..1, vec![]).build()?;
+        for out_id in (0..out_count).map(TxOutId::new) {
+            let spk_hash = storage.output_spk_hash(out_id);
+            assert!(
+                storage
+                    .script_pubkey_to_txout_ids(&spk_hash)?
+                    .contains(&out_id)
+            );
+        }
+        drop(storage);
+        let db = sled::open(index_dir.join("spk_db"))?;
+        assert!(
+            !db.tree_names()
+                .iter()
+                .any(|name| name.as_ref() == LEGACY_SPK_TREE_NAME.as_bytes())
+        );
+        Ok(())
+    }
+
+    /// An output of `dense` that no transaction in it spends, with its outpoint.
+    fn unspent_dense_output(dense: &DenseStorage) -> (TxOutId, bitcoin::OutPoint) {
+        let (prev_txid, prev_out) = (1..dense.tx_count() as u32)
+            .map(TxId::new)
+            .flat_map(|txid| dense.get_txout_ids(txid).map(move |out| (txid, out)))
+            .find(|(_, out)| dense.spender_for_out(*out).is_none())
+            .expect("block has an output it does not spend itself");
+        let (out_start, _) = dense.tx_out_range(prev_txid);
+        let outpoint = bitcoin::OutPoint::new(
+            dense.tx_hash(prev_txid),
+            (prev_out.index() - out_start) as u32,
+        );
+        (prev_out, outpoint)
+    }
+
+    /// Transaction spending `prevout` into a single `value` sat OP_RETURN output.
+    fn spend_of(prevout: bitcoin::OutPoint, value: u64) -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: bitcoin::ScriptBuf::new_op_return([]),
+            }],
+        }
+    }
+
+    #[test]
+    fn loose_tx_spending_dense_output_links_both_ways() -> Result<()> {
+        use crate::loose::{ConfirmedTx, LooseIndexBuilder};
+
+        let dense = mainnet_702861_storage("loose_over_dense")?;
+        let (prev_out, outpoint) = unspent_dense_output(&dense);
+        let spend = spend_of(outpoint, 1_000);
+        let mut loose = LooseIndexBuilder::new();
+        loose.add_tx(Arc::new(ConfirmedTx::new(
+            bitcoin::consensus::serialize(&spend).into(),
+        )));
+
+        let expected_value = dense.output_value(prev_out);
+        let storage = UnifiedStorage::from(dense).with_loose(loose);
+        let loose_txid = crate::unified::AnyTxId::from(crate::loose::TxId::new(1));
+        let input = loose_txid.with(&storage).inputs().next().unwrap();
+
+        let prevout = input
+            .prev_txout()
+            .expect("prevout resolves into dense storage");
+        assert_eq!(prevout.id(), prev_out.into());
+        assert_eq!(prevout.value(), expected_value);
+        assert_eq!(storage.spender_for_out(prev_out.into()), Some(input.id()));
+        Ok(())
+    }
+
+    #[test]
+    fn first_added_loose_spend_of_dense_output_is_its_spender() -> Result<()> {
+        use crate::loose::{MempoolSnapshot, MempoolTxInfo};
+
+        let dense = mainnet_702861_storage("loose_conflicts")?;
+        let (prev_out, outpoint) = unspent_dense_output(&dense);
+        // Loose keys of mempool transactions derive from their txids, so they do not follow
+        // the order the conflicting spends are added in.
+        let spends: Vec<_> = (0..16).map(|i| spend_of(outpoint, 1_000 + i)).collect();
+        let index = MempoolSnapshot {
+            txs: spends
+                .iter()
+                .map(|tx| {
+                    (
+                        tx.clone(),
+                        MempoolTxInfo {
+                            time: 0,
+                            fee_delta: 0,
+                        },
+                    )
+                })
+                .collect(),
+            fee_deltas: vec![],
+            unbroadcast: vec![],
+        }
+        .into_index();
+        let lids: Vec<_> = spends
+            .iter()
+            .map(|tx| {
+                index
+                    .loose_txid(&tx.compute_txid().to_byte_array())
+                    .unwrap()
+            })
+            .collect();
+
+        let storage = UnifiedStorage::from(dense).with_loose_index(index);
+        assert_eq!(
+            storage.spender_for_out(prev_out.into()),
+            Some(crate::unified::AnyInId::from(lids[0].txin_id(0)))
+        );
+        for lid in lids {
+            let input = crate::unified::AnyTxId::from(lid)
+                .with(&storage)
+                .inputs()
+                .next()
+                .unwrap();
+            assert_eq!(input.prev_txout().unwrap().id(), prev_out.into());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dense_weight_matches_deserialized_block() -> Result<()> {
+        let storage: UnifiedStorage = mainnet_702861_storage("dense_weight")?.into();
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+
+        for (txid, expected) in storage.dense_txids_from(0).zip(&block.txdata) {
+            let handle = txid.with(&storage);
+            assert_eq!(handle.weight(), expected.weight());
+            assert_eq!(handle.vsize(), expected.vsize() as u64);
+            // The size re-derived from the parsed fields agrees with the raw bytes.
+            let tx = storage.tx(txid);
+            assert_eq!(
+                crate::traits::abstract_types::serialized_weight(&*tx),
+                expected.weight()
+            );
+        }
+        let coinbase = storage.dense_txids_from(0).next().unwrap().with(&storage);
+        assert_eq!(coinbase.fee(), None);
+        assert_eq!(coinbase.feerate(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn adding_a_loose_tx_twice_returns_the_first_copy() {
+        use crate::loose::{ConfirmedTx, InMemoryIndex};
+
+        let tx = spend_of(bitcoin::OutPoint::new(bitcoin::Txid::all_zeros(), 7), 1_000);
+        let bytes: Arc<[u8]> = bitcoin::consensus::serialize(&tx).into();
+        let mut index = InMemoryIndex::new();
+        let first = index.add_tx(Arc::new(ConfirmedTx::new(bytes.clone()))).id();
+        let second = index.add_tx(Arc::new(ConfirmedTx::new(bytes))).id();
+
+        assert_eq!(first, second);
+        assert_eq!(index.txs.len(), 1);
+        assert_eq!(index.tx_order.len(), 1);
+        assert_eq!(
+            index.loose_txid(&tx.compute_txid().to_byte_array()),
+            first.loose_txid()
+        );
+    }
+
+    #[test]
+    fn loose_inputs_link_by_txid_and_keep_the_first_spend() {
+        use crate::loose::{ConfirmedTx, InMemoryIndex};
+
+        let funding = spend_of(bitcoin::OutPoint::new(bitcoin::Txid::all_zeros(), 7), 3_000);
+        let prev_out = bitcoin::OutPoint::new(funding.compute_txid(), 0);
+        let unknown = bitcoin::OutPoint::new(bitcoin::Txid::from_byte_array([0x42; 32]), 0);
+        let mut index = InMemoryIndex::new();
+        let mut add = |tx: &bitcoin::Transaction| {
+            let bytes: Arc<[u8]> = bitcoin::consensus::serialize(tx).into();
+            index
+                .add_tx(Arc::new(ConfirmedTx::new(bytes)))
+                .id()
+                .loose_txid()
+                .unwrap()
+        };
+        let funding_id = add(&funding);
+        let first = add(&spend_of(prev_out, 1_000));
+        let second = add(&spend_of(prev_out, 2_000));
+        let orphan = add(&spend_of(unknown, 500));
+
+        let spent = funding_id.txout_id(0);
+        assert_eq!(index.spending_txins.get(&spent), Some(&first.txin_id(0)));
+        assert_eq!(index.prev_txouts.get(&first.txin_id(0)), Some(&spent));
+        assert_eq!(index.prev_txouts.get(&second.txin_id(0)), Some(&spent));
+        // Prevouts whose txid is not indexed stay unresolved.
+        assert_eq!(index.prev_txouts.get(&orphan.txin_id(0)), None);
+        assert_eq!(index.prev_txouts.get(&funding_id.txin_id(0)), None);
+        assert_eq!(index.spending_txins.len(), 1);
+    }
+
+    #[test]
+    fn loose_segwit_spend_reports_fee_and_feerate() -> Result<()> {
+        use crate::loose::{ConfirmedTx, LooseIndexBuilder};
+
+        let dense = mainnet_702861_storage("loose_fee")?;
+        let (prev_txid, prev_out) = (1..dense.tx_count() as u32)
+            .map(TxId::new)
+            .flat_map(|txid| dense.get_txout_ids(txid).map(move |out| (txid, out)))
+            .find(|(_, out)| {
+                dense.spender_for_out(*out).is_none() && dense.output_value(*out).to_sat() > 10_000
+            })
+            .expect("block has an unspent output worth spending");
+        let (out_start, _) = dense.tx_out_range(prev_txid);
+        let spend = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::new(
+                    dense.tx_hash(prev_txid),
+                    (prev_out.index() - out_start) as u32,
+                ),
+                witness: bitcoin::Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]]),
+                ..Default::default()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: dense.output_value(prev_out) - Amount::from_sat(1_000),
+                script_pubkey: bitcoin::ScriptBuf::new_op_return([]),
+            }],
+        };
+        let mut loose = LooseIndexBuilder::new();
+        loose.add_tx(Arc::new(ConfirmedTx::new(
+            bitcoin::consensus::serialize(&spend).into(),
+        )));
+
+        let storage = UnifiedStorage::from(dense).with_loose(loose);
+        let handle = crate::unified::AnyTxId::from(crate::loose::TxId::new(1)).with(&storage);
+        assert_eq!(handle.weight(), spend.weight());
+        assert_eq!(handle.fee(), Some(Amount::from_sat(1_000)));
+        assert_eq!(
+            handle.feerate(),
+            Some(bitcoin::FeeRate::from_sat_per_kwu(
+                1_000_000 / spend.weight().to_wu()
+            ))
+        );
+        Ok(())
+    }
+
+    /// Dense txids: 0, 1 and 3 are coinbases, 2 and 4 the spends.
+    fn spend_chain_storage(prefix: &str) -> Result<UnifiedStorage> {
+        let datadir = block_file_datadir(prefix, &spend_chain_blocks())?;
+        let index_dir = datadir.join("index");
+        Ok(DenseStorageBuilder::new(datadir, index_dir, 0..3, vec![])
+            .build()?
+            .into())
+    }
+
+    #[test]
+    fn unspent_at_follows_spender_heights() -> Result<()> {
+        let storage = spend_chain_storage("unspent_at")?;
+
+        let outputs = |txid: u32| storage.tx_out_ids(TxId::new(txid).into());
+        let coinbase0 = outputs(0)[0];
+        let (first_out, op_return) = (outputs(2)[0], outputs(2)[1]);
+        let second_out = outputs(4)[0];
+
+        assert!(storage.is_unspent_at(coinbase0, 0));
+        assert!(!storage.is_unspent_at(coinbase0, 1));
+        assert!(!storage.is_unspent_at(first_out, 0));
+        assert!(storage.is_unspent_at(first_out, 1));
+        assert!(!storage.is_unspent_at(first_out, 2));
+        assert!(storage.is_unspent_at(second_out, 2));
+        // Heights past the tip see the current UTXO set.
+        assert!(storage.is_unspent_at(second_out, 100));
+        assert!((0..3).all(|height| !storage.is_unspent_at(op_return, height)));
+        Ok(())
+    }
+
+    #[test]
+    fn unspent_at_skips_oversized_scripts() -> Result<()> {
+        use crate::test_utils::unmined_block;
+
+        let block0 = unmined_block(
+            bitcoin::BlockHash::from_byte_array([0; 32]),
+            0,
+            Amount::from_sat(5_000),
+            vec![],
+        );
+        let script_of_len =
+            |len: usize| bitcoin::ScriptBuf::from_bytes(std::iter::repeat_n(0x61, len).collect());
+        let spend = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::new(block0.txdata[0].compute_txid(), 0),
+                ..Default::default()
+            }],
+            output: [10_000, 10_001]
+                .map(|len| bitcoin::TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: script_of_len(len),
+                })
+                .to_vec(),
+        };
+        let block1 = unmined_block(block0.block_hash(), 1, Amount::from_sat(5_000), vec![spend]);
+
+        let datadir = block_file_datadir("unspent_at_oversized", &[block0, block1])?;
+        let index_dir = datadir.join("index");
+        let storage: UnifiedStorage = DenseStorageBuilder::new(datadir, index_dir, 0..2, vec![])
+            .build()?
+            .into();
+
+        let outputs = storage.tx_out_ids(TxId::new(2).into());
+        assert!(storage.is_unspent_at(outputs[0], 1));
+        assert!(!storage.is_unspent_at(outputs[1], 1));
+        Ok(())
+    }
+
+    #[test]
+    fn coin_age_and_days_destroyed_follow_block_times() -> Result<()> {
+        use crate::handle::CoinAge;
+
+        let storage = spend_chain_storage("coin_age")?;
+        let tx = |txid: u32| crate::unified::AnyTxId::from(TxId::new(txid)).with(&storage);
+
+        let first = tx(2).inputs().next().unwrap();
+        assert_eq!(
+            first.coin_age(),
+            Some(CoinAge {
+                blocks: 1,
+                seconds: Some(600),
+            })
+        );
+        let expected = 5_000e-8 * 600.0 / 86_400.0;
+        assert!((tx(2).coin_days_destroyed().unwrap() - expected).abs() < 1e-12);
+        assert_eq!(tx(0).coin_days_destroyed(), Some(0.0));
+        assert_eq!(tx(0).inputs().next().unwrap().coin_age(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn coin_age_before_indexed_range_uses_undo_height() -> Result<()> {
+        use crate::handle::CoinAge;
+        use bitcoin_block_index::undo::{BlockUndo, SpentOutput, TxUndo};
+
+        // Index only block 1, whose spend of the block 0 coinbase is known from undo data.
+        let undo = BlockUndo {
+            txs: vec![TxUndo {
+                spent: vec![SpentOutput {
+                    height: 0,
+                    is_coinbase: true,
+                    value: 5_000,
+                    script_pubkey: vec![0x51],
+                }],
+            }],
+        };
+        let payload = undo.encode();
+        let mut rev = vec![0xf9, 0xbe, 0xb4, 0xd9];
+        rev.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        rev.extend_from_slice(&payload);
+        rev.extend_from_slice(&[0u8; 32]);
+
+        let datadir = block_file_datadir("coin_age_undo", &spend_chain_blocks())?;
+        fs::write(datadir.join("blocks").join("rev00000.dat"), &rev)?;
+        let index_dir = datadir.join("index");
+        let storage: UnifiedStorage = DenseStorageBuilder::new(datadir, index_dir, 1..2, vec![])
+            .with_undo_hints(vec![UndoHint {
+                height: 1,
+                file_no: 0,
+                undo_pos: 8,
+            }])
+            .build()?
+            .into();
+
+        let spend = crate::unified::AnyTxId::from(TxId::new(1)).with(&storage);
+        let input = spend.inputs().next().unwrap();
+        assert!(input.prev_txout().is_none());
+        // Block 0's header is not indexed, so only the age in blocks is known.
+        assert_eq!(
+            input.coin_age(),
+            Some(CoinAge {
+                blocks: 1,
+                seconds: None,
+            })
+        );
+        assert_eq!(spend.coin_days_destroyed(), None);
+        Ok(())
+    }
+
+    /// BIP158 basic filters of `blocks` as rust-bitcoin builds them.
+    fn reference_filters(blocks: &[bitcoin::Block]) -> Result<Vec<bitcoin::bip158::BlockFilter>> {
+        let txouts: std::collections::HashMap<_, _> = blocks
+            .iter()
+            .flat_map(|block| &block.txdata)
+            .flat_map(|tx| {
+                let txid = tx.compute_txid();
+                (0..)
+                    .zip(&tx.output)
+                    .map(move |(vout, txout)| (bitcoin::OutPoint::new(txid, vout), txout.clone()))
+            })
+            .collect();
+        let filters = blocks.iter().map(|block| {
+            bitcoin::bip158::BlockFilter::new_script_filter(block, |outpoint| {
+                txouts
+                    .get(outpoint)
+                    .map(|txout| txout.script_pubkey.clone())
+                    .ok_or(bitcoin::bip158::Error::UtxoMissing(*outpoint))
+            })
+        });
+        Ok(filters.collect::<Result<_, _>>()?)
+    }
+
+    #[test]
+    fn block_filters_match_reference_and_persist() -> Result<()> {
+        let blocks = spend_chain_blocks();
+        let datadir = block_file_datadir("block_filters", &blocks)?;
+        let undo_hints = write_undo_file(&datadir.join("blocks"), &blocks)?;
+        let index_dir = datadir.join("index");
+        let storage = DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..3, vec![])
+            .with_undo_hints(undo_hints)
+            .with_block_filters()
+            .build()?;
+
+        for (height, expected) in (0..).zip(reference_filters(&blocks)?) {
+            assert_eq!(storage.block_filter(height), Some(expected));
+        }
+        assert_eq!(storage.block_filter(3), None);
+
+        let op_true = [vec![0x51u8]];
+        assert_eq!(storage.heights_matching(&op_true), vec![0, 1, 2]);
+        assert_eq!(storage.filter_matches_any(1, &[[0x6au8]]), Some(false));
+        assert!(storage.heights_matching::<Vec<u8>>(&[]).is_empty());
+
+        drop(storage);
+        let read_only = DenseStorage::open_read_only(datadir.clone(), index_dir.clone())?;
+        assert_eq!(read_only.heights_matching(&op_true), vec![0, 1, 2]);
+        drop(read_only);
+        let reopened = DenseStorageBuilder::open(datadir, index_dir)?.build()?;
+        let unified: UnifiedStorage = reopened.into();
+        assert_eq!(unified.filter_matching_heights(&op_true), vec![0, 1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn block_filters_catch_up_with_the_index() -> Result<()> {
+        let blocks = spend_chain_blocks();
+        let datadir = block_file_datadir("block_filters_catch_up", &blocks)?;
+        let undo_hints = write_undo_file(&datadir.join("blocks"), &blocks)?;
+        let index_dir = datadir.join("index");
+        DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..2, vec![])
+            .with_undo_hints(undo_hints)
+            .with_block_filters()
+            .build()?;
+        // As if the sync had been interrupted after indexing block 1 but before filtering it.
+        crate::indices::BlockFilterIndex::open_or_create(&index_dir)?.truncate(1)?;
+
+        let storage =
+            DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 2..3, vec![]).build()?;
+        for (height, expected) in (0..).zip(reference_filters(&blocks)?) {
+            assert_eq!(
+                storage.block_filter(height),
+                Some(expected),
+                "height {height}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn block_filters_cannot_start_on_an_unfiltered_index() -> Result<()> {
+        let datadir = block_file_datadir("block_filters_unfiltered", &spend_chain_blocks())?;
+        let index_dir = datadir.join("index");
+        DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..2, vec![]).build()?;
+
+        let result = DenseStorageBuilder::new(datadir, index_dir, 2..3, vec![])
+            .with_block_filters()
+            .build();
+        assert!(matches!(
+            result,
+            Err(crate::unified::SyncError::MissingBlockFilters {
+                filtered_end: 0,
+                indexed_end: 2,
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn block_filters_after_genesis_read_spent_scripts_from_undo() -> Result<()> {
+        let blocks = spend_chain_blocks();
+        let datadir = block_file_datadir("block_filters_after_genesis", &blocks)?;
+        let undo_hints = write_undo_file(&datadir.join("blocks"), &blocks)?;
+        let storage =
+            DenseStorageBuilder::new(datadir.clone(), datadir.join("index"), 1..3, vec![])
+                .with_undo_hints(undo_hints)
+                .with_block_filters()
+                .build()?;
+
+        let expected = reference_filters(&blocks)?;
+        assert_eq!(storage.block_filter(1), Some(expected[1].clone()));
+        assert_eq!(storage.block_filter(2), Some(expected[2].clone()));
+        // The spend of the block 0 coinbase was recorded from the undo record on the way.
+        let spend_in = storage.get_txin_ids(TxId::new(1)).next().unwrap();
+        assert_eq!(
+            storage.spent_txout(spend_in),
+            Some(blocks[0].txdata[0].output[0].clone())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_filters_missing_spent_scripts_are_not_served() -> Result<()> {
+        let blocks = spend_chain_blocks();
+        let datadir = block_file_datadir("block_filters_incomplete", &blocks)?;
+        let mut undo_hints = write_undo_file(&datadir.join("blocks"), &blocks)?;
+        // Block 1 spends the coinbase of block 0, but its undo record is not known.
+        undo_hints.retain(|hint| hint.height != 1);
+        let storage =
+            DenseStorageBuilder::new(datadir.clone(), datadir.join("index"), 1..3, vec![])
+                .with_undo_hints(undo_hints)
+                .with_block_filters()
+                .build()?;
+
+        let expected = reference_filters(&blocks)?;
+        assert_eq!(storage.block_filter(1), None);
+        assert_eq!(storage.filter_matches_any(1, &[[0x51u8]]), None);
+        assert_eq!(storage.block_filter(2), Some(expected[2].clone()));
+        assert_eq!(storage.heights_matching(&[[0x51u8]]), vec![2]);
+        Ok(())
     }
 
     #[test]
     fn fixture_sync_from_tip_multiple_blk_files() -> Result<()> {
         use bitcoin_block_index::BlockIndex;
 
-        let fixture = fixture_dir();
+        let fixture = fixture_dir("fixture_multi_blk_datadir")?;
         let index_path = fixture.join("blocks/index");
 
         // Discover chain tip height and last blk file number from the block index.
@@ -60,9 +909,461 @@ mod primitive_tests {
         Ok(())
     }
 
+    /// Best-chain block hashes of the fixture copy in `datadir`, indexed by height.
+    fn fixture_chain_hashes(datadir: &Path) -> Result<Vec<[u8; 32]>> {
+        use bitcoin_block_index::BlockIndex;
+
+        let mut index = BlockIndex::open(&datadir.join("blocks/index"))?;
+        let mut hash = index.best_block()?;
+        let mut loc = index.block_location(&hash)?;
+        let mut hashes = vec![hash];
+        while loc.height > 0 {
+            hash = loc.prev_hash;
+            loc = index.block_location(&hash)?;
+            hashes.push(hash);
+        }
+        hashes.reverse();
+        Ok(hashes)
+    }
+
+    fn assert_matches_fixture_chain(storage: &DenseStorage, chain: &[[u8; 32]]) {
+        assert_eq!(storage.indexed_heights(), 0..chain.len() as u64);
+        assert_eq!(storage.tx_count(), chain.len() as u64);
+        for (height, want) in chain.iter().enumerate() {
+            let got = storage.block_hash(height as u64).expect("indexed height");
+            assert_eq!(got.to_byte_array(), *want, "block hash at height {height}");
+        }
+        let out_count = storage
+            .tx_out_range(TxId::new(storage.tx_count() as u32 - 1))
+            .1;
+        let mut spk_hashes = std::collections::BTreeSet::new();
+        for out_id in 0..out_count {
+            let out_id = TxOutId::new(out_id);
+            let spk_hash = storage.output_spk_hash(out_id);
+            let history = storage.script_pubkey_to_txout_ids(&spk_hash).unwrap();
+            assert!(history.contains(&out_id));
+            assert_eq!(
+                storage.script_pubkey_to_txout_id(&spk_hash).unwrap(),
+                history.first().copied()
+            );
+            spk_hashes.insert(spk_hash);
+        }
+        // No entries survive for outputs that were rolled back.
+        let indexed: usize = spk_hashes
+            .iter()
+            .map(|spk_hash| storage.script_pubkey_to_txout_ids(spk_hash).unwrap().len())
+            .sum();
+        assert_eq!(indexed as u64, out_count);
+    }
+
+    #[test]
+    fn dense_block_headers_match_block() -> Result<()> {
+        let storage = mainnet_702861_storage("dense_block_headers")?;
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+
+        assert_eq!(storage.block_header(0), Some(block.header));
+        assert_eq!(storage.block_time(0), Some(block.header.time));
+        assert_eq!(storage.block_header(1), None);
+
+        let storage = UnifiedStorage::from(storage);
+        let tx = storage.dense_txids_from(0).next().unwrap().with(&storage);
+        assert_eq!(tx.block_time(), Some(block.header.time));
+        assert_eq!(tx.block_hash(), Some(block.block_hash()));
+        Ok(())
+    }
+
+    #[test]
+    fn dense_undo_data_resolves_prevouts_before_indexed_range() -> Result<()> {
+        use crate::traits::abstract_types::EnumerateInputValueInArbitraryOrder;
+        use bitcoin_block_index::undo::{BlockUndo, SpentOutput, TxUndo};
+
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+        // Synthetic undo record with a distinct spent output per input, in Core's layout.
+        let mut n = 0u64;
+        let undo = BlockUndo {
+            txs: block.txdata[1..]
+                .iter()
+                .map(|tx| TxUndo {
+                    spent: tx
+                        .input
+                        .iter()
+                        .map(|_| {
+                            n += 1;
+                            SpentOutput {
+                                height: 700_000,
+                                is_coinbase: false,
+                                value: 1_000 + n,
+                                script_pubkey: [&[0x00, 0x14][..], &[n as u8; 20]].concat(),
+                            }
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        let payload = undo.encode();
+        let mut rev = vec![0xf9, 0xbe, 0xb4, 0xd9];
+        rev.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        rev.extend_from_slice(&payload);
+        rev.extend_from_slice(&[0u8; 32]);
+
+        // Filtered syncs read the undo record while indexing, the others after it.
+        for block_filters in [false, true] {
+            let datadir = temp_dir("dense_undo_prevouts");
+            let blocks_dir = datadir.join("blocks");
+            fs::create_dir_all(&blocks_dir)?;
+            write_single_block_file(&blocks_dir, bitcoin_test_data::blocks::mainnet_702861())?;
+            fs::write(blocks_dir.join("rev00000.dat"), &rev)?;
+            let index_dir = datadir.join("index");
+            fs::create_dir_all(&index_dir)?;
+            let mut builder = DenseStorageBuilder::new(datadir, index_dir, 0..1, vec![])
+                .with_undo_hints(vec![UndoHint {
+                    height: 0,
+                    file_no: 0,
+                    undo_pos: 8,
+                }]);
+            if block_filters {
+                builder = builder.with_block_filters();
+            }
+            let storage = builder.build()?;
+
+            let coinbase_in = storage.get_txin_ids(TxId::new(0)).next().unwrap();
+            assert_eq!(storage.spent_txout(coinbase_in), None);
+
+            let mut from_undo = 0;
+            for (i, tx_undo) in undo.txs.iter().enumerate() {
+                let in_ids = storage.get_txin_ids(TxId::new(i as u32 + 1));
+                for (in_id, spent) in in_ids.zip(&tx_undo.spent) {
+                    let txout = storage.spent_txout(in_id).expect("every input is resolved");
+                    match storage.prevout_for_in(in_id) {
+                        // Outputs created earlier in the block come from the index, not undo data.
+                        Some(out_id) => {
+                            assert_eq!(txout, storage.get_txout(out_id));
+                            assert_eq!(storage.undo_spent_output(in_id), None);
+                        }
+                        None => {
+                            assert_eq!(txout.value.to_sat(), spent.value);
+                            assert_eq!(txout.script_pubkey.as_bytes(), spent.script_pubkey);
+                            assert_eq!(storage.undo_spent_output(in_id).as_ref(), Some(spent));
+                            from_undo += 1;
+                        }
+                    }
+                }
+            }
+            assert!(from_undo > 0);
+            if block_filters {
+                let undo_script = &undo.txs[0].spent[0].script_pubkey;
+                assert_eq!(storage.filter_matches_any(0, &[undo_script]), Some(true));
+            }
+
+            let storage = UnifiedStorage::from(storage);
+            let tx = storage.dense_txids_from(0).nth(1).unwrap().with(&storage);
+            let expected: u64 = undo.txs[0].spent.iter().map(|spent| spent.value).sum();
+            assert_eq!(tx.input_values().map(Amount::to_sat).sum::<u64>(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fixture_median_time_past_and_height_for_time() -> Result<()> {
+        let datadir = fixture_dir("fixture_mtp_datadir")?;
+        let chain = fixture_chain_hashes(&datadir)?;
+        let storage =
+            DenseStorageBuilder::sync_from_genesis(datadir, temp_dir("fixture_mtp"))?.build()?;
+
+        let mut prev_mtp = 0;
+        for (height, hash) in chain.iter().enumerate() {
+            let height = height as u64;
+            let header = storage.block_header(height).expect("indexed height");
+            assert_eq!(header.block_hash().to_byte_array(), *hash);
+            if height > 0 {
+                assert_eq!(
+                    header.prev_blockhash.to_byte_array(),
+                    chain[height as usize - 1]
+                );
+            }
+
+            let first = height.saturating_sub(10);
+            let mut times: Vec<u32> = (first..=height)
+                .map(|h| storage.block_time(h).unwrap())
+                .collect();
+            times.sort_unstable();
+            let mtp = storage.median_time_past(height).unwrap();
+            assert_eq!(mtp, times[times.len() / 2]);
+            assert!(mtp >= prev_mtp);
+            prev_mtp = mtp;
+
+            let found = storage.height_for_time(mtp).unwrap();
+            assert!(found <= height);
+            assert_eq!(storage.median_time_past(found), Some(mtp));
+            if found > 0 {
+                assert!(storage.median_time_past(found - 1).unwrap() < mtp);
+            }
+        }
+        assert_eq!(storage.height_for_time(prev_mtp + 1), None);
+        assert_eq!(storage.height_for_time(0), Some(0));
+
+        let storage = UnifiedStorage::from(storage);
+        for txid in storage.dense_txids_from(0) {
+            let tx = txid.with(&storage);
+            let height = tx.block_height().unwrap();
+            assert_eq!(tx.median_time_past(), storage.median_time_past(height));
+            assert!(tx.median_time_past().is_some());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fixture_parallel_parse_matches_sequential() -> Result<()> {
+        let datadir = fixture_dir("fixture_parse_datadir")?;
+        let build = |prefix: &str, threads: usize| -> Result<PathBuf> {
+            let index_dir = temp_dir(prefix);
+            DenseStorageBuilder::sync_from_genesis(datadir.clone(), index_dir.clone())?
+                .with_parse_threads(threads)
+                .build()?;
+            Ok(index_dir)
+        };
+        let sequential = build("fixture_parse_sequential", 1)?;
+        let parallel = build("fixture_parse_parallel", 3)?;
+
+        let mut compared = 0;
+        for entry in fs::read_dir(&sequential)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "bin") {
+                let name = path.file_name().unwrap();
+                assert_eq!(
+                    fs::read(&path)?,
+                    fs::read(parallel.join(name))?,
+                    "{name:?} differs"
+                );
+                compared += 1;
+            }
+        }
+        assert!(compared > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn fixture_sync_resumes_after_last_indexed_block() -> Result<()> {
+        use crate::parser::collect_file_hints;
+        use bitcoin_block_index::BlockIndex;
+
+        let datadir = fixture_dir("fixture_resume_datadir")?;
+        let chain = fixture_chain_hashes(&datadir)?;
+        let index_dir = temp_dir("fixture_resume");
+
+        let file_hints = {
+            let mut index = BlockIndex::open(&datadir.join("blocks/index"))?;
+            collect_file_hints(&mut index, 0, 4)?
+        };
+        let partial =
+            DenseStorageBuilder::new(datadir.clone(), index_dir.clone(), 0..5, file_hints)
+                .build()?;
+        assert_eq!(partial.indexed_heights(), 0..5);
+        assert_eq!(partial.tx_count(), 5);
+        drop(partial);
+
+        let resumed = DenseStorageBuilder::sync_from_genesis(datadir, index_dir)?.build()?;
+        assert_matches_fixture_chain(&resumed, &chain);
+        Ok(())
+    }
+
+    #[test]
+    fn fixture_sync_rolls_back_blocks_off_the_best_chain() -> Result<()> {
+        let datadir = fixture_dir("fixture_reorg_datadir")?;
+        let chain = fixture_chain_hashes(&datadir)?;
+        let index_dir = temp_dir("fixture_reorg");
+
+        let full =
+            DenseStorageBuilder::sync_from_genesis(datadir.clone(), index_dir.clone())?.build()?;
+        assert_matches_fixture_chain(&full, &chain);
+        drop(full);
+
+        // Pretend the index followed a fork from height 3 on, by storing hashes the node
+        // does not know.
+        let mut hashes = fs::read(index_dir.join("block_hash.bin"))?;
+        hashes[3 * 32..].fill(0xab);
+        fs::write(index_dir.join("block_hash.bin"), hashes)?;
+
+        let rebuilt = DenseStorageBuilder::sync_from_genesis(datadir, index_dir)?.build()?;
+        assert_matches_fixture_chain(&rebuilt, &chain);
+        Ok(())
+    }
+
+    #[test]
+    fn fixture_verify_reports_corrupted_columns() -> Result<()> {
+        use crate::dense::VerifyIssue;
+
+        let datadir = fixture_dir("fixture_verify_datadir")?;
+        let index_dir = temp_dir("fixture_verify");
+        let storage =
+            DenseStorageBuilder::sync_from_genesis(datadir.clone(), index_dir.clone())?.build()?;
+        let report = storage.verify(1)?;
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.sampled_txs, report.txs);
+        assert_eq!(report.spk_entries, report.outputs);
+        drop(storage);
+
+        let mut block_hashes = fs::read(index_dir.join("block_hash.bin"))?;
+        block_hashes[32] ^= 1;
+        fs::write(index_dir.join("block_hash.bin"), block_hashes)?;
+        let mut values = fs::read(index_dir.join("out_value.bin"))?;
+        values[0] ^= 1;
+        fs::write(index_dir.join("out_value.bin"), values)?;
+
+        // Opening the index to verify it must leave every index file as it found it.
+        let index_files = || -> Result<Vec<(Vec<u8>, PathBuf)>> {
+            let mut paths = fs::read_dir(&index_dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.retain(|path| path.is_file());
+            paths.sort();
+            paths
+                .into_iter()
+                .map(|path| Ok((fs::read(&path)?, path)))
+                .collect()
+        };
+        let before = index_files()?;
+        let storage = DenseStorage::open_read_only(datadir.clone(), index_dir.clone())?;
+        let report = storage.verify(1)?;
+        assert_eq!(
+            report.issues,
+            vec![
+                VerifyIssue::BlockHashMismatch { height: 1 },
+                VerifyIssue::OutputMismatch {
+                    out_id: TxOutId::new(0),
+                    column: "out_value",
+                },
+            ]
+        );
+        drop(storage);
+        assert_eq!(index_files()?, before);
+
+        let empty = temp_dir("fixture_verify_no_index");
+        assert!(DenseStorage::open_read_only(datadir, empty.clone()).is_err());
+        assert_eq!(fs::read_dir(&empty)?.count(), 0);
+        Ok(())
+    }
+
+    /// Copy the fixture into a new datadir and prune blk00000.dat from it the way Bitcoin
+    /// Core does: delete the file, reset its file info, clear the data flags of its blocks and
+    /// set the `prunedblockfiles` flag.
+    fn pruned_fixture(prefix: &str, chain: &[[u8; 32]]) -> Result<PathBuf> {
+        use bitcoin_block_index::{read_varint, write_varint};
+
+        let datadir = fixture_dir(prefix)?;
+        let blocks_dir = datadir.join("blocks");
+        fs::remove_file(blocks_dir.join("blk00000.dat"))?;
+
+        let mut db = rusty_leveldb::DB::open(blocks_dir.join("index"), Default::default())?;
+        for hash in chain {
+            let key = [&b"b"[..], hash].concat();
+            let raw = db.get(&key).expect("block is indexed");
+            let mut cursor = std::io::Cursor::new(&raw[..]);
+            let fields: Vec<u64> = (0..5)
+                .map(|_| read_varint(&mut cursor))
+                .collect::<Result<_, _>>()?;
+            let (status, n_file) = (fields[2], fields[4]);
+            if n_file != 0 {
+                continue;
+            }
+            // Skip the data and undo positions to reach the header.
+            read_varint(&mut cursor)?;
+            if status & 16 != 0 {
+                read_varint(&mut cursor)?;
+            }
+            let mut value = Vec::new();
+            write_varint(&mut value, fields[0]);
+            write_varint(&mut value, fields[1]);
+            write_varint(&mut value, status & !(8 | 16));
+            write_varint(&mut value, fields[3]);
+            value.extend_from_slice(&raw[cursor.position() as usize..]);
+            db.put(&key, &value)?;
+        }
+        db.put(&[&b"f"[..], &0u32.to_le_bytes()].concat(), &[0; 7])?;
+        db.put(b"F\x10prunedblockfiles", b"1")?;
+        db.flush()?;
+        Ok(datadir)
+    }
+
+    #[test]
+    fn fixture_sync_on_pruned_node_starts_at_first_stored_block() -> Result<()> {
+        use bitcoin_block_index::BlockIndex;
+
+        let unpruned = fixture_dir("fixture_unpruned")?;
+        let chain = fixture_chain_hashes(&unpruned)?;
+        let first_stored = BlockIndex::open(&unpruned.join("blocks/index"))?
+            .block_file_info(1)?
+            .height_first as u64;
+        assert!(first_stored > 0);
+        let datadir = pruned_fixture("fixture_pruned", &chain)?;
+
+        let index_dir = temp_dir("fixture_pruned_index");
+        let builder = DenseStorageBuilder::sync_from_genesis(datadir.clone(), index_dir.clone())?;
+        assert_eq!(builder.indexed_heights(), first_stored..chain.len() as u64);
+        let storage = builder.build()?;
+        assert_eq!(storage.indexed_heights(), first_stored..chain.len() as u64);
+        for height in storage.indexed_heights() {
+            let got = storage.block_hash(height).expect("indexed height");
+            assert_eq!(got.to_byte_array(), chain[height as usize]);
+        }
+        // The fixture holds coinbases only, so the first stored block has nothing to resolve;
+        // see `prevouts_before_first_indexed_block_are_unresolved` for spends.
+        let storage = UnifiedStorage::from(storage);
+        let first_tx = storage.dense_txids_from(0).next().unwrap().with(&storage);
+        assert_eq!(first_tx.block_height(), Some(first_stored));
+        for input in first_tx.inputs() {
+            assert!(input.prev_txout().is_none());
+            assert!(input.spent_txout().is_none());
+        }
+        drop(storage);
+
+        // An index that ends before the pruned blocks cannot be continued.
+        let behind = temp_dir("fixture_pruned_behind");
+        let file_hints = {
+            let mut index = BlockIndex::open(&unpruned.join("blocks/index"))?;
+            crate::parser::collect_file_hints(&mut index, 0, 0)?
+        };
+        DenseStorageBuilder::new(unpruned, behind.clone(), 0..1, file_hints).build()?;
+        assert!(matches!(
+            DenseStorageBuilder::sync_from_genesis(datadir, behind),
+            Err(crate::parser::BlockFileError::Pruned { height: 1, first_stored: h })
+                if h == first_stored
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn prevouts_before_first_indexed_block_are_unresolved() -> Result<()> {
+        // The index starts at block 702861 without undo data for it, so only inputs spending
+        // an output of the block itself resolve.
+        let storage: UnifiedStorage = mainnet_702861_storage("unresolved_prevouts")?.into();
+        let block: bitcoin::Block =
+            bitcoin::consensus::deserialize(bitcoin_test_data::blocks::mainnet_702861())?;
+        let in_block: std::collections::HashSet<_> =
+            block.txdata.iter().map(|tx| tx.compute_txid()).collect();
+
+        let mut unresolved = 0;
+        for (txid, expected) in storage.dense_txids_from(0).zip(&block.txdata).skip(1) {
+            for (input, txin) in txid.with(&storage).inputs().zip(&expected.input) {
+                if in_block.contains(&txin.previous_output.txid) {
+                    assert!(input.prev_txout().is_some());
+                    continue;
+                }
+                assert!(input.prev_txout().is_none());
+                assert!(input.spent_txout().is_none());
+                assert_eq!(input.output_type(), None);
+                unresolved += 1;
+            }
+        }
+        assert!(unresolved > 0);
+        Ok(())
+    }
+
     #[test]
     fn build_indices_stops_at_logical_blk_size() -> Result<()> {
-        let fixture_blocks = fixture_dir().join("blocks");
+        let fixture_blocks = fixture_dir("preallocated_blk_fixture")?.join("blocks");
         let plaintext = fs::read(fixture_blocks.join("blk00000.dat"))?;
         let logical_size = plaintext.len();
 
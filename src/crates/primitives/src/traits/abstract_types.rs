@@ -1,4 +1,4 @@
-use bitcoin::Amount;
+use bitcoin::{Amount, Weight, hashes::Hash};
 
 use crate::{AnyOutId, OutputType, ScriptPubkeyHash, classify_script_pubkey};
 
@@ -50,6 +50,23 @@ pub trait AbstractTransaction: HasNLockTime + HasVersion {
     /// Returns the output at the given index, if it exists
     fn output_at(&self, index: usize) -> Option<Box<dyn AbstractTxOut + '_>>;
     fn is_coinbase(&self) -> bool;
+    /// Txid in internal byte order. The default rebuilds and re-serializes the transaction;
+    /// types holding the raw bytes should hash those instead.
+    fn txid_bytes(&self) -> [u8; 32] {
+        crate::loose::to_bitcoin_tx(self)
+            .compute_txid()
+            .to_byte_array()
+    }
+    /// Segwit-aware weight of the serialized transaction. The default rebuilds and
+    /// re-serializes the transaction; types holding the raw bytes should measure those instead.
+    fn weight(&self) -> Weight {
+        serialized_weight(self)
+    }
+}
+
+/// Weight of `tx` as it would be serialized.
+pub(crate) fn serialized_weight<T: AbstractTransaction + ?Sized>(tx: &T) -> Weight {
+    crate::loose::to_bitcoin_tx(tx).weight()
 }
 
 /// Transaction nlocktime value
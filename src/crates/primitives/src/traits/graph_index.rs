@@ -1,7 +1,7 @@
 use bitcoin::Amount;
 
 use crate::traits::abstract_types::AbstractTransaction;
-use crate::{AnyInId, AnyOutId, AnyTxId, ScriptPubkeyHash};
+use crate::{AnyInId, AnyOutId, AnyTxId, OutputType, ScriptPubkeyHash};
 
 pub trait PrevOutIndex {
     // TODO: consider handle wrappers converting ids to the actual types.
@@ -20,6 +20,8 @@ pub trait TxInOwnerIndex {
 pub trait ScriptPubkeyIndex {
     /// Returns the first transaction output ID that uses the given script pubkey.
     fn script_pubkey_to_txout_id(&self, script_pubkey: &ScriptPubkeyHash) -> Option<AnyOutId>;
+    /// Returns every transaction output ID that uses the given script pubkey, in chain order.
+    fn outputs_for_script_pubkey(&self, script_pubkey: &ScriptPubkeyHash) -> Vec<AnyOutId>;
 }
 
 pub trait TxIndex {
@@ -38,8 +40,19 @@ pub trait TxIoIndex {
     fn script_sig_bytes(&self, in_id: &AnyInId) -> Vec<u8>;
     // block_height is optional because loose transactions are not confirmed.
     fn block_height(&self, txid: &AnyTxId) -> Option<u64>;
+    /// Header timestamp of the block containing the transaction.
+    fn block_time(&self, txid: &AnyTxId) -> Option<u32>;
+    fn block_hash(&self, txid: &AnyTxId) -> Option<bitcoin::BlockHash>;
+    /// Median-time-past of the block containing the transaction.
+    fn median_time_past(&self, txid: &AnyTxId) -> Option<u32>;
     fn prev_outpoint_txid_bytes(&self, in_id: &AnyInId) -> [u8; 32];
     fn prev_outpoint_vout(&self, in_id: &AnyInId) -> u32;
+    /// Output spent by the input, or `None` for a coinbase input or a prevout the storage
+    /// has no data for.
+    fn spent_txout(&self, in_id: &AnyInId) -> Option<bitcoin::TxOut>;
+    /// Height of the block that created the output spent by the input, for prevouts created
+    /// before the indexed range whose undo data was read at sync time. `None` otherwise.
+    fn undo_spent_height(&self, in_id: &AnyInId) -> Option<u64>;
 }
 
 pub trait OutpointIndex {
@@ -53,6 +66,7 @@ pub trait TxOutDataIndex {
     // TODO: this is redundant with the script_pubkey_bytes method. Should always be able to get the hash from the spk
     fn script_pubkey_hash(&self, out_id: &AnyOutId) -> ScriptPubkeyHash;
     fn script_pubkey_bytes(&self, out_id: &AnyOutId) -> Vec<u8>;
+    fn output_type(&self, out_id: &AnyOutId) -> OutputType;
 }
 
 pub trait IndexedGraph:
@@ -67,4 +81,9 @@ pub trait IndexedGraph:
     + OutpointIndex
     + TxOutDataIndex
 {
+    /// Network used to render output scripts as addresses. Loose-only indexes have no
+    /// network of their own and use mainnet.
+    fn network(&self) -> bitcoin::Network {
+        bitcoin::Network::Bitcoin
+    }
 }
@@ -10,7 +10,13 @@ pub trait IndexSink {
     ) -> Result<(), Self::Error>;
 
     /// Called for each output in the current tx (before `on_transaction`).
-    fn on_output(&mut self, vout: usize, script_pubkey: &[u8]) -> Result<(), Self::Error>;
+    /// `value` is the output amount in satoshis.
+    fn on_output(
+        &mut self,
+        vout: usize,
+        value: u64,
+        script_pubkey: &[u8],
+    ) -> Result<(), Self::Error>;
 
     /// Called after all inputs/outputs of a tx have been visited.
     /// `tx_bytes` is the raw serialized transaction (same bytes the visitor walked).
@@ -23,6 +29,12 @@ pub trait IndexSink {
         tx_bytes: &[u8],
     ) -> Result<(), Self::Error>;
 
-    /// Called once per block, after all its transactions, with the per-block tx count.
-    fn on_block_end(&mut self, block_tx_count: u64) -> Result<(), Self::Error>;
+    /// Called once per block, after all its transactions, with the raw 80-byte header,
+    /// the block hash (internal byte order) and the per-block tx count.
+    fn on_block_end(
+        &mut self,
+        header: &[u8; 80],
+        block_hash: &[u8; 32],
+        block_tx_count: u64,
+    ) -> Result<(), Self::Error>;
 }
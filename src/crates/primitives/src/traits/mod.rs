@@ -10,20 +10,56 @@ pub use abstract_types::{
 pub use index_sink::IndexSink;
 
 use crate::ScriptPubkeyHash;
-use crate::dense::TxOutId;
+use crate::dense::{TxId, TxInId, TxOutId};
+use bitcoin_block_index::undo::SpentOutput;
 
-/// Embedded key-value database for mapping script pubkey hashes to the first
-/// TxOutId that uses them (dense representation).
+/// Embedded key-value database mapping script pubkey hashes to every dense TxOutId
+/// that pays them.
 pub trait ScriptPubkeyDb {
     type Error: std::error::Error;
 
     /// Returns the first dense TxOutId for the given script pubkey hash, if any.
     fn get(&self, spk_hash: &ScriptPubkeyHash) -> Result<Option<TxOutId>, Self::Error>;
 
-    /// Inserts the first dense TxOutId for the given script pubkey hash, if absent.
-    fn insert_if_absent(
-        &mut self,
-        spk_hash: ScriptPubkeyHash,
-        out_id: TxOutId,
-    ) -> Result<(), Self::Error>;
+    /// Returns every dense TxOutId for the given script pubkey hash, in chain order.
+    fn out_ids(&self, spk_hash: &ScriptPubkeyHash) -> Result<Vec<TxOutId>, Self::Error>;
+
+    /// Records that `out_id` pays the given script pubkey hash.
+    fn insert(&mut self, spk_hash: ScriptPubkeyHash, out_id: TxOutId) -> Result<(), Self::Error>;
+
+    /// Removes `out_id` from the outputs of the given script pubkey hash.
+    /// Used to undo [`ScriptPubkeyDb::insert`] when blocks are rolled back.
+    fn remove(&mut self, spk_hash: &ScriptPubkeyHash, out_id: TxOutId) -> Result<(), Self::Error>;
+}
+
+/// Embedded key-value database mapping txids to dense TxIds.
+pub trait TxidDb {
+    type Error: std::error::Error;
+
+    /// Returns the dense TxId for the given txid (internal byte order), if indexed.
+    fn get(&self, txid: &[u8; 32]) -> Result<Option<TxId>, Self::Error>;
+
+    /// Maps `txid` to `dense_txid`. A duplicate txid resolves to the latest transaction,
+    /// which is the one its outputs can be spent from.
+    fn insert(&mut self, txid: [u8; 32], dense_txid: TxId) -> Result<(), Self::Error>;
+
+    /// Removes the entry for `txid` if it points at `dense_txid`, restoring the mapping the
+    /// insert of `dense_txid` replaced. Used to undo inserts, newest first, when blocks are
+    /// rolled back.
+    fn remove(&mut self, txid: &[u8; 32], dense_txid: TxId) -> Result<(), Self::Error>;
+}
+
+/// Embedded key-value database holding, for dense inputs whose prevout was created before the
+/// indexed range, the spent output as recorded in Bitcoin Core's undo data.
+pub trait SpentOutputDb {
+    type Error: std::error::Error;
+
+    /// Returns the output spent by `in_id`, if one was recorded.
+    fn get(&self, in_id: TxInId) -> Result<Option<SpentOutput>, Self::Error>;
+
+    /// Records the output spent by `in_id`.
+    fn insert(&mut self, in_id: TxInId, spent: &SpentOutput) -> Result<(), Self::Error>;
+
+    /// Removes the entries of `in_id` and every later input. Used when blocks are rolled back.
+    fn remove_from(&mut self, in_id: TxInId) -> Result<(), Self::Error>;
 }
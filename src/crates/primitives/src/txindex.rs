@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use bitcoin::hashes::Hash;
+use bitcoin_block_index::{TxIndex, TxLocation};
+use bitcoin_slices::{Parse, bsl};
+
+use crate::{blk_file::BlkFileStore, loose::ConfirmedTx, parser::BlockFileError};
+
+const BLOCK_HEADER_LEN: usize = 80;
+
+/// Transaction lookups by txid through the index Bitcoin Core keeps with `-txindex`.
+///
+/// Resolves any confirmed transaction to its blk file location and raw bytes without
+/// building a dense index first.
+pub struct NodeTxIndex {
+    index: TxIndex,
+    store: BlkFileStore,
+}
+
+impl NodeTxIndex {
+    /// Open the txindex of a node.
+    ///
+    /// `data_dir` is Bitcoin Core's data directory (e.g. `~/.bitcoin/` or
+    /// `~/.bitcoin/regtest/`); `indexes/txindex/` and `blocks/` are derived from it.
+    pub fn open(data_dir: &Path) -> Result<Self, BlockFileError> {
+        let index =
+            TxIndex::open(&data_dir.join("indexes/txindex")).map_err(BlockFileError::BlockIndex)?;
+        Ok(Self {
+            index,
+            store: BlkFileStore::open(data_dir.join("blocks")),
+        })
+    }
+
+    /// Hash of the block the node's txindex is synced up to.
+    pub fn best_block(&mut self) -> Result<bitcoin::BlockHash, BlockFileError> {
+        let hash = self
+            .index
+            .best_block()
+            .map_err(BlockFileError::BlockIndex)?;
+        Ok(bitcoin::BlockHash::from_byte_array(hash))
+    }
+
+    /// Where the transaction is stored, or `None` if the txindex does not know it.
+    pub fn tx_location(
+        &mut self,
+        txid: &bitcoin::Txid,
+    ) -> Result<Option<TxLocation>, BlockFileError> {
+        self.index
+            .tx_location(&txid.to_byte_array())
+            .map_err(BlockFileError::BlockIndex)
+    }
+
+    /// Raw serialized bytes of the transaction, or `None` if the txindex does not know it.
+    pub fn raw_tx(&mut self, txid: &bitcoin::Txid) -> Result<Option<Vec<u8>>, BlockFileError> {
+        let Some(loc) = self.tx_location(txid)? else {
+            return Ok(None);
+        };
+        // The index does not record transaction lengths, so read the whole block and let
+        // the parser find where the transaction ends.
+        let block = self
+            .store
+            .read_block(loc.n_file, loc.block_pos)
+            .map_err(BlockFileError::Io)?;
+        let start = BLOCK_HEADER_LEN + loc.tx_offset as usize;
+        let tx_bytes = block.get(start..).ok_or(BlockFileError::UnexpectedEof {
+            offset: start,
+            len: block.len(),
+        })?;
+        let tx_len = bsl::Transaction::parse(tx_bytes)
+            .map_err(BlockFileError::Parse)?
+            .consumed();
+        Ok(Some(tx_bytes[..tx_len].to_vec()))
+    }
+
+    /// The transaction as a [`ConfirmedTx`], or `None` if the txindex does not know it.
+    pub fn tx(&mut self, txid: &bitcoin::Txid) -> Result<Option<ConfirmedTx>, BlockFileError> {
+        Ok(self
+            .raw_tx(txid)?
+            .map(|bytes| ConfirmedTx::new(bytes.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{temp_dir, write_single_block_file};
+    use bitcoin::consensus::encode::VarInt;
+    use bitcoin_block_index::write_varint;
+
+    /// Write a txindex LevelDB for mainnet block 702861, stored as the only block of
+    /// blk00000.dat, the way Bitcoin Core's `TxIndex::CustomAppend` does.
+    fn write_txindex(data_dir: &Path, block: &bitcoin::Block) {
+        let opts = rusty_leveldb::Options {
+            create_if_missing: true,
+            ..Default::default()
+        };
+        let mut db = rusty_leveldb::DB::open(data_dir.join("indexes/txindex"), opts).unwrap();
+        let mut tx_offset = VarInt(block.txdata.len() as u64).size() as u64;
+        for tx in &block.txdata {
+            let mut value = Vec::new();
+            write_varint(&mut value, 0);
+            // Block data starts after the 4-byte magic and 4-byte length.
+            write_varint(&mut value, 8);
+            write_varint(&mut value, tx_offset);
+            let key = [&b"t"[..], &tx.compute_txid().to_byte_array()].concat();
+            db.put(&key, &value).unwrap();
+            tx_offset += bitcoin::consensus::serialize(tx).len() as u64;
+        }
+        let mut locator = 0i32.to_le_bytes().to_vec();
+        locator.push(1);
+        locator.extend_from_slice(&block.block_hash().to_byte_array());
+        db.put(b"B", &locator).unwrap();
+        db.flush().unwrap();
+    }
+
+    #[test]
+    fn raw_tx_matches_block_transactions() {
+        let block_bytes = bitcoin_test_data::blocks::mainnet_702861();
+        let block: bitcoin::Block = bitcoin::consensus::deserialize(block_bytes).unwrap();
+        let data_dir = temp_dir("node_txindex");
+        std::fs::create_dir_all(data_dir.join("blocks")).unwrap();
+        write_single_block_file(&data_dir.join("blocks"), block_bytes).unwrap();
+        write_txindex(&data_dir, &block);
+
+        let mut index = NodeTxIndex::open(&data_dir).unwrap();
+        assert_eq!(index.best_block().unwrap(), block.block_hash());
+        for tx in &block.txdata {
+            let raw = index.raw_tx(&tx.compute_txid()).unwrap().unwrap();
+            assert_eq!(raw, bitcoin::consensus::serialize(tx));
+        }
+        let last = block.txdata.last().unwrap().compute_txid();
+        let loc = index.tx_location(&last).unwrap().unwrap();
+        assert_eq!(loc.n_file, 0);
+        assert_eq!(loc.block_pos, 8);
+        assert_eq!(
+            index.raw_tx(&bitcoin::Txid::all_zeros()).unwrap(),
+            None,
+            "unknown txids are not an error"
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use crate::HasScriptPubkey;
 use crate::dense::{DenseStorage, DenseStorageBuilder, build_indices};
 use crate::handle::{TxHandle, TxInHandle, TxOutHandle};
 use crate::loose::InMemoryIndex;
@@ -8,8 +7,17 @@ use crate::traits::graph_index::{
     IndexedGraph, OutpointIndex, PrevOutIndex, ScriptPubkeyIndex, TxInIndex, TxInOwnerIndex,
     TxIndex, TxIoIndex, TxOutDataIndex,
 };
-use crate::{ScriptPubkeyHash, dense, loose, traits::abstract_types::AbstractTransaction};
-use bitcoin::Amount;
+use crate::{
+    OutputType, ScriptPubkeyHash, dense, loose, traits::abstract_types::AbstractTransaction,
+};
+use bitcoin::{
+    Amount,
+    hashes::{Hash, hash160},
+};
+use std::collections::HashMap;
+
+/// Longest script the node will ever execute; longer output scripts are provably unspendable.
+const MAX_SCRIPT_SIZE: usize = 10_000;
 
 #[repr(transparent)]
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Ord, PartialOrd)]
@@ -186,14 +194,16 @@ impl From<loose::TxInId> for AnyInId {
 pub struct UnifiedStorage {
     dense: Option<DenseStorage>,
     loose: Option<InMemoryIndex>,
+    /// Loose inputs whose prevout is a dense output.
+    loose_prevouts_in_dense: HashMap<loose::TxInId, dense::TxOutId>,
+    /// Dense outputs spent by a loose input, for outputs no dense input spends.
+    dense_spent_by_loose: HashMap<dense::TxOutId, loose::TxInId>,
+    network: bitcoin::Network,
 }
 
 impl From<LooseIndexBuilder> for UnifiedStorage {
     fn from(builder: LooseIndexBuilder) -> Self {
-        Self {
-            dense: None,
-            loose: Some(builder.build()),
-        }
+        Self::from(builder.build())
     }
 }
 
@@ -202,6 +212,9 @@ impl From<InMemoryIndex> for UnifiedStorage {
         Self {
             dense: None,
             loose: Some(index),
+            loose_prevouts_in_dense: HashMap::new(),
+            dense_spent_by_loose: HashMap::new(),
+            network: bitcoin::Network::Bitcoin,
         }
     }
 }
@@ -211,18 +224,18 @@ impl TryFrom<DenseStorageBuilder> for UnifiedStorage {
     type Error = SyncError;
 
     fn try_from(builder: DenseStorageBuilder) -> Result<Self, Self::Error> {
-        Ok(Self {
-            dense: Some(build_indices(builder)?),
-            loose: None,
-        })
+        Ok(Self::from(build_indices(builder)?))
     }
 }
 
 impl From<DenseStorage> for UnifiedStorage {
     fn from(dense: DenseStorage) -> Self {
         Self {
+            network: dense.network().unwrap_or(bitcoin::Network::Bitcoin),
             dense: Some(dense),
             loose: None,
+            loose_prevouts_in_dense: HashMap::new(),
+            dense_spent_by_loose: HashMap::new(),
         }
     }
 }
@@ -233,6 +246,21 @@ pub enum SyncError {
     BlockIndex(bitcoin_block_index::Error),
     Parse(BlockFileError),
     Sled(sled::Error),
+    /// The requested range does not start right after the last indexed block.
+    NonContiguousRange {
+        indexed_end: u64,
+        requested_start: u64,
+    },
+    /// The index was created for a different network than the one requested.
+    NetworkMismatch {
+        indexed: bitcoin::Network,
+        requested: bitcoin::Network,
+    },
+    /// Block filters were requested for an index that was built without them.
+    MissingBlockFilters {
+        filtered_end: u64,
+        indexed_end: u64,
+    },
 }
 
 impl std::fmt::Display for SyncError {
@@ -241,6 +269,23 @@ impl std::fmt::Display for SyncError {
             SyncError::BlockIndex(e) => write!(f, "block index: {e}"),
             SyncError::Parse(e) => write!(f, "parse: {e}"),
             SyncError::Sled(e) => write!(f, "sled: {e}"),
+            SyncError::NonContiguousRange {
+                indexed_end,
+                requested_start,
+            } => write!(
+                f,
+                "requested blocks from height {requested_start}, but the index ends before height {indexed_end}"
+            ),
+            SyncError::NetworkMismatch { indexed, requested } => {
+                write!(f, "index was built for {indexed}, not {requested}")
+            }
+            SyncError::MissingBlockFilters {
+                filtered_end,
+                indexed_end,
+            } => write!(
+                f,
+                "block filters end before height {filtered_end}, but the index reaches height {indexed_end}; remove the index and sync again with filters"
+            ),
         }
     }
 }
@@ -248,11 +293,91 @@ impl std::fmt::Display for SyncError {
 impl std::error::Error for SyncError {}
 
 impl UnifiedStorage {
-    pub fn with_loose(mut self, builder: LooseIndexBuilder) -> Self {
-        self.loose = Some(builder.build());
+    /// Render and parse addresses for `network`. Storage built from a [`DenseStorage`] uses
+    /// the network recorded in the index and fails with [`SyncError::NetworkMismatch`] for any
+    /// other; loose-only storage and indexes without a recorded network start out with
+    /// mainnet.
+    pub fn with_network(mut self, network: bitcoin::Network) -> Result<Self, SyncError> {
+        if let Some(indexed) = self.dense.as_ref().and_then(DenseStorage::network)
+            && indexed != network
+        {
+            return Err(SyncError::NetworkMismatch {
+                indexed,
+                requested: network,
+            });
+        }
+        self.network = network;
+        Ok(self)
+    }
+
+    pub fn network(&self) -> bitcoin::Network {
+        self.network
+    }
+
+    pub fn with_loose(self, builder: LooseIndexBuilder) -> Self {
+        self.with_loose_index(builder.build())
+    }
+
+    /// Layer `index` on top of the dense storage. Loose inputs spending a confirmed output
+    /// are linked to it, so prevout and spender lookups cross between the two.
+    pub fn with_loose_index(mut self, mut index: InMemoryIndex) -> Self {
+        self.loose_prevouts_in_dense.clear();
+        self.dense_spent_by_loose.clear();
+        if let Some(ds) = self.dense.as_ref() {
+            (self.loose_prevouts_in_dense, self.dense_spent_by_loose) =
+                Self::link_loose_to_dense(ds, &mut index);
+        }
+        self.loose = Some(index);
         self
     }
 
+    /// Resolve every loose input whose prevout txid is confirmed in `ds`. Returns the
+    /// loose-input-to-dense-prevout links and their reverse.
+    #[allow(clippy::type_complexity)]
+    fn link_loose_to_dense(
+        ds: &DenseStorage,
+        index: &mut InMemoryIndex,
+    ) -> (
+        HashMap<loose::TxInId, dense::TxOutId>,
+        HashMap<dense::TxOutId, loose::TxInId>,
+    ) {
+        let mut prevouts = HashMap::new();
+        let mut spenders = HashMap::new();
+        let mut links = Vec::new();
+        // Walked in insertion order so that, among loose txs spending the same output, the
+        // first one added wins.
+        for lid in &index.tx_order {
+            let tx = &index.txs[lid];
+            for (vin, input) in tx.inputs().enumerate() {
+                let prev_vout = input.prev_outpoint_vout();
+                let prev_txid = bitcoin::Txid::from_byte_array(input.prev_outpoint_txid_bytes());
+                let Some(prev_did) = ds.txid_for_hash(&prev_txid) else {
+                    continue;
+                };
+                let (start, end) = ds.tx_out_range(prev_did);
+                let prev_out = start + prev_vout as u64;
+                if prev_vout == u32::MAX || prev_out >= end {
+                    continue;
+                }
+                links.push((lid.txin_id(vin as u32), dense::TxOutId::new(prev_out)));
+            }
+        }
+        for (in_id, prev_out) in links {
+            // A confirmed txid takes precedence over whatever the loose index resolved the
+            // input to on its own.
+            if let Some(stale) = index.prev_txouts.remove(&in_id)
+                && index.spending_txins.get(&stale) == Some(&in_id)
+            {
+                index.spending_txins.remove(&stale);
+            }
+            prevouts.insert(in_id, prev_out);
+            if ds.spender_for_out(prev_out).is_none() {
+                spenders.entry(prev_out).or_insert(in_id);
+            }
+        }
+        (prevouts, spenders)
+    }
+
     #[inline(always)]
     fn loose(&self) -> &InMemoryIndex {
         self.loose.as_ref().expect("loose storage not initialized")
@@ -389,23 +514,62 @@ impl UnifiedStorage {
         self.resolve_out(
             out_id,
             |ls, lid| ls.spending_txins.get(&lid).copied().map(AnyInId::from),
-            |ds, did| ds.spender_for_out(did).map(AnyInId::from),
+            |ds, did| {
+                ds.spender_for_out(did).map(AnyInId::from).or_else(|| {
+                    self.dense_spent_by_loose
+                        .get(&did)
+                        .copied()
+                        .map(AnyInId::from)
+                })
+            },
         )
     }
 
-    // TODO: support confirmed tx access
-    pub fn tx(&self, txid: AnyTxId) -> std::sync::Arc<dyn AbstractTransaction + Send + Sync> {
-        if let Some(loose_txid) = txid.loose_txid() {
-            return self.loose_tx(loose_txid).clone();
+    /// Whether `out_id` was in the UTXO set once block `height` was connected: confirmed at or
+    /// below `height`, not provably unspendable, and not spent by a transaction confirmed at or
+    /// below `height`. Like the node's `IsUnspendable`, a script is provably unspendable if it
+    /// starts with `OP_RETURN` or is longer than 10,000 bytes; other non-standard scripts count.
+    ///
+    /// Loose outputs are never part of a confirmed UTXO set, and loose spenders are ignored.
+    pub fn is_unspent_at(&self, out_id: AnyOutId, height: u64) -> bool {
+        let Some(did) = out_id.confirmed_id() else {
+            return false;
+        };
+        let ds = self.dense();
+        if ds.block_of_tx(ds.txid_for_out(did)) > height {
+            return false;
+        }
+        // Oversized scripts are classified non-standard, so only those need their script read.
+        let unspendable = match ds.output_type(did) {
+            OutputType::OpReturn => true,
+            OutputType::NonStandard => ds.get_txout(did).script_pubkey.len() > MAX_SCRIPT_SIZE,
+            _ => false,
+        };
+        if unspendable {
+            return false;
+        }
+        match ds.spender_for_out(did) {
+            Some(in_id) => ds.block_of_tx(ds.txid_for_in(in_id)) > height,
+            None => true,
         }
-        todo!("confirmed tx access not supported yet")
+    }
+
+    pub fn tx(&self, txid: AnyTxId) -> std::sync::Arc<dyn AbstractTransaction + Send + Sync> {
+        self.resolve_tx(
+            txid,
+            |_, lid| self.loose_tx(lid).clone(),
+            |ds, did| std::sync::Arc::new(ds.confirmed_tx(did)),
+        )
     }
 
     pub fn script_pubkey_to_txout_id(&self, script_pubkey: &ScriptPubkeyHash) -> Option<AnyOutId> {
         if let Some(ls) = self.loose.as_ref()
-            && let Some(id) = ls.spk_to_txout_ids.get(script_pubkey).copied()
+            && let Some(id) = ls
+                .spk_to_txout_ids
+                .get(script_pubkey)
+                .and_then(|ids| ids.first())
         {
-            return Some(AnyOutId::from(id));
+            return Some(AnyOutId::from(*id));
         }
         self.dense
             .as_ref()?
@@ -413,13 +577,84 @@ impl UnifiedStorage {
             .unwrap_or(None)
             .map(AnyOutId::from)
     }
+
+    /// Median-time-past of the confirmed block at `height`.
+    /// See [`DenseStorage::median_time_past`].
+    pub fn median_time_past(&self, height: u64) -> Option<u32> {
+        self.dense.as_ref()?.median_time_past(height)
+    }
+
+    /// First confirmed height whose median-time-past is at or after `time`.
+    /// See [`DenseStorage::height_for_time`].
+    pub fn height_for_time(&self, time: u32) -> Option<u64> {
+        self.dense.as_ref()?.height_for_time(time)
+    }
+
+    /// Confirmed heights whose BIP158 filter matches any of `scripts`.
+    /// See [`DenseStorage::heights_matching`].
+    pub fn filter_matching_heights<S: AsRef<[u8]>>(&self, scripts: &[S]) -> Vec<u64> {
+        self.dense
+            .as_ref()
+            .map(|dense| dense.heights_matching(scripts))
+            .unwrap_or_default()
+    }
+
+    /// Every output paying the given script pubkey: dense outputs in chain order, followed by
+    /// loose outputs in the order they were added.
+    pub fn outputs_for_script_pubkey(&self, script_pubkey: &ScriptPubkeyHash) -> Vec<AnyOutId> {
+        let mut out_ids: Vec<AnyOutId> = self
+            .dense
+            .as_ref()
+            .map(|ds| {
+                ds.script_pubkey_to_txout_ids(script_pubkey)
+                    .unwrap_or_else(|e| {
+                        panic!("Corrupted data store: error reading spk db: {:?}", e)
+                    })
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(AnyOutId::from)
+            .collect();
+        if let Some(ls) = self.loose.as_ref()
+            && let Some(ids) = ls.spk_to_txout_ids.get(script_pubkey)
+        {
+            out_ids.extend(ids.iter().copied().map(AnyOutId::from));
+        }
+        out_ids
+    }
+
+    /// Every output paying `address`, as for [`Self::outputs_for_script_pubkey`].
+    ///
+    /// Fails if `address` does not parse or belongs to a different network than
+    /// [`Self::network`].
+    pub fn outputs_for_address(
+        &self,
+        address: &str,
+    ) -> Result<Vec<AnyOutId>, bitcoin::address::ParseError> {
+        let address = address
+            .parse::<bitcoin::Address<_>>()?
+            .require_network(self.network)?;
+        let spk_hash = hash160::Hash::hash(address.script_pubkey().as_bytes()).to_byte_array();
+        Ok(self.outputs_for_script_pubkey(&spk_hash))
+    }
 }
 
 impl PrevOutIndex for UnifiedStorage {
     fn prev_txout(&self, id: &AnyInId) -> Option<AnyOutId> {
         self.resolve_in(
             *id,
-            |ls, lid| ls.prev_txouts.get(&lid).copied().map(AnyOutId::from),
+            |ls, lid| {
+                ls.prev_txouts
+                    .get(&lid)
+                    .copied()
+                    .map(AnyOutId::from)
+                    .or_else(|| {
+                        self.loose_prevouts_in_dense
+                            .get(&lid)
+                            .copied()
+                            .map(AnyOutId::from)
+                    })
+            },
             |ds, did| ds.prevout_for_in(did).map(AnyOutId::from),
         )
     }
@@ -441,12 +676,19 @@ impl ScriptPubkeyIndex for UnifiedStorage {
     fn script_pubkey_to_txout_id(&self, script_pubkey: &ScriptPubkeyHash) -> Option<AnyOutId> {
         UnifiedStorage::script_pubkey_to_txout_id(self, script_pubkey)
     }
+
+    fn outputs_for_script_pubkey(&self, script_pubkey: &ScriptPubkeyHash) -> Vec<AnyOutId> {
+        UnifiedStorage::outputs_for_script_pubkey(self, script_pubkey)
+    }
 }
 
 impl TxIndex for UnifiedStorage {
     fn tx(&self, txid: &AnyTxId) -> Option<std::sync::Arc<dyn AbstractTransaction + Send + Sync>> {
-        let loose_txid = txid.loose_txid()?;
-        self.loose.as_ref()?.txs.get(&loose_txid).cloned()
+        if txid.is_loose() {
+            self.loose.as_ref()?.tx(txid)
+        } else {
+            self.dense.as_ref()?.tx(txid)
+        }
     }
 }
 
@@ -552,6 +794,21 @@ impl TxIoIndex for UnifiedStorage {
             .map(|did| self.dense().block_of_tx(did))
     }
 
+    fn block_time(&self, txid: &AnyTxId) -> Option<u32> {
+        let height = self.block_height(txid)?;
+        self.dense().block_time(height)
+    }
+
+    fn block_hash(&self, txid: &AnyTxId) -> Option<bitcoin::BlockHash> {
+        let height = self.block_height(txid)?;
+        self.dense().block_hash(height)
+    }
+
+    fn median_time_past(&self, txid: &AnyTxId) -> Option<u32> {
+        let height = self.block_height(txid)?;
+        self.dense().median_time_past(height)
+    }
+
     fn prev_outpoint_txid_bytes(&self, in_id: &AnyInId) -> [u8; 32] {
         self.resolve_in(
             *in_id,
@@ -593,6 +850,28 @@ impl TxIoIndex for UnifiedStorage {
             },
         )
     }
+
+    fn spent_txout(&self, in_id: &AnyInId) -> Option<bitcoin::TxOut> {
+        self.resolve_in(
+            *in_id,
+            |ls, lid| match self.loose_prevouts_in_dense.get(&lid) {
+                Some(&prev_out) => Some(self.dense().get_txout(prev_out)),
+                None => ls.spent_txout(&AnyInId::from(lid)),
+            },
+            |ds, did| ds.spent_txout(did),
+        )
+    }
+
+    fn undo_spent_height(&self, in_id: &AnyInId) -> Option<u64> {
+        self.resolve_in(
+            *in_id,
+            |_, _| None,
+            |ds, did| {
+                ds.undo_spent_output(did)
+                    .map(|spent| u64::from(spent.height))
+            },
+        )
+    }
 }
 
 impl OutpointIndex for UnifiedStorage {
@@ -620,7 +899,7 @@ impl TxOutDataIndex for UnifiedStorage {
                     .expect("txout should be present if index is built correctly")
                     .value()
             },
-            |ds, did| ds.get_txout(did).value,
+            |ds, did| ds.output_value(did),
         )
     }
 
@@ -633,7 +912,7 @@ impl TxOutDataIndex for UnifiedStorage {
                     .expect("txout should be present if index is built correctly")
                     .script_pubkey_hash()
             },
-            |ds, did| ds.get_txout(did).script_pubkey_hash(),
+            |ds, did| ds.output_spk_hash(did),
         )
     }
 
@@ -649,9 +928,26 @@ impl TxOutDataIndex for UnifiedStorage {
             |ds, did| ds.get_txout(did).script_pubkey.to_bytes(),
         )
     }
+
+    fn output_type(&self, out_id: &AnyOutId) -> OutputType {
+        self.resolve_out(
+            *out_id,
+            |ls, lid| {
+                ls.txs[&lid.txid()]
+                    .output_at(lid.vout() as usize)
+                    .expect("txout should be present if index is built correctly")
+                    .output_type()
+            },
+            |ds, did| ds.output_type(did),
+        )
+    }
 }
 
-impl IndexedGraph for UnifiedStorage {}
+impl IndexedGraph for UnifiedStorage {
+    fn network(&self) -> bitcoin::Network {
+        self.network
+    }
+}
 
 #[cfg(test)]
 mod tests {